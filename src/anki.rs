@@ -0,0 +1,206 @@
+//! Anki deck export: history entries as flashcards with the image on the
+//! front and the LaTeX on the back, packaged as an importable `.apkg` (an
+//! SQLite collection plus the media files, zipped).
+
+use rusqlite::{Connection, params};
+use serde_json::json;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::document;
+use crate::history::Entry;
+use crate::html::escape;
+
+/// Collection schema of the legacy `collection.anki2`, which every Anki
+/// version still imports
+const SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+    ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+    conf text not null, models text not null, decks text not null, dconf text not null, tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null, mod integer not null,
+    usn integer not null, tags text not null, flds text not null, sfld integer not null,
+    csum integer not null, flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null, ord integer not null,
+    mod integer not null, usn integer not null, type integer not null, queue integer not null,
+    due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null, odid integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+    ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null,
+    type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+/// Fixed so re-exported decks update the same note type instead of adding one
+const MODEL_ID: i64 = 1_718_000_000_001;
+
+const CSS: &str = ".card { font-family: sans-serif; font-size: 20px; text-align: center; }\nimg { max-width: 100%; }";
+
+/// Writes `entries` to `path` as a deck named `deck`; entries whose image is
+/// gone and that have no note to put on the front instead are skipped.
+/// Returns how many cards were written
+pub fn export(entries: &[Entry], deck: &str, path: &Path) -> Result<usize, Box<dyn Error>> {
+    let now = chrono::Local::now();
+    let seconds = now.timestamp();
+    let millis = now.timestamp_millis();
+    let deck_id = millis;
+
+    let collection_path = std::env::temp_dir().join(format!("latex_ocr-{}.anki2", std::process::id()));
+    let _ = fs::remove_file(&collection_path);
+    let collection = Connection::open(&collection_path)?;
+    collection.execute_batch(SCHEMA)?;
+    collection.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            seconds,
+            millis,
+            conf(deck_id).to_string(),
+            models(deck_id, seconds).to_string(),
+            decks(deck_id, deck, seconds).to_string(),
+            dconf().to_string(),
+        ],
+    )?;
+
+    let mut media = Vec::new();
+    for entry in entries {
+        let id = millis + media.len() as i64 + 1;
+        let front = match fs::read(&entry.image) {
+            Ok(data) => {
+                let extension = Path::new(&entry.image).extension().unwrap_or_default().to_string_lossy();
+                let name = format!("latex_ocr-{}.{}", entry.id(), extension);
+                let front = format!("<img src=\"{}\">", escape(&name));
+                media.push((name, data));
+                front
+            }
+            Err(_) => match &entry.note {
+                Some(note) => escape(note),
+                None => continue,
+            },
+        };
+        let latex = entry.latex.trim();
+        let mut back = if document::is_bare_math(latex) {
+            escape(&format!("\\[{}\\]", strip_display(latex)))
+        } else {
+            escape(latex)
+        };
+        if let Some(note) = &entry.note {
+            back.push_str(&format!("<br><small>{}</small>", escape(note)));
+        }
+        collection.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ' latex_ocr ', ?5, ?6, ?7, 0, '')",
+            params![id, entry.id(), MODEL_ID, seconds, format!("{}\x1f{}", front, back), front, checksum(&front)],
+        )?;
+        collection.execute(
+            "INSERT INTO cards VALUES (?1, ?1, ?2, 0, ?3, -1, 0, 0, ?4, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+            params![id, deck_id, seconds, id - millis],
+        )?;
+    }
+    let cards: usize = collection.query_row("SELECT count(*) FROM notes", [], |row| row.get(0))?;
+    collection.close().map_err(|(_, e)| e)?;
+
+    let written = package(path, &collection_path, &media);
+    let _ = fs::remove_file(&collection_path);
+    written?;
+    Ok(cards)
+}
+
+/// Zips the collection and media into the `.apkg` layout: media files are
+/// stored as `0`, `1`, ... with a `media` JSON mapping them to their names
+fn package(path: &Path, collection: &Path, media: &[(String, Vec<u8>)]) -> Result<(), Box<dyn Error>> {
+    let options = zip::write::SimpleFileOptions::default();
+    let mut archive = zip::ZipWriter::new(fs::File::create(path)?);
+    archive.start_file("collection.anki2", options)?;
+    archive.write_all(&fs::read(collection)?)?;
+    let mut names = serde_json::Map::new();
+    for (index, (name, data)) in media.iter().enumerate() {
+        archive.start_file(index.to_string(), options)?;
+        archive.write_all(data)?;
+        names.insert(index.to_string(), json!(name));
+    }
+    archive.start_file("media", options)?;
+    archive.write_all(serde_json::Value::Object(names).to_string().as_bytes())?;
+    archive.finish()?;
+    Ok(())
+}
+
+fn conf(deck_id: i64) -> serde_json::Value {
+    json!({
+        "activeDecks": [deck_id], "curDeck": deck_id, "newSpread": 0, "collapseTime": 1200,
+        "timeLim": 0, "estTimes": true, "dueCounts": true, "curModel": MODEL_ID.to_string(),
+        "nextPos": 1, "sortType": "noteFld", "sortBackwards": false, "addToCur": true
+    })
+}
+
+fn models(deck_id: i64, modified: i64) -> serde_json::Value {
+    let field = |name: &str, ord: u32| {
+        json!({ "name": name, "ord": ord, "sticky": false, "rtl": false, "font": "Arial", "size": 20, "media": [] })
+    };
+    json!({ MODEL_ID.to_string(): {
+        "id": MODEL_ID, "name": "latex_ocr", "type": 0, "mod": modified, "usn": -1, "sortf": 0,
+        "did": deck_id, "tags": [], "vers": [], "css": CSS,
+        "flds": [field("Image", 0), field("LaTeX", 1)],
+        "tmpls": [{
+            "name": "Card 1", "ord": 0, "did": null, "bqfmt": "", "bafmt": "",
+            "qfmt": "{{Image}}", "afmt": "{{FrontSide}}<hr id=answer>{{LaTeX}}"
+        }],
+        "latexPre": "\\documentclass[12pt]{article}\n\\usepackage{amsmath,amssymb}\n\\pagestyle{empty}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "req": [[0, "all", [0]]]
+    }})
+}
+
+fn decks(deck_id: i64, name: &str, modified: i64) -> serde_json::Value {
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id, "name": name, "mod": modified, "usn": -1, "desc": "", "dyn": 0, "conf": 1,
+            "collapsed": false, "extendNew": 10, "extendRev": 50,
+            "newToday": [0, 0], "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0]
+        })
+    };
+    json!({ "1": deck(1, "Default"), deck_id.to_string(): deck(deck_id, name) })
+}
+
+fn dconf() -> serde_json::Value {
+    json!({ "1": {
+        "id": 1, "name": "Default", "mod": 0, "usn": 0, "maxTaken": 60, "autoplay": true,
+        "timer": 0, "replayq": true,
+        "new": { "bury": true, "delays": [1, 10], "initialFactor": 2500, "ints": [1, 4, 7], "order": 1, "perDay": 20, "separate": true },
+        "rev": { "bury": true, "ease4": 1.3, "fuzz": 0.05, "ivlFct": 1, "maxIvl": 36500, "minSpace": 1, "perDay": 100 },
+        "lapse": { "delays": [10], "leechAction": 0, "leechFails": 8, "minInt": 1, "mult": 0 }
+    }})
+}
+
+/// Anki's duplicate check: the first 8 hex digits of the SHA-1 of the sort field
+fn checksum(field: &str) -> i64 {
+    use sha1::{Digest, Sha1};
+
+    let digest = Sha1::digest(field.as_bytes());
+    i64::from(u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]))
+}
+
+/// The body of `\[...\]` or `$$...$$`, or `latex` if it has neither
+fn strip_display(latex: &str) -> &str {
+    for (open, close) in [("\\[", "\\]"), ("$$", "$$")] {
+        if let Some(inner) = latex.strip_prefix(open).and_then(|rest| rest.strip_suffix(close)) {
+            return inner.trim();
+        }
+    }
+    latex
+}
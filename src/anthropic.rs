@@ -0,0 +1,182 @@
+//! Anthropic Messages API backend.
+
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::error::Error;
+
+use crate::provider::{self, BoxFuture, Chunk, Failure, Limits, OcrProvider, Reply, Request, Usage};
+use crate::response;
+
+/// Smallest extended thinking budget the API accepts
+pub const MIN_THINKING_BUDGET: u32 = 1024;
+
+/// Status Anthropic answers with when it is over capacity
+const OVERLOADED: u16 = 529;
+
+/// `OcrProvider` for Claude models
+pub struct Anthropic {
+    pub api_key: String,
+    /// Messages API endpoint
+    pub url: String,
+}
+
+/// Sets `max_tokens` and, when thinking, the `thinking` block; thinking
+/// tokens count towards `max_tokens` so the budget is added on top
+fn apply(limits: Limits, payload: &mut Value) {
+    match limits.thinking_budget {
+        Some(budget) => {
+            payload["max_tokens"] = json!(limits.max_tokens + budget);
+            payload["thinking"] = json!({ "type": "enabled", "budget_tokens": budget });
+        }
+        None => payload["max_tokens"] = json!(limits.max_tokens),
+    }
+}
+
+/// One event of a streamed Messages API reply
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart { message: MessageStart },
+    ContentBlockDelta { delta: Delta },
+    MessageDelta {
+        #[serde(default)]
+        usage: Usage,
+    },
+    MessageStop,
+    Error { error: ApiError },
+    /// Pings, block starts and stops, and any event types added later
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageStart {
+    #[serde(default)]
+    usage: Usage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Delta {
+    TextDelta { text: String },
+    /// Thinking and any delta types added later
+    #[serde(other)]
+    Other,
+}
+
+/// The body of a failed request
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ApiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+impl OcrProvider for Anthropic {
+    fn name(&self) -> &'static str {
+        "Anthropic"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn stream<'a>(&'a self, client: &'a reqwest::Client, request: &'a Request<'a>)
+    -> BoxFuture<'a, Result<Reply, Failure>> {
+        Box::pin(self.send(client, request))
+    }
+}
+
+impl Anthropic {
+    /// The Messages API payload for `request`, the image before the prompt
+    fn payload(request: &Request) -> Value {
+        let content = match request.image {
+            Some(image) => json!([
+                {
+                    "type": "image",
+                    "source": {
+                        "type": "base64",
+                        "media_type": image.media_type,
+                        "data": BASE64.encode(image.data)
+                    }
+                },
+                {
+                    "type": "text",
+                    "text": request.prompt
+                }
+            ]),
+            None => json!(request.prompt),
+        };
+        let mut payload = json!({
+            "model": request.model,
+            "stream": true,
+            "messages": [
+                {
+                    "role": "user",
+                    "content": content
+                }
+            ]
+        });
+        apply(request.limits, &mut payload);
+        if let Some(tag) = provider::client_tag() {
+            payload["metadata"] = json!({ "user_id": tag });
+        }
+        payload
+    }
+
+    async fn send(&self, client: &reqwest::Client, request: &Request<'_>) -> Result<Reply, Failure> {
+        // Send the request to Anthropic API
+        let response = provider::post(client, &self.url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json")
+            .json(&Self::payload(request))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Reply::new(response, decode));
+        }
+        let retry_after = provider::retry_after(response.headers());
+        let body = response.text().await?;
+        let failure = serde_json::from_str::<ErrorResponse>(&body);
+        // Rate limits (429) pass too, as does the API being briefly unavailable
+        let overloaded = matches!(status.as_u16(), OVERLOADED | 429 | 503)
+            || failure
+                .as_ref()
+                .is_ok_and(|failure| matches!(failure.error.kind.as_str(), "overloaded_error" | "rate_limit_error"));
+        if overloaded {
+            return Err(Failure::Overloaded { retry_after });
+        }
+        Err(match failure {
+            Ok(failure) => format!(
+                "API request failed with status: {} ({}: {})",
+                status, failure.error.kind, failure.error.message
+            ),
+            Err(_) => format!("API request failed with status: {}", status),
+        }
+        .into())
+    }
+}
+
+/// Text deltas pass through, thinking is skipped
+fn decode(data: &str) -> Result<Vec<Chunk>, Box<dyn Error>> {
+    let chunk = match response::parse(data)? {
+        StreamEvent::MessageStart { message } => Some(Chunk::Usage(message.usage)),
+        StreamEvent::ContentBlockDelta { delta: Delta::TextDelta { text } } => Some(Chunk::Text(text)),
+        StreamEvent::ContentBlockDelta { delta: Delta::Other } => None,
+        StreamEvent::MessageDelta { usage } => Some(Chunk::Usage(usage)),
+        StreamEvent::MessageStop => Some(Chunk::Done),
+        // Errors after the stream started, e.g. when the API got overloaded mid-reply
+        StreamEvent::Error { error } => return Err(format!("{}: {}", error.kind, error.message).into()),
+        StreamEvent::Other => None,
+    };
+    Ok(chunk.into_iter().collect())
+}
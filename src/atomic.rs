@@ -0,0 +1,85 @@
+//! Crash-safe writes for config, history and state: the contents go to a temp
+//! file next to the target, are fsynced and renamed over it, so a reader (or
+//! the next run after a crash) sees either the old file or the new one.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Replaces `path` with `contents`. A symlink's target is replaced rather
+/// than the link, and an existing file keeps its permissions (config.toml
+/// holds the API key)
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let resolved = fs::canonicalize(path).ok();
+    let path = resolved.as_deref().unwrap_or(path);
+    let permissions = fs::metadata(path).ok().map(|metadata| metadata.permissions());
+    let directory = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let temp = directory.join(format!(".{}.tmp-{}", name.to_string_lossy(), std::process::id()));
+
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        // Before anything is written, so the contents are never more readable than before
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.write_all(contents.as_ref())?;
+        file.sync_all()?;
+        fs::rename(&temp, path)?;
+        sync_directory(directory)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Adds `text` to the end of `path` (creating it) by rewriting it atomically
+pub fn append(path: &Path, text: &str) -> io::Result<()> {
+    let mut contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    contents.extend_from_slice(text.as_bytes());
+    write(path, contents)
+}
+
+/// Makes the rename itself durable
+#[cfg(unix)]
+fn sync_directory(directory: &Path) -> io::Result<()> {
+    File::open(directory)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_directory: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn rewrites_keep_permissions_and_symlinks() {
+        let directory = std::env::temp_dir().join(format!("latex_ocr-atomic-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let target = directory.join("config.toml");
+        let link = directory.join("link.toml");
+        fs::write(&target, "old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write(&link, "new").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o600);
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
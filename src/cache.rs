@@ -0,0 +1,52 @@
+//! Earlier results by what produced them, so converting the same screenshot
+//! twice doesn't pay for a second request. Each is a file in
+//! `~/.cache/latex_ocr/results` named after a hash of the image bytes, the
+//! model, the prompt and the settings that change the result, see
+//! `Converter::cache_settings`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::atomic;
+use crate::paths;
+use crate::pipeline::{self, Conversion};
+use crate::provider::Usage;
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Sends every conversion of this process, for `--no-cache`
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+fn path(image_data: &[u8], model: &str, prompt: &str, settings: &str) -> PathBuf {
+    let key = format!("{}\n{}\n{}\n{}", pipeline::sha256_hex(image_data), model, prompt, settings);
+    paths::cache_dir().join("results").join(format!("{}.json", pipeline::sha256_hex(key.as_bytes())))
+}
+
+/// The result of converting the same image with the same model, prompt and
+/// settings before, as if it came from `image_path`; it used no tokens this time
+pub fn lookup(image_data: &[u8], model: &str, prompt: &str, settings: &str, image_path: &str) -> Option<Conversion> {
+    if DISABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let text = fs::read_to_string(path(image_data, model, prompt, settings)).ok()?;
+    let cached: Conversion = serde_json::from_str(&text).ok()?;
+    Some(Conversion { image: image_path.to_string(), usage: Usage::default(), ..cached })
+}
+
+/// Keeps `conversion` for `lookup`; a cache that can't be written only costs a request later
+pub fn store(image_data: &[u8], model: &str, prompt: &str, settings: &str, conversion: &Conversion) {
+    if DISABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let path = path(image_data, model, prompt, settings);
+    let written = fs::create_dir_all(path.parent().expect("results live in a directory"))
+        .map_err(|e| e.to_string())
+        .and_then(|()| serde_json::to_string(conversion).map_err(|e| e.to_string()))
+        .and_then(|json| atomic::write(&path, json).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        eprintln!("Failed to cache the result: {}", e);
+    }
+}
@@ -0,0 +1,177 @@
+//! Cancelling an in-flight conversion, either with Ctrl-C or from a second
+//! invocation running `latex_ocr cancel`.
+//!
+//! The process that is waiting on the API records its pid in the state
+//! directory; `cancel` sends that pid SIGUSR1, which trips the
+//! conversion's cancellation token. Nothing has been written to the clipboard
+//! at that point, so the previous clipboard contents stay as they were.
+//!
+//! Once the signals are handled here they no longer end the process on
+//! their own, so a Ctrl-C with nothing in flight (or a second one while a
+//! conversion is already cancelling) exits as the default handler would.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, Once};
+use tokio_util::sync::CancellationToken;
+
+use crate::atomic;
+use crate::paths;
+
+const PID_FILE: &str = "inflight.pid";
+
+/// The exit status of a process ended by SIGINT
+const INTERRUPTED: i32 = 130;
+
+/// The tokens of the conversions in flight in this process, by id
+static IN_FLIGHT: Mutex<Vec<(u64, CancellationToken)>> = Mutex::new(Vec::new());
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+static LISTENING: Once = Once::new();
+
+fn pid_path() -> PathBuf {
+    paths::state_dir().join(PID_FILE)
+}
+
+/// Marks this process as having a conversion in flight; the marker is
+/// removed when the last one running at once is dropped
+pub struct InFlight {
+    id: u64,
+    token: CancellationToken,
+    path: PathBuf,
+}
+
+impl InFlight {
+    /// Must be called inside the runtime. The signals are handled before the
+    /// pid is written, so a `cancel` right after can't kill the process
+    pub fn begin() -> Self {
+        listen();
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let token = CancellationToken::new();
+        IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner()).push((id, token.clone()));
+        let path = pid_path();
+        let _ = atomic::write(&path, std::process::id().to_string());
+        Self { id, token, path }
+    }
+
+    /// Resolves on Ctrl-C or when another invocation runs `cancel`
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut in_flight = IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner());
+        in_flight.retain(|(id, _)| *id != self.id);
+        // Only clean up our own marker, a queued instance may have taken over
+        if in_flight.is_empty() && read_pid(&self.path) == Some(std::process::id()) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Starts handling Ctrl-C and SIGUSR1 for the rest of the process, once.
+/// The streams are registered before this returns; only waiting on them is
+/// left to a task
+fn listen() {
+    LISTENING.call_once(|| {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+
+            let (Ok(mut user1), Ok(mut interrupt)) =
+                (signal(SignalKind::user_defined1()), signal(SignalKind::interrupt()))
+            else {
+                return;
+            };
+            tokio::spawn(async move {
+                loop {
+                    let interrupted = tokio::select! {
+                        _ = interrupt.recv() => true,
+                        _ = user1.recv() => false,
+                    };
+                    trip(interrupted);
+                }
+            });
+        }
+        #[cfg(not(unix))]
+        tokio::spawn(async {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                trip(true);
+            }
+        });
+    });
+}
+
+/// Cancels every conversion in flight; a Ctrl-C with none left to cancel
+/// ends the process
+fn trip(interrupted: bool) {
+    let in_flight = IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner());
+    let pending: Vec<_> = in_flight.iter().filter(|(_, token)| !token.is_cancelled()).collect();
+    if pending.is_empty() {
+        if interrupted {
+            std::process::exit(INTERRUPTED);
+        }
+        return;
+    }
+    for (_, token) in pending {
+        token.cancel();
+    }
+}
+
+/// Signals the conversion in flight, if any. Returns the pid that was signalled.
+pub fn request() -> Result<Option<u32>, Box<dyn Error>> {
+    let path = pid_path();
+    let Some(pid) = read_pid(&path) else {
+        return Ok(None);
+    };
+    // The pid outlives a process that didn't clean up, and may be another program's by now
+    if is_latex_ocr(pid)? && signal(pid)? {
+        Ok(Some(pid))
+    } else {
+        let _ = fs::remove_file(&path);
+        Ok(None)
+    }
+}
+
+/// Whether `pid` is a running latex_ocr
+#[cfg(unix)]
+fn is_latex_ocr(pid: u32) -> Result<bool, Box<dyn Error>> {
+    let output = std::process::Command::new("ps")
+        .args(["-p", &pid.to_string(), "-o", "comm="])
+        .output()
+        .map_err(|e| format!("couldn't run ps: {}", e))?;
+    if !output.status.success() {
+        return Ok(false);
+    }
+    let exe = std::env::current_exe()?;
+    let ours = exe.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let command = String::from_utf8_lossy(&output.stdout);
+    // macOS prints the whole path, Linux the name cut to 15 bytes
+    let theirs = Path::new(command.trim()).file_name().map(|name| name.to_string_lossy().to_string());
+    Ok(theirs.is_some_and(|theirs| theirs == ours || (theirs.len() == 15 && ours.starts_with(&theirs))))
+}
+
+/// `signal` refuses anyway
+#[cfg(not(unix))]
+fn is_latex_ocr(_pid: u32) -> Result<bool, Box<dyn Error>> {
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn signal(pid: u32) -> Result<bool, Box<dyn Error>> {
+    Ok(unsafe { libc::kill(pid as libc::pid_t, libc::SIGUSR1) } == 0)
+}
+
+#[cfg(not(unix))]
+fn signal(_pid: u32) -> Result<bool, Box<dyn Error>> {
+    Err("cancelling from another invocation is only supported on Unix".into())
+}
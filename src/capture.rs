@@ -0,0 +1,101 @@
+//! Interactive screen region capture for `latex_ocr capture`.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::shell;
+
+/// Region selection tools tried in order when `capture_command` is empty, by
+/// the program that has to be installed
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_CAPTURE_COMMANDS: &[(&str, &str)] = &[
+    ("maim", "maim --select --hidecursor {output}"),
+    ("slurp", "grim -g \"$(slurp)\" {output}"),
+    ("gnome-screenshot", "gnome-screenshot --area --file={output}"),
+    ("spectacle", "spectacle --region --background --nonotify --output {output}"),
+];
+
+/// Captured region images in selection order; the temp files are removed on drop
+#[derive(Default)]
+pub struct Regions {
+    paths: Vec<PathBuf>,
+}
+
+impl Regions {
+    /// Lets the user select one more region with `command` (`capture_command`,
+    /// empty for the platform's tool); returns false once they cancel the selection
+    pub fn select_next(&mut self, command: &str) -> Result<bool, Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "latex_ocr-{}-{}.png",
+            std::process::id(),
+            self.paths.len()
+        ));
+        let captured = if command.trim().is_empty() {
+            capture_region(&path)?
+        } else {
+            run_capture_command(command, &path)?
+        };
+        if captured {
+            self.paths.push(path);
+        }
+        Ok(captured)
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+impl Drop for Regions {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Runs the system's interactive selection into `dest`; false if the user pressed Escape
+#[cfg(target_os = "macos")]
+fn capture_region(dest: &Path) -> Result<bool, Box<dyn Error>> {
+    // -i: interactive selection, -x: no shutter sound
+    Command::new("screencapture")
+        .arg("-i")
+        .arg("-x")
+        .arg(dest)
+        .status()?;
+    // screencapture exits cleanly on Escape too, it just doesn't write the file
+    Ok(dest.exists())
+}
+
+/// The first region selection tool that is installed
+#[cfg(not(target_os = "macos"))]
+fn capture_region(dest: &Path) -> Result<bool, Box<dyn Error>> {
+    let installed = |program: &str| {
+        Command::new("sh")
+            .arg("-c")
+            .arg(format!("command -v {}", program))
+            .output()
+            .is_ok_and(|output| output.status.success())
+    };
+    let Some((_, command)) = DEFAULT_CAPTURE_COMMANDS.iter().find(|(program, _)| installed(program)) else {
+        return Err(
+            "no region capture tool found, install maim, grim and slurp, gnome-screenshot or spectacle, \
+             or set capture_command"
+                .into(),
+        );
+    };
+    run_capture_command(command, dest)
+}
+
+/// Runs the shell command `template` with `{output}` replaced by `dest`
+fn run_capture_command(template: &str, dest: &Path) -> Result<bool, Box<dyn Error>> {
+    let (mut capture, command) = shell::run_template(template, "output", dest);
+    let status = capture.status()?;
+    // Not found, as opposed to a cancelled selection, which most tools exit 1 on
+    if status.code() == Some(127) {
+        return Err(format!("`{}` exited with {}", command, status).into());
+    }
+    Ok(fs::metadata(dest).is_ok_and(|metadata| metadata.len() > 0))
+}
@@ -0,0 +1,474 @@
+//! Command-line arguments. Anything given here takes precedence over config.toml.
+
+use clap::{CommandFactory, Parser, Subcommand};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::events;
+use crate::format::Format;
+use crate::target::Target;
+
+/// What the invocation should do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Convert the most recent screenshot (the default), or the image at
+    /// `path`, read from stdin when it is `-`
+    Convert { path: Option<PathBuf> },
+    /// Convert several images at once: `paths`, and the screenshots taken
+    /// within `since`; `separate` writes each result next to its image
+    /// instead of delivering them together
+    ConvertMany {
+        paths: Vec<PathBuf>,
+        since: Option<Duration>,
+        separate: bool,
+    },
+    /// Select screen regions interactively and convert them; `multi` keeps
+    /// selecting until a selection is cancelled
+    Capture { multi: bool },
+    /// Capture one region and read it like Live Text, math as LaTeX
+    Snip,
+    /// Convert the image on the clipboard and paste the LaTeX into the frontmost app
+    Paste,
+    /// Stay resident and run `hotkey_command` whenever the global hotkey is pressed
+    Hotkey,
+    /// Stay resident and offer to convert every new screenshot; `daemon`
+    /// detaches from the terminal first
+    Watch { daemon: bool },
+    /// Record spoken math and convert the transcript
+    Dictate,
+    /// Convert every image under the given paths in order; `document` assembles
+    /// them into one .tex file instead, `compile` checks that it builds.
+    /// `report` also writes an HTML page of every image next to its result.
+    /// `only_new` skips images converted since they last changed, and
+    /// `resume` continues the last batch that didn't complete instead
+    Batch {
+        inputs: Vec<String>,
+        document: Option<String>,
+        compile: bool,
+        report: Option<String>,
+        only_new: bool,
+        resume: bool,
+    },
+    /// Convert the distinct frames of a screen recording, or a folder of its
+    /// frames, as a batch; `every` overrides `recording_frame_seconds`
+    Recording {
+        path: String,
+        every: Option<u32>,
+        document: Option<String>,
+        compile: bool,
+    },
+    /// Convert the distinct frames of an animated GIF or APNG of a derivation
+    /// into the numbered rows of one `align`, in order
+    Derivation { path: String },
+    /// The latest `limit` results, newest first and numbered for `history show`
+    HistoryList { limit: usize },
+    /// Everything recorded about one result, by number or id
+    HistoryShow { number: String },
+    /// Copy an earlier result again, by number or id
+    HistoryCopy { number: String },
+    /// Search earlier results by LaTeX substring or normalized structure
+    HistoryFind { query: String },
+    /// Attach a free-text note to a history entry, searchable with `history find`
+    HistoryAnnotate { id: String, note: String },
+    /// Write history entries, those matching `query` if given, as an Anki deck
+    HistoryExport { anki: String, query: Option<String> },
+    /// Write the corrected history entries, those matching `query` if given,
+    /// as a JSONL fine-tuning dataset
+    HistoryDataset { path: String, query: Option<String> },
+    /// Begin grouping conversions into a lecture document
+    SessionStart { name: Option<String> },
+    /// Assemble the running session into a .tex file
+    SessionStop { output: Option<String> },
+    /// Print where the config file is; `edit` opens it in an editor instead
+    Config { edit: bool },
+    /// Install the latest release; `check_only` just reports whether there is one
+    SelfUpdate { check_only: bool },
+    /// Abort the conversion another invocation has in flight
+    Cancel,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub command: Command,
+    /// Print a per-phase startup timing report
+    pub timing: bool,
+    /// Attach raw provider responses to errors
+    pub debug: bool,
+    /// Log lifecycle events to stderr in this format
+    pub log_format: Option<events::Format>,
+    /// Named profile from the config (or a built-in one like `whiteboard`)
+    pub profile: Option<String>,
+    /// What is being transcribed (`table`, `tikz`, or one from the config)
+    pub mode: Option<String>,
+    /// Extra text sent with the image to disambiguate symbols
+    pub context: Option<String>,
+    /// How the clipboard copy is written, regardless of the app in front
+    pub target: Option<Target>,
+    /// Markup results are written in, over the profile's and the config's `format`
+    pub output_format: Option<Format>,
+    /// Keep the page layout of excerpts, over the profile's and the config's `preserve_layout`
+    pub preserve_layout: bool,
+    /// Ask for context text before converting
+    pub ask_context: bool,
+    /// Send the previous conversion's result as context
+    pub previous: bool,
+    /// Convert the image on the clipboard instead of the newest screenshot
+    pub from_clipboard: bool,
+    /// Model to convert with, over the profile's and the config's
+    pub model: Option<String>,
+    /// Prompt sent with the image, over the profile's and the config's
+    pub prompt: Option<String>,
+    /// Directory to look for screenshots in, over `image_directory`
+    pub directory: Option<String>,
+    /// Convert without asking first and deliver without showing the result,
+    /// whatever `confirm` and `confirm_result` say
+    pub no_confirm: bool,
+    /// Send the request even if the same image was converted before
+    pub no_cache: bool,
+}
+
+impl Command {
+    /// Short name for event logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Convert { .. } | Command::ConvertMany { .. } => "convert",
+            Command::Capture { .. } => "capture",
+            Command::Snip => "snip",
+            Command::Paste => "paste",
+            Command::Hotkey => "hotkey",
+            Command::Watch { .. } => "watch",
+            Command::Dictate => "dictate",
+            Command::Batch { .. } => "batch",
+            Command::Recording { .. } => "recording",
+            Command::Derivation { .. } => "derivation",
+            Command::HistoryList { .. } => "history list",
+            Command::HistoryShow { .. } => "history show",
+            Command::HistoryCopy { .. } => "history copy",
+            Command::HistoryFind { .. } => "history find",
+            Command::HistoryAnnotate { .. } => "history annotate",
+            Command::HistoryExport { .. } | Command::HistoryDataset { .. } => "history export",
+            Command::SessionStart { .. } => "session start",
+            Command::SessionStop { .. } => "session stop",
+            Command::Config { .. } => "config",
+            Command::SelfUpdate { .. } => "self-update",
+            Command::Cancel => "cancel",
+        }
+    }
+}
+
+/// Converts screenshots of math to LaTeX
+#[derive(Debug, Parser)]
+#[command(name = "latex_ocr", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Sub>,
+    /// Print a per-phase startup timing report
+    #[arg(long, global = true)]
+    timing: bool,
+    /// Attach raw provider responses to errors
+    #[arg(long, global = true)]
+    debug: bool,
+    /// Log lifecycle events to stderr
+    #[arg(long, global = true, value_name = "text|json", value_parser = parse_log_format)]
+    log_format: Option<events::Format>,
+    /// Profile from the config, or a built-in one like `whiteboard`
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    /// What is being transcribed: `table`, `tikz` or a mode from the config
+    #[arg(long, global = true, value_name = "NAME")]
+    mode: Option<String>,
+    /// Text sent with the image to disambiguate symbols
+    #[arg(long, global = true, value_name = "TEXT")]
+    context: Option<String>,
+    /// Write the clipboard copy for this kind of app
+    #[arg(long, global = true, value_name = "latex|katex|mathml", value_parser = parse_target)]
+    target: Option<Target>,
+    /// Write results in this format instead of the configured one
+    #[arg(long, global = true, value_name = "FORMAT", value_parser = parse_format)]
+    output_format: Option<Format>,
+    /// Keep columns, figure placement and footnotes of page excerpts
+    #[arg(long, global = true)]
+    preserve_layout: bool,
+    /// Ask for context text before converting
+    #[arg(long, global = true)]
+    ask_context: bool,
+    /// Send the previous result as context
+    #[arg(long, global = true)]
+    previous: bool,
+    /// Convert the image on the clipboard, if there is one
+    #[arg(long, global = true)]
+    from_clipboard: bool,
+    /// Model to convert with instead of the configured one
+    #[arg(long, global = true)]
+    model: Option<String>,
+    /// Prompt sent with the image instead of the configured one
+    #[arg(long, global = true, value_name = "TEXT")]
+    prompt: Option<String>,
+    /// Directory to look for screenshots in instead of `image_directory`
+    #[arg(long, global = true, value_name = "DIR")]
+    directory: Option<String>,
+    /// Convert and deliver without asking for confirmation
+    #[arg(long, global = true)]
+    no_confirm: bool,
+    /// Convert again even if the same image was converted before
+    #[arg(long, global = true)]
+    no_cache: bool,
+    /// Same as `dictate`, kept for scripts written before the subcommand
+    #[arg(long, hide = true)]
+    dictate: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Sub {
+    /// Convert the newest screenshot, or the images at PATH (`-` reads one from stdin; the default)
+    Convert {
+        /// Convert every screenshot taken within this long, e.g. 10m
+        #[arg(long, value_name = "DURATION", value_parser = crate::settings::parse_duration)]
+        all_since: Option<Duration>,
+        /// Write each result to a file next to its image instead of delivering them together
+        #[arg(long)]
+        separate: bool,
+        #[arg(value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+    /// Select screen regions and convert them
+    Capture {
+        /// Keep selecting regions until a selection is cancelled
+        #[arg(long)]
+        multi: bool,
+    },
+    /// Capture a region and read it like Live Text, math as LaTeX
+    Snip,
+    /// Convert the clipboard image and paste the LaTeX into the frontmost app
+    Paste,
+    /// Stay running and capture or convert whenever the configured global hotkey is pressed
+    Hotkey,
+    /// Stay running and offer to convert every new screenshot
+    Watch {
+        /// Keep watching in the background after the terminal closes
+        #[arg(long)]
+        daemon: bool,
+    },
+    /// Record spoken math and convert the transcript
+    Dictate,
+    /// Convert every image under the given files and directories in order
+    Batch {
+        /// Continue the last batch that didn't complete, as it was started
+        #[arg(long, conflicts_with_all = ["inputs", "document", "compile", "report", "only_new"])]
+        resume: bool,
+        /// Skip images converted since they last changed
+        #[arg(long)]
+        only_new: bool,
+        /// Assemble the results into this .tex file
+        #[arg(long, value_name = "FILE")]
+        document: Option<String>,
+        /// Check that the document compiles
+        #[arg(long, requires = "document")]
+        compile: bool,
+        /// Also write an HTML page of every image next to its result
+        #[arg(long, value_name = "FILE")]
+        report: Option<String>,
+        #[arg(value_name = "PATH", required_unless_present = "resume")]
+        inputs: Vec<String>,
+    },
+    /// Convert the distinct frames of a screen recording or a folder of frames
+    Recording {
+        /// Sample a frame every this many seconds, instead of `recording_frame_seconds`
+        #[arg(long, value_name = "SECONDS")]
+        every: Option<u32>,
+        /// Assemble the results into this .tex file
+        #[arg(long, value_name = "FILE")]
+        document: Option<String>,
+        /// Check that the document compiles
+        #[arg(long, requires = "document")]
+        compile: bool,
+        /// Video file, or a folder of frames exported from one
+        #[arg(value_name = "PATH")]
+        path: String,
+    },
+    /// Convert an animated GIF or APNG of a derivation into a numbered align, one step per distinct frame
+    Derivation {
+        #[arg(value_name = "PATH")]
+        path: String,
+    },
+    /// List, recall, search, annotate and export earlier results
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+    /// Group conversions into a lecture document
+    Session {
+        #[command(subcommand)]
+        action: SessionAction,
+    },
+    /// Show where the config file is, or edit it
+    Config {
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+    },
+    /// Install the latest release
+    SelfUpdate {
+        /// Only report whether there is a newer release
+        #[arg(long)]
+        check: bool,
+    },
+    /// Abort the conversion another invocation has in flight
+    Cancel,
+}
+
+#[derive(Debug, Subcommand)]
+enum HistoryAction {
+    /// Number the latest results, newest first
+    List {
+        /// How many to list
+        #[arg(long, short = 'n', default_value_t = 20)]
+        limit: usize,
+    },
+    /// Print everything recorded about result N from `history list` (or an id)
+    Show { number: String },
+    /// Copy result N from `history list` (or an id) again, without a new request
+    Copy { number: String },
+    /// Search by LaTeX substring, normalized structure or note
+    Find {
+        #[arg(required = true, allow_hyphen_values = true)]
+        query: Vec<String>,
+    },
+    /// Attach a note to an entry; an empty note removes it
+    Annotate {
+        id: String,
+        #[arg(allow_hyphen_values = true)]
+        note: Vec<String>,
+    },
+    /// Write every entry, or those matching QUERY, as an Anki deck, or the
+    /// corrected ones as a fine-tuning dataset
+    Export {
+        #[arg(long, value_name = "FILE", required_unless_present = "dataset", conflicts_with = "dataset")]
+        anki: Option<String>,
+        /// JSONL file for the entries corrected in review, with the model's output and the fix
+        #[arg(long, value_name = "FILE")]
+        dataset: Option<String>,
+        query: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum SessionAction {
+    /// Start grouping conversions, under NAME if given
+    Start { name: Option<String> },
+    /// Assemble the session into FILE
+    Stop { output: Option<String> },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigAction {
+    /// Print the path of the config file (the default)
+    Path,
+    /// Open the config file in $VISUAL, $EDITOR or the system's text editor
+    Edit,
+}
+
+fn parse_log_format(name: &str) -> Result<events::Format, String> {
+    events::Format::parse(name).ok_or_else(|| "expected text or json".to_string())
+}
+
+fn parse_format(name: &str) -> Result<Format, String> {
+    Format::parse(name)
+        .ok_or_else(|| "expected latex, org, org-export, rst, markdown, mathml, typst or asciimath".to_string())
+}
+
+fn parse_target(name: &str) -> Result<Target, String> {
+    Target::parse(name).ok_or_else(|| "expected latex, katex or mathml".to_string())
+}
+
+impl Args {
+    /// Parses `std::env::args`, exiting with a usage message on unknown input
+    pub fn parse() -> Self {
+        let cli = Cli::parse();
+        let command = match cli.command {
+            None if cli.dictate => Command::Dictate,
+            None => Command::Convert { path: None },
+            Some(Sub::Convert { all_since: None, separate: false, mut paths }) if paths.len() <= 1 => {
+                Command::Convert { path: paths.pop() }
+            }
+            Some(Sub::Convert { all_since, separate, paths }) => {
+                Command::ConvertMany { paths, since: all_since, separate }
+            }
+            Some(Sub::Capture { multi }) => Command::Capture { multi },
+            Some(Sub::Snip) => Command::Snip,
+            Some(Sub::Paste) => Command::Paste,
+            Some(Sub::Hotkey) => Command::Hotkey,
+            Some(Sub::Watch { daemon }) => Command::Watch { daemon },
+            Some(Sub::Dictate) => Command::Dictate,
+            Some(Sub::Batch { resume, only_new, document, compile, report, inputs }) => {
+                Command::Batch { inputs, document, compile, report, only_new, resume }
+            }
+            Some(Sub::Recording { every, document, compile, path }) => {
+                Command::Recording { path, every, document, compile }
+            }
+            Some(Sub::Derivation { path }) => Command::Derivation { path },
+            Some(Sub::History { action }) => match action {
+                HistoryAction::List { limit } => Command::HistoryList { limit },
+                HistoryAction::Show { number } => Command::HistoryShow { number },
+                HistoryAction::Copy { number } => Command::HistoryCopy { number },
+                HistoryAction::Find { query } => Command::HistoryFind { query: query.join(" ") },
+                HistoryAction::Annotate { id, note } => Command::HistoryAnnotate { id, note: note.join(" ") },
+                HistoryAction::Export { anki, dataset, query } => {
+                    let query = Some(query.join(" ")).filter(|query| !query.is_empty());
+                    match (anki, dataset) {
+                        (Some(anki), _) => Command::HistoryExport { anki, query },
+                        (None, Some(path)) => Command::HistoryDataset { path, query },
+                        (None, None) => unreachable!("clap requires --anki or --dataset"),
+                    }
+                }
+            },
+            Some(Sub::Session { action }) => match action {
+                SessionAction::Start { name } => Command::SessionStart { name },
+                SessionAction::Stop { output } => Command::SessionStop { output },
+            },
+            Some(Sub::Config { action }) => Command::Config { edit: matches!(action, Some(ConfigAction::Edit)) },
+            Some(Sub::SelfUpdate { check }) => Command::SelfUpdate { check_only: check },
+            Some(Sub::Cancel) => Command::Cancel,
+        };
+        Args {
+            command,
+            timing: cli.timing,
+            debug: cli.debug,
+            log_format: cli.log_format,
+            profile: cli.profile,
+            mode: cli.mode,
+            context: cli.context,
+            target: cli.target,
+            output_format: cli.output_format,
+            preserve_layout: cli.preserve_layout,
+            ask_context: cli.ask_context,
+            previous: cli.previous,
+            from_clipboard: cli.from_clipboard,
+            model: cli.model,
+            prompt: cli.prompt,
+            directory: cli.directory,
+            no_confirm: cli.no_confirm,
+            no_cache: cli.no_cache,
+        }
+    }
+}
+
+/// Where the subcommand is in `args` (without the program name): the first
+/// one that is neither a flag nor a flag's value, so `--profile hotkey` isn't it
+pub fn subcommand_index(args: &[OsString]) -> Option<usize> {
+    let cli = Cli::command();
+    let mut args = args.iter().enumerate();
+    while let Some((index, arg)) = args.next() {
+        let arg = arg.to_string_lossy();
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Some(index);
+        };
+        let takes_value = cli
+            .get_arguments()
+            .any(|known| known.get_long() == Some(flag) && known.get_action().takes_values());
+        if takes_value {
+            args.next();
+        }
+    }
+    None
+}
@@ -0,0 +1,214 @@
+//! Result output: the system clipboard with the `gui` feature, stdout otherwise.
+//! Also reads clipboard images for `latex_ocr paste`.
+
+use std::error::Error;
+use std::time::Duration;
+
+/// Where `copy` puts the text, for user-facing messages
+#[cfg(feature = "gui")]
+pub const DESTINATION: &str = "clipboard";
+#[cfg(not(feature = "gui"))]
+pub const DESTINATION: &str = "stdout";
+
+/// How results are written for clipboard managers to pick up
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Add a syntax-highlighted RTF flavor next to the plain text (macOS only)
+    pub rtf: bool,
+    /// Push every equation as its own clipboard event, then the full result
+    pub split_equations: bool,
+    /// Pause between events so managers that poll the pasteboard see each one
+    pub event_delay: Duration,
+}
+
+/// Copies `text` honoring the clipboard manager `options`; `alt` is a
+/// description of it for an HTML flavor rich editors keep as alt text (macOS only)
+pub fn copy_with(text: &str, alt: Option<&str>, options: &Options) -> Result<(), Box<dyn Error>> {
+    if options.split_equations {
+        let equations = split_equations(text);
+        if equations.len() > 1 {
+            for equation in &equations {
+                set_contents(equation, None, options.rtf)?;
+                std::thread::sleep(options.event_delay);
+            }
+        }
+    }
+    set_contents(text, alt, options.rtf)
+}
+
+#[cfg(all(feature = "gui", target_os = "macos"))]
+fn set_contents(text: &str, alt: Option<&str>, rtf: bool) -> Result<(), Box<dyn Error>> {
+    if !rtf && alt.is_none() {
+        return set_plain(text);
+    }
+    // The clipboard crate only knows plain text, AppleScript can set several flavors at once
+    let mut flavors = vec![format!("«class utf8»:«data utf8{}»", hex(text.as_bytes()))];
+    if rtf {
+        flavors.push(format!("«class RTF »:«data RTF {}»", hex(crate::rtf::highlight_latex(text).as_bytes())));
+    }
+    if let Some(alt) = alt {
+        let html = format!("<span role=\"img\" aria-label=\"{}\">{}</span>", crate::html::escape(alt), crate::html::escape(text));
+        flavors.push(format!("«class HTML»:«data HTML{}»", hex(html.as_bytes())));
+    }
+    let script = format!("set the clipboard to {{{}}}", flavors.join(", "));
+    let status = std::process::Command::new("osascript")
+        .args(["-e", &script])
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("osascript exited with {}", status).into())
+    }
+}
+
+#[cfg(all(feature = "gui", not(target_os = "macos")))]
+fn set_contents(text: &str, _alt: Option<&str>, _rtf: bool) -> Result<(), Box<dyn Error>> {
+    set_plain(text)
+}
+
+#[cfg(all(feature = "gui", not(all(unix, not(target_os = "macos")))))]
+fn set_plain(text: &str) -> Result<(), Box<dyn Error>> {
+    arboard::Clipboard::new()?.set_text(text)?;
+    Ok(())
+}
+
+/// On X11 and Wayland the copying process has to serve the clipboard to whoever
+/// pastes, so the copy is handed to wl-copy or xclip, which stay in the background
+/// for that; without them it is only held for `X11_HOLD`, unless a clipboard
+/// manager takes it over sooner
+#[cfg(all(feature = "gui", unix, not(target_os = "macos")))]
+fn set_plain(text: &str) -> Result<(), Box<dyn Error>> {
+    use arboard::SetExtLinux;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    const X11_HOLD: Duration = Duration::from_secs(10);
+
+    let helpers: [(&str, &[&str]); 2] = [("wl-copy", &[]), ("xclip", &["-selection", "clipboard", "-in"])];
+    for (program, args) in helpers {
+        let Ok(mut child) = Command::new(program).args(args).stdin(Stdio::piped()).spawn() else {
+            continue;
+        };
+        child.stdin.take().expect("stdin is piped").write_all(text.as_bytes())?;
+        if child.wait()?.success() {
+            return Ok(());
+        }
+    }
+    arboard::Clipboard::new()?
+        .set()
+        .wait_until(std::time::Instant::now() + X11_HOLD)
+        .text(text)?;
+    Ok(())
+}
+
+/// Print text to stdout in headless builds
+#[cfg(not(feature = "gui"))]
+fn set_contents(text: &str, _alt: Option<&str>, _rtf: bool) -> Result<(), Box<dyn Error>> {
+    println!("{}", text);
+    Ok(())
+}
+
+/// The image currently on the clipboard as PNG bytes, `None` if it holds something else
+#[cfg(all(feature = "gui", target_os = "macos"))]
+pub fn read_image() -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    // AppleScript can coerce any pasteboard image to PNG; the clipboard crate only reads text
+    let path = std::env::temp_dir().join(format!("latex_ocr-clipboard-{}.png", std::process::id()));
+    let script = format!(
+        "set png to (the clipboard as «class PNGf»)\n\
+         set f to open for access (POSIX file \"{}\") with write permission\n\
+         set eof f to 0\n\
+         write png to f\n\
+         close access f",
+        path.display()
+    );
+    let output = std::process::Command::new("osascript")
+        .args(["-e", &script])
+        .output()?;
+    let data = if output.status.success() { std::fs::read(&path).ok() } else { None };
+    let _ = std::fs::remove_file(&path);
+    Ok(data.filter(|data| !data.is_empty()))
+}
+
+#[cfg(all(feature = "gui", unix, not(target_os = "macos")))]
+pub fn read_image() -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    // Wayland first, then X11; wl-paste installed but run under X11 fails
+    let attempts: [(&str, &[&str]); 2] = [
+        ("wl-paste", &["--no-newline", "--type", "image/png"]),
+        ("xclip", &["-selection", "clipboard", "-target", "image/png", "-out"]),
+    ];
+    let mut ran = false;
+    for (program, args) in attempts {
+        let Ok(output) = std::process::Command::new(program).args(args).output() else {
+            continue;
+        };
+        ran = true;
+        if output.status.success() && !output.stdout.is_empty() {
+            return Ok(Some(output.stdout));
+        }
+    }
+    if ran { Ok(None) } else { Err("reading images from the clipboard needs wl-paste or xclip".into()) }
+}
+
+/// Windows: arboard hands over raw RGBA pixels, encoded as PNG for upload
+#[cfg(all(feature = "gui", not(unix)))]
+pub fn read_image() -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let image = match arboard::Clipboard::new()?.get_image() {
+        Ok(image) => image,
+        Err(arboard::Error::ContentNotAvailable) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    encode_png(image.width as u32, image.height as u32, image.bytes.into_owned())
+}
+
+#[cfg(all(feature = "gui", not(unix), feature = "preprocess"))]
+fn encode_png(width: u32, height: u32, rgba: Vec<u8>) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let image = image::RgbaImage::from_raw(width, height, rgba).ok_or("the clipboard image is truncated")?;
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut encoded, image::ImageFormat::Png)?;
+    Ok(Some(encoded.into_inner()))
+}
+
+#[cfg(all(feature = "gui", not(unix), not(feature = "preprocess")))]
+fn encode_png(_width: u32, _height: u32, _rgba: Vec<u8>) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    Err("reading images from the clipboard needs a build with the `preprocess` feature".into())
+}
+
+#[cfg(not(feature = "gui"))]
+pub fn read_image() -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    Err("reading images from the clipboard needs a build with the `gui` feature".into())
+}
+
+#[cfg(all(feature = "gui", target_os = "macos"))]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Splits a result into its separate equations: blank-line separated blocks,
+/// keeping environments and display math that contain blank lines together
+pub fn split_equations(text: &str) -> Vec<String> {
+    let mut equations = Vec::new();
+    let mut current = String::new();
+    let mut depth: i32 = 0;
+
+    for line in text.lines() {
+        if line.trim().is_empty() && depth <= 0 {
+            if !current.trim().is_empty() {
+                equations.push(current.trim().to_string());
+            }
+            current.clear();
+            continue;
+        }
+        depth += line.matches("\\begin{").count() as i32 + line.matches("\\[").count() as i32;
+        depth -= line.matches("\\end{").count() as i32 + line.matches("\\]").count() as i32;
+        // $$ toggles display math
+        if line.matches("$$").count() % 2 == 1 {
+            depth += if depth > 0 { -1 } else { 1 };
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.trim().is_empty() {
+        equations.push(current.trim().to_string());
+    }
+    equations
+}
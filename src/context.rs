@@ -0,0 +1,76 @@
+//! Context text sent along with the image so a sequence of related
+//! screenshots is read with consistent notation.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::atomic;
+use crate::cli::Args;
+use crate::dialog;
+use crate::metadata::Hint;
+use crate::paths;
+use crate::pipeline::Conversion;
+use crate::session;
+use crate::settings::AppConfig;
+
+/// Context gathered once per invocation; the previous result is re-read on
+/// every conversion so `watch` sees each new one
+pub struct Context {
+    given: Option<String>,
+    previous: bool,
+}
+
+impl Context {
+    /// Takes `--context`, asks for more with `--ask-context` and remembers
+    /// whether to include the previous result (`--previous` or `context_previous`)
+    pub fn gather(args: &Args, config: &AppConfig) -> Self {
+        let asked = if args.ask_context {
+            dialog::input("Context", "Anything that helps reading the image (optional):")
+        } else {
+            None
+        };
+        let given: Vec<String> = args.context.iter().cloned().chain(asked).collect();
+        Self {
+            given: Some(given.join("\n")).filter(|text| !text.is_empty()),
+            previous: args.previous || config.context_previous,
+        }
+    }
+
+    /// The text to send, if there is any, led by the screenshot's origin `hint`
+    pub fn text(&self, hint: Option<&Hint>) -> Option<String> {
+        let hint = hint.and_then(Hint::describe);
+        let previous = if self.previous { previous() } else { None };
+        let previous = previous.map(|latex| format!("The previous screenshot converted to:\n{}", latex));
+        let lecture = session::active().and_then(|session| session.context());
+        let parts: Vec<String> = hint
+            .into_iter()
+            .chain(self.given.iter().cloned())
+            .chain(lecture)
+            .chain(previous)
+            .collect();
+        Some(parts.join("\n\n")).filter(|text| !text.is_empty())
+    }
+}
+
+fn last_result_path() -> PathBuf {
+    paths::state_dir().join("last_result.tex")
+}
+
+/// Keeps the bare LaTeX of the latest delivery for the next `--previous` and
+/// adds it to the running session
+pub fn remember(conversions: &[Conversion]) {
+    let latex: Vec<&str> = conversions.iter().map(|conversion| conversion.latex.trim()).collect();
+    let latex = latex.join("\n\n");
+    if let Some(session) = session::active()
+        && let Err(e) = session.record(&latex)
+    {
+        eprintln!("Failed to add the result to the session: {}", e);
+    }
+    let _ = atomic::write(&last_result_path(), latex);
+}
+
+fn previous() -> Option<String> {
+    fs::read_to_string(last_result_path())
+        .ok()
+        .filter(|latex| !latex.trim().is_empty())
+}
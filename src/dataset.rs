@@ -0,0 +1,56 @@
+//! Fine-tuning and evaluation data from the history: every entry whose result
+//! was corrected by hand, as one JSON object per line holding the image, the
+//! prompt, what the model answered and the corrected LaTeX.
+
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde_json::json;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::atomic;
+use crate::history::Entry;
+use crate::provider;
+
+/// Writes the corrected `entries` to `path`, returning how many were written
+/// and how many were left out because their image is gone
+pub fn export(entries: &[Entry], path: &Path) -> Result<(usize, usize), Box<dyn Error>> {
+    let mut lines = String::new();
+    let mut written = 0;
+    let mut missing = 0;
+    for entry in entries {
+        let Some(original) = &entry.original else {
+            continue;
+        };
+        let Ok(image) = fs::read(&entry.image) else {
+            missing += 1;
+            continue;
+        };
+        let prompt = entry.prompt.as_deref().unwrap_or_default();
+        let url = format!("data:{};base64,{}", provider::media_type(&image, &entry.image), BASE64.encode(&image));
+        // `messages` is the chat format fine-tuning tools take; the rest is for evaluation
+        let line = json!({
+            "id": entry.id(),
+            "image": entry.image,
+            "model": entry.model,
+            "prompt": prompt,
+            "output": original,
+            "corrected": entry.latex,
+            "messages": [
+                {
+                    "role": "user",
+                    "content": [
+                        { "type": "text", "text": prompt },
+                        { "type": "image_url", "image_url": { "url": url } },
+                    ],
+                },
+                { "role": "assistant", "content": entry.latex },
+            ],
+        });
+        lines.push_str(&line.to_string());
+        lines.push('\n');
+        written += 1;
+    }
+    atomic::write(path, lines)?;
+    Ok((written, missing))
+}
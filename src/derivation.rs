@@ -0,0 +1,179 @@
+//! Turning an animated GIF or APNG of a derivation worked step by step into
+//! one numbered `align` for `latex_ocr derivation`: each distinct frame is
+//! converted on its own and adds the rows it shows, in the order they appear.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use latex_ocr::normalize;
+
+use crate::recording;
+
+/// Sent with every frame, so each reply is just the math it shows
+pub const CONTEXT: &str = "This is one frame of an animation that works through a derivation step by step. \
+Transcribe only its math, one step per line, without prose or display delimiters around it.";
+
+/// Environments a step may come wrapped in, dropped so its rows join the `align`
+const DISPLAYS: &[&str] = &[
+    "align", "align*", "aligned", "equation", "equation*", "gather", "gather*", "multline", "multline*", "split",
+];
+
+/// Where a step's row is aligned when it doesn't say: its first relation
+const RELATIONS: &[&str] = &[
+    "=", "<", ">", "\\leq", "\\geq", "\\le", "\\ge", "\\approx", "\\equiv", "\\implies", "\\iff", "\\Rightarrow",
+    "\\Leftrightarrow",
+];
+
+/// The frames of the animation at `path`, in order: APNGs are decoded here
+/// when there is image decoding, anything else is split with ffmpeg
+pub fn frames(path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if !path.is_file() {
+        return Err("no such file".into());
+    }
+    #[cfg(feature = "preprocess")]
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")) {
+        return split_apng(path);
+    }
+    let directory = recording::extract(path, None, "derivations")?;
+    recording::in_order(&directory)
+}
+
+/// Each frame of the APNG at `path` as a PNG of its own; a still PNG is its only frame
+#[cfg(feature = "preprocess")]
+fn split_apng(path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    use image::AnimationDecoder;
+    use image::codecs::png::PngDecoder;
+
+    let decoder = PngDecoder::new(std::io::BufReader::new(std::fs::File::open(path)?))?;
+    if !decoder.is_apng()? {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let directory = recording::frame_directory(path, "derivations")?;
+    let mut frames = Vec::new();
+    for (index, frame) in decoder.apng()?.into_frames().enumerate() {
+        let frame_path = directory.join(format!("frame-{:05}.png", index + 1));
+        frame?.into_buffer().save_with_format(&frame_path, image::ImageFormat::Png)?;
+        frames.push(frame_path);
+    }
+    Ok(frames)
+}
+
+/// The rows of `steps` as one numbered `align`, in order and without rows an
+/// earlier step already had, so frames that keep the steps before on screen
+/// only add their new one
+pub fn assemble(steps: &[String]) -> String {
+    let mut seen = HashSet::new();
+    let mut rows = Vec::new();
+    for step in steps {
+        for row in rows_of(step) {
+            if seen.insert(normalize::math(&row.replace('&', ""))) {
+                rows.push(aligned(&row));
+            }
+        }
+    }
+    format!("\\begin{{align}}\n{}\n\\end{{align}}", rows.join(" \\\\\n"))
+}
+
+/// The rows of one step's math, taken out of any display around it
+fn rows_of(step: &str) -> Vec<String> {
+    let mut math = step.trim().to_string();
+    for (open, close) in [("\\[", "\\]"), ("$$", "$$")] {
+        if let Some(inner) = math.strip_prefix(open).and_then(|rest| rest.strip_suffix(close)) {
+            math = inner.to_string();
+        }
+    }
+    for environment in DISPLAYS {
+        math = math
+            .replace(&format!("\\begin{{{}}}", environment), "")
+            .replace(&format!("\\end{{{}}}", environment), "");
+    }
+    split_rows(&math)
+        .into_iter()
+        .map(|row| row.trim().trim_end_matches(['.', ',']).trim_end().to_string())
+        .filter(|row| !row.is_empty())
+        .collect()
+}
+
+/// `math` split at the `\\` and line breaks outside braces and environments,
+/// like a matrix's
+fn split_rows(math: &str) -> Vec<&str> {
+    let mut rows = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    let mut i = 0;
+    while let Some(c) = math[i..].chars().next() {
+        let rest = &math[i..];
+        if rest.starts_with("\\begin") {
+            depth += 1;
+            i += "\\begin".len();
+        } else if rest.starts_with("\\end") {
+            depth -= 1;
+            i += "\\end".len();
+        } else if rest.starts_with("\\\\") {
+            i += 2;
+            if depth == 0 {
+                rows.push(&math[start..i - 2]);
+                // The spacing of `\\[2pt]` means nothing once rows are rejoined
+                if math[i..].starts_with('[')
+                    && let Some(close) = math[i..].find(']')
+                {
+                    i += close + 1;
+                }
+                start = i;
+            }
+        } else if c == '\\' {
+            // `\{` and `\}` aren't groups
+            i += 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+        } else {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                '\n' if depth == 0 => {
+                    rows.push(&math[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+            i += c.len_utf8();
+        }
+    }
+    rows.push(&math[start..]);
+    rows
+}
+
+/// `row` with an `&` before its first relation outside braces and
+/// environments, unless it is aligned already or has none
+fn aligned(row: &str) -> String {
+    let mut depth = 0i32;
+    let mut first = None;
+    for (i, c) in row.char_indices() {
+        // `\{` and `\}` aren't groups, and `\=` isn't a relation
+        if row[..i].ends_with('\\') {
+            continue;
+        }
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '\\' if row[i..].starts_with("\\begin") => depth += 1,
+            '\\' if row[i..].starts_with("\\end") => depth -= 1,
+            '&' if depth == 0 => return row.to_string(),
+            _ if depth == 0 && first.is_none() && is_relation(&row[i..]) => first = Some(i),
+            _ => {}
+        }
+    }
+    match first {
+        Some(i) => format!("{}&{}", &row[..i], &row[i..]),
+        None => row.to_string(),
+    }
+}
+
+/// Whether `rest` starts with a relation; `\le` doesn't start `\left`
+fn is_relation(rest: &str) -> bool {
+    RELATIONS.iter().any(|relation| {
+        let Some(after) = rest.strip_prefix(relation) else {
+            return false;
+        };
+        !relation.starts_with('\\') || !after.starts_with(|c: char| c.is_ascii_alphabetic())
+    })
+}
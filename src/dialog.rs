@@ -0,0 +1,145 @@
+//! Confirmation prompts: native dialogs with the `gui` feature, a terminal
+//! prompt otherwise.
+
+use std::path::PathBuf;
+
+/// Asks a yes/no question, defaulting to "no"
+#[cfg(feature = "gui")]
+pub fn confirm(title: &str, message: &str) -> bool {
+    use tinyfiledialogs::{MessageBoxIcon, YesNo};
+
+    tinyfiledialogs::message_box_yes_no(title, message, MessageBoxIcon::Question, YesNo::No) == YesNo::Yes
+}
+
+/// Asks a yes/no question, defaulting to "no"
+#[cfg(not(feature = "gui"))]
+pub fn confirm(title: &str, message: &str) -> bool {
+    use std::io::{self, BufRead, Write};
+
+    eprint!("{}: {} [y/N] ", title, message);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Asks which of `choices` to go with, numbered from 1; the index picked,
+/// `None` if cancelled. Entering nothing picks the first
+#[cfg(feature = "gui")]
+pub fn choose(title: &str, choices: &[String]) -> Option<usize> {
+    let message = format!("{}\n\nNumber of the one to use:", numbered(choices));
+    let answer = tinyfiledialogs::input_box(title, &message, "1")?;
+    chosen(&answer, choices.len())
+}
+
+/// Asks which of `choices` to go with, numbered from 1; the index picked,
+/// `None` if cancelled. Entering nothing picks the first
+#[cfg(not(feature = "gui"))]
+pub fn choose(title: &str, choices: &[String]) -> Option<usize> {
+    use std::io::{self, BufRead, Write};
+
+    eprint!("{}:\n{}\nNumber of the one to use [1]: ", title, numbered(choices));
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok()?;
+    chosen(&answer, choices.len())
+}
+
+fn numbered(choices: &[String]) -> String {
+    let lines: Vec<String> = choices.iter().enumerate().map(|(index, choice)| format!("{}. {}", index + 1, choice)).collect();
+    lines.join("\n")
+}
+
+/// The index `answer` names out of `count`, the first for an empty one
+fn chosen(answer: &str, count: usize) -> Option<usize> {
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Some(0);
+    }
+    answer.parse::<usize>().ok().filter(|number| (1..=count).contains(number)).map(|number| number - 1)
+}
+
+/// Shows an error and waits until it is dismissed
+#[cfg(feature = "gui")]
+pub fn alert(title: &str, message: &str) {
+    use tinyfiledialogs::MessageBoxIcon;
+
+    tinyfiledialogs::message_box_ok(title, message, MessageBoxIcon::Error);
+}
+
+/// Shows an error; there is nothing to dismiss on the terminal
+#[cfg(not(feature = "gui"))]
+pub fn alert(title: &str, message: &str) {
+    eprintln!("{}: {}", title, message);
+}
+
+/// Asks for a line of free text; `None` if cancelled or left empty
+#[cfg(feature = "gui")]
+pub fn input(title: &str, message: &str) -> Option<String> {
+    tinyfiledialogs::input_box(title, message, "").filter(|text| !text.trim().is_empty())
+}
+
+/// Asks for a line of free text; `None` if left empty
+#[cfg(not(feature = "gui"))]
+pub fn input(title: &str, message: &str) -> Option<String> {
+    use std::io::{self, BufRead, Write};
+
+    eprint!("{}: {} ", title, message);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok()?;
+    Some(answer.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+/// Shows `text` for editing; what was accepted, or `None` if cancelled or emptied
+#[cfg(feature = "gui")]
+pub fn edit(title: &str, message: &str, text: &str) -> Option<String> {
+    tinyfiledialogs::input_box(title, message, text).filter(|text| !text.trim().is_empty())
+}
+
+/// Shows `text` and reads a replacement; an empty line keeps it, `-` discards it
+#[cfg(not(feature = "gui"))]
+pub fn edit(title: &str, message: &str, text: &str) -> Option<String> {
+    use std::io::{self, BufRead, Write};
+
+    eprint!("{}: {}\n{}\n(enter keeps it, - discards it) ", title, message, text);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok()?;
+    match answer.trim() {
+        "" => Some(text.to_string()),
+        "-" => None,
+        replacement => Some(replacement.to_string()),
+    }
+}
+
+/// Lets the user choose an image file, starting in `directory`
+#[cfg(feature = "gui")]
+pub fn pick_image(title: &str, directory: &str) -> Option<PathBuf> {
+    let start = format!("{}/", directory.trim_end_matches('/'));
+    tinyfiledialogs::open_file_dialog(
+        title,
+        &start,
+        Some((&["*.png", "*.jpg", "*.jpeg"], "Images")),
+    )
+    .map(PathBuf::from)
+}
+
+/// Lets the user type the path of an image file, relative to `directory`
+#[cfg(not(feature = "gui"))]
+pub fn pick_image(title: &str, directory: &str) -> Option<PathBuf> {
+    use std::io::{self, BufRead, Write};
+
+    eprint!("{} (path, empty to cancel): ", title);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok()?;
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return None;
+    }
+    let path = PathBuf::from(shellexpand::tilde(answer).as_ref());
+    Some(if path.is_absolute() { path } else { PathBuf::from(directory).join(path) })
+}
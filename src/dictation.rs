@@ -0,0 +1,89 @@
+//! `--dictate`: record a short audio clip, transcribe it and turn the spoken
+//! math into LaTeX through the same output path as screenshots.
+
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::response;
+use crate::settings::AppConfig;
+use crate::shell;
+
+/// Prepended to the transcript for the LaTeX conversion request
+pub const PROMPT: &str = "The following is a transcript of someone dictating mathematics out loud. Convert it to latex. Only output latex code corresponding to what was said, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.\n\nTranscript: ";
+
+#[cfg(target_os = "macos")]
+const DEFAULT_RECORD_COMMAND: &str = "ffmpeg -loglevel error -f avfoundation -i :0 -t {seconds} -y {output}";
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_RECORD_COMMAND: &str = "arecord -q -f cd -d {seconds} {output}";
+
+#[derive(Debug, Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+/// A recorded clip on disk; the temp file is removed on drop
+pub struct Recording {
+    path: PathBuf,
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Records `dictation_seconds` of audio with the configured (or platform default) command
+pub fn record(config: &AppConfig) -> Result<Recording, Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("latex_ocr-{}.wav", std::process::id()));
+    let template = if config.record_command.trim().is_empty() {
+        DEFAULT_RECORD_COMMAND
+    } else {
+        config.record_command.as_str()
+    };
+    let template = template.replace("{seconds}", &config.dictation_seconds.to_string());
+    let (mut recorder, command) = shell::run_template(&template, "output", &path);
+
+    let status = recorder.status()?;
+    let recording = Recording { path };
+    if !status.success() {
+        return Err(format!("`{}` exited with {}", command, status).into());
+    }
+    if !recording.path.exists() {
+        return Err(format!("`{}` produced no recording", command).into());
+    }
+    Ok(recording)
+}
+
+/// Sends the clip to the configured Whisper-compatible transcription endpoint
+pub async fn transcribe(
+    client: &reqwest::Client,
+    config: &AppConfig,
+    recording: &Recording,
+) -> Result<String, Box<dyn Error>> {
+    if config.transcription_api_key.trim().is_empty() {
+        return Err("transcription_api_key is not set".into());
+    }
+    let audio = tokio::fs::read(&recording.path).await?;
+    let file = reqwest::multipart::Part::bytes(audio)
+        .file_name("dictation.wav")
+        .mime_str("audio/wav")?;
+    let form = reqwest::multipart::Form::new()
+        .text("model", config.transcription_model.clone())
+        .part("file", file);
+
+    let response = client
+        .post(&config.transcription_url)
+        .bearer_auth(&config.transcription_api_key)
+        .multipart(form)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(format!("Transcription failed with status: {}", response.status()).into());
+    }
+    let transcription: TranscriptionResponse = response::parse(&response.text().await?)?;
+    Ok(transcription.text.trim().to_string())
+}
@@ -0,0 +1,232 @@
+//! Finding the screenshot to convert in the image directory.
+
+use chrono::TimeZone;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How "most recent" is decided when picking an image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    /// Last modification time
+    #[default]
+    Modified,
+    /// Creation (birth) time, where the filesystem records one
+    Created,
+    /// The timestamp in names like "Screenshot 2024-05-01 at 10.23.45.png",
+    /// which survives syncing; files without one fall back to their mtime
+    Filename,
+}
+
+/// An image file found in the scanned directory
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub path: PathBuf,
+    /// When the image was taken, according to the selection policy
+    pub time: SystemTime,
+}
+
+impl Candidate {
+    /// How long ago the image was taken
+    pub fn age(&self) -> Duration {
+        SystemTime::now().duration_since(self.time).unwrap_or_default()
+    }
+
+    fn new(path: PathBuf, selection: Selection) -> Option<Self> {
+        let metadata = fs::metadata(&path).ok()?;
+        let modified = metadata.modified().ok()?;
+        let time = match selection {
+            Selection::Modified => modified,
+            Selection::Created => metadata.created().unwrap_or(modified),
+            Selection::Filename => filename_time(&path).unwrap_or(modified),
+        };
+        Some(Self { path, time })
+    }
+}
+
+/// Name of the file listing paths to skip in a scanned directory
+pub const IGNORE_FILE: &str = ".latexocrignore";
+
+/// The `.latexocrignore` patterns of a directory, gitignore syntax
+pub struct Ignore(Option<Gitignore>);
+
+impl Ignore {
+    /// Reads `directory`'s ignore file; a missing or unreadable one ignores nothing
+    pub fn load(directory: &Path) -> Self {
+        let path = directory.join(IGNORE_FILE);
+        if !path.is_file() {
+            return Self(None);
+        }
+        let mut builder = GitignoreBuilder::new(directory);
+        if let Some(e) = builder.add(&path) {
+            eprintln!("Skipping part of {}: {}", path.display(), e);
+        }
+        match builder.build() {
+            Ok(patterns) => Self(Some(patterns)),
+            Err(e) => {
+                eprintln!("Ignoring {}: {}", path.display(), e);
+                Self(None)
+            }
+        }
+    }
+
+    /// Whether `path`, or a folder it is in, matches a pattern
+    pub fn skips(&self, path: &Path) -> bool {
+        self.0.as_ref().is_some_and(|patterns| {
+            patterns.matched_path_or_any_parents(path, path.is_dir()).is_ignore()
+        })
+    }
+}
+
+/// Whether `path` has one of the image extensions we can upload
+pub fn is_image(path: &Path) -> bool {
+    if let Some(ext) = path.extension() {
+        let ext = ext.to_string_lossy().to_lowercase();
+        ext == "png" || ext == "jpg" || ext == "jpeg"
+    } else {
+        false
+    }
+}
+
+/// How often `settle` looks at the file size
+const SETTLE_INTERVAL: Duration = Duration::from_millis(100);
+/// How long `settle` waits for a file that keeps growing
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Waits until `path` has stopped growing, so a screenshot that is still
+/// being written (or a download in progress) isn't uploaded truncated; the
+/// create event of a watched file can come that early too. A file counts as
+/// complete once it is non-empty and its size and mtime are unchanged
+/// between two looks. False if it is gone or still changing at the deadline
+pub async fn settle(path: &Path) -> bool {
+    let look = || fs::metadata(path).ok().map(|metadata| (metadata.len(), metadata.modified().ok()));
+    let mut last = look();
+    // Files nobody has touched for a while, like most of a batch, are done
+    if let Some((size, Some(modified))) = last
+        && size > 0
+        && modified.elapsed().is_ok_and(|age| age > SETTLE_TIMEOUT)
+    {
+        return true;
+    }
+    let deadline = tokio::time::Instant::now() + SETTLE_TIMEOUT;
+    while last.is_some() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(SETTLE_INTERVAL).await;
+        let current = look();
+        if current == last && current.is_some_and(|(size, _)| size > 0) {
+            return true;
+        }
+        last = current;
+    }
+    false
+}
+
+/// Finds the `count` most recent PNG/JPEGs in `directory` that aren't
+/// ignored, newest first
+pub fn newest_images(directory: &str, selection: Selection, count: usize) -> Vec<Candidate> {
+    let ignore = Ignore::load(Path::new(directory));
+    let mut images: Vec<Candidate> = fs::read_dir(directory)
+        .expect("Failed to read directory")
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_image(&entry.path()) && !ignore.skips(&entry.path()))
+        .filter_map(|entry| Candidate::new(entry.path(), selection))
+        .collect();
+    images.sort_by_key(|candidate| std::cmp::Reverse(candidate.time));
+    images.truncate(count);
+    images
+}
+
+/// Every PNG/JPEG in `directory` that isn't ignored, oldest first
+pub fn images_in(directory: &Path, selection: Selection) -> std::io::Result<Vec<Candidate>> {
+    let ignore = Ignore::load(directory);
+    let mut images: Vec<Candidate> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_image(&entry.path()) && !ignore.skips(&entry.path()))
+        .filter_map(|entry| Candidate::new(entry.path(), selection))
+        .collect();
+    images.sort_by_key(|candidate| candidate.time);
+    Ok(images)
+}
+
+/// Local time in a screenshot name: macOS ("Screenshot 2024-05-01 at
+/// 10.23.45.png", "Screen Shot 2020-01-01 at 1.02.03 PM.png"), GNOME
+/// ("Screenshot from 2024-05-01 10-23-45.png"), Android and KDE
+/// ("Screenshot_20240501-102345.png")
+fn filename_time(path: &Path) -> Option<SystemTime> {
+    let name = path.file_stem()?.to_string_lossy().to_string();
+    // Digit runs, with compact dates and times split into their fields
+    let mut fields: Vec<&str> = Vec::new();
+    for run in name.split(|c: char| !c.is_ascii_digit()).filter(|run| !run.is_empty()) {
+        match run.len() {
+            8 => fields.extend([&run[..4], &run[4..6], &run[6..]]),
+            6 => fields.extend([&run[..2], &run[2..4], &run[4..]]),
+            14 => fields.extend([&run[..4], &run[4..6], &run[6..8], &run[8..10], &run[10..12], &run[12..]]),
+            _ => fields.push(run),
+        }
+    }
+    let start = fields.iter().position(|field| field.len() == 4)?;
+    let [year, month, day, hour, minute, second] = fields.get(start..start + 6)? else {
+        return None;
+    };
+    let mut hour: u32 = hour.parse().ok()?;
+    // Recent macOS puts a narrow no-break space before AM/PM
+    let upper = name.to_uppercase().replace('\u{202f}', " ");
+    if upper.contains(" PM") && hour < 12 {
+        hour += 12;
+    } else if upper.contains(" AM") && hour == 12 {
+        hour = 0;
+    }
+    let time = chrono::Local
+        .with_ymd_and_hms(
+            year.parse().ok()?,
+            month.parse().ok()?,
+            day.parse().ok()?,
+            hour,
+            minute.parse().ok()?,
+            second.parse().ok()?,
+        )
+        .earliest()?;
+    Some(SystemTime::from(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> Option<SystemTime> {
+        let time = chrono::Local.with_ymd_and_hms(year, month, day, hour, minute, second).earliest()?;
+        Some(SystemTime::from(time))
+    }
+
+    fn time(name: &str) -> Option<SystemTime> {
+        filename_time(Path::new(name))
+    }
+
+    #[test]
+    fn macos_names_give_their_time() {
+        assert_eq!(time("Screenshot 2024-05-01 at 10.23.45.png"), local(2024, 5, 1, 10, 23, 45));
+    }
+
+    #[test]
+    fn twelve_hour_names_give_their_time() {
+        assert_eq!(time("Screen Shot 2020-01-01 at 1.02.03 PM.png"), local(2020, 1, 1, 13, 2, 3));
+        assert_eq!(time("Screenshot 2024-05-01 at 12.00.10\u{202f}AM.png"), local(2024, 5, 1, 0, 0, 10));
+    }
+
+    #[test]
+    fn gnome_names_give_their_time() {
+        assert_eq!(time("Screenshot from 2024-05-01 10-23-45.png"), local(2024, 5, 1, 10, 23, 45));
+    }
+
+    #[test]
+    fn android_and_kde_names_give_their_time() {
+        assert_eq!(time("Screenshot_20240501-102345.png"), local(2024, 5, 1, 10, 23, 45));
+    }
+
+    #[test]
+    fn names_without_a_year_give_none() {
+        assert_eq!(time("IMG_12.png"), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! Assembling results into one compilable .tex document, for sessions and
+//! `batch --document`.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+
+use crate::shell;
+
+/// A titled part of the document; untitled parts follow each other directly
+pub struct Section<'a> {
+    pub title: Option<&'a str>,
+    pub latex: &'a str,
+}
+
+/// `preamble` (everything before `\begin{document}`), then every section in
+/// order; bare math is put in display environments so the document compiles
+pub fn assemble(preamble: &str, title: &str, sections: &[Section]) -> String {
+    let mut document = format!(
+        "{}\n\\title{{{}}}\n\\date{{}}\n\n\\begin{{document}}\n\\maketitle\n",
+        preamble.trim_end(),
+        escape(title)
+    );
+    for section in sections {
+        document.push('\n');
+        if let Some(title) = section.title {
+            document.push_str(&format!("\\section{{{}}}\n", escape(title)));
+        }
+        let latex = section.latex.trim();
+        if is_bare_math(latex) {
+            document.push_str(&format!("\\begin{{equation*}}\n{}\n\\end{{equation*}}\n", latex));
+        } else {
+            document.push_str(latex);
+            document.push('\n');
+        }
+    }
+    document.push_str("\n\\end{document}\n");
+    document
+}
+
+/// Whether `latex` has no math delimiters or environments at all
+pub fn is_bare_math(latex: &str) -> bool {
+    !["$", "\\[", "\\(", "\\begin{"]
+        .iter()
+        .any(|delimiter| latex.contains(delimiter))
+}
+
+/// Escapes the characters of a file or session name that are special in text mode
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '_' | '&' | '%' | '$' | '#' | '{' | '}' => format!("\\{}", c),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Runs `command` (with `{file}` replaced) next to `path` as a final check
+/// that the document compiles
+pub fn compile(command: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let (command, error) = first_error(command, path)?;
+    match error {
+        Some(error) => Err(format!("`{}` failed: {}", command, error).into()),
+        None => Ok(()),
+    }
+}
+
+/// Compiles `latex` alone in a minimal document after `preamble` with
+/// `command`, for `compile_check`; the first error if it doesn't compile.
+/// Fails if the command couldn't be run at all
+pub fn check(command: &str, preamble: &str, latex: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let directory = std::env::temp_dir().join(format!("latex_ocr-check-{}", std::process::id()));
+    fs::create_dir_all(&directory)?;
+    let path = directory.join("snippet.tex");
+    let latex = latex.trim();
+    let body = if is_bare_math(latex) {
+        format!("\\begin{{equation*}}\n{}\n\\end{{equation*}}", latex)
+    } else {
+        latex.to_string()
+    };
+    let document = format!("{}\n\\begin{{document}}\n{}\n\\end{{document}}\n", preamble.trim_end(), body);
+    let checked = match fs::write(&path, document) {
+        Ok(()) => first_error(command, &path),
+        Err(e) => Err(e.into()),
+    };
+    // The .aux, .log and .pdf are of no use afterwards
+    let _ = fs::remove_dir_all(&directory);
+    Ok(checked?.1)
+}
+
+/// `command` as run and, if it failed, the first error LaTeX reported
+fn first_error(command: &str, path: &Path) -> Result<(String, Option<String>), Box<dyn Error>> {
+    let directory = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let file = path.file_name().ok_or("document path has no file name")?;
+    let (mut latex, command) = shell::run_template(command, "file", Path::new(file));
+    let output = latex
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        return Ok((command, None));
+    }
+    // The shell's code for a command it couldn't find
+    if output.status.code() == Some(127) {
+        return Err(format!("`{}` failed: {}", command, String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    // LaTeX reports errors on stdout as lines starting with "!"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let error = stdout
+        .lines()
+        .find(|line| line.starts_with('!'))
+        .unwrap_or("see the log for details")
+        .to_string();
+    Ok((command, Some(error)))
+}
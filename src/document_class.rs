@@ -0,0 +1,108 @@
+//! The kind of document results are pasted into, set with `document_class`:
+//! the prompt says what the class expects, and environments it doesn't have
+//! are rewritten or dropped from the result.
+
+/// A document class latex_ocr knows what to ask for and what to avoid in
+struct Class {
+    name: &'static str,
+    /// Sent with the prompt, completing "The result goes into ..."
+    context: &'static str,
+    /// Environments the class doesn't have, and the one their body goes in
+    /// instead; `None` keeps just the body
+    environments: &'static [(&'static str, Option<&'static str>)],
+    /// Commands that make no sense in it, dropped
+    commands: &'static [&'static str],
+}
+
+const CLASSES: &[Class] = &[
+    Class {
+        name: "article",
+        context: "a standard LaTeX article",
+        environments: &[],
+        commands: &[],
+    },
+    Class {
+        name: "amsart",
+        context: "an AMS article (amsart), which loads amsmath and amsthm: prefer their environments, \
+            e.g. align over eqnarray, and theorems and proofs the way amsthm writes them",
+        environments: &[("eqnarray", Some("align")), ("eqnarray*", Some("align*"))],
+        commands: &[],
+    },
+    Class {
+        name: "amsbook",
+        context: "an AMS book (amsbook), which loads amsmath and amsthm: prefer their environments, \
+            e.g. align over eqnarray",
+        environments: &[("eqnarray", Some("align")), ("eqnarray*", Some("align*")), ("abstract", None)],
+        commands: &[],
+    },
+    Class {
+        name: "beamer",
+        context: "a beamer slide: keep it compact, with no sectioning or page-break commands and no floats \
+            (figure and table environments)",
+        environments: &[("figure", Some("center")), ("table", Some("center")), ("abstract", None)],
+        commands: &["\\newpage", "\\clearpage", "\\pagebreak", "\\maketitle"],
+    },
+    Class {
+        name: "exam",
+        context: "an exam (the exam class): write numbered questions as \\question inside a questions \
+            environment, their parts as \\part inside parts, and solutions inside solution environments",
+        environments: &[("abstract", None)],
+        commands: &["\\maketitle"],
+    },
+];
+
+/// Names `document_class` can be
+pub fn names() -> Vec<&'static str> {
+    CLASSES.iter().map(|class| class.name).collect()
+}
+
+fn find(name: &str) -> Option<&'static Class> {
+    CLASSES.iter().find(|class| class.name == name.trim())
+}
+
+/// What is added to the prompt for `name`, nothing for an unknown or empty one
+pub fn instructions(name: &str) -> Option<String> {
+    find(name).map(|class| format!("\n\nThe result goes into {}.", class.context))
+}
+
+/// `latex` without the environments and commands `name` doesn't support
+pub fn adapt(latex: &str, name: &str) -> String {
+    let Some(class) = find(name) else {
+        return latex.to_string();
+    };
+    let mut adapted = latex.to_string();
+    for (environment, replacement) in class.environments {
+        adapted = replace_environment(&adapted, environment, *replacement);
+    }
+    for command in class.commands {
+        adapted = adapted.replace(command, "");
+    }
+    adapted
+}
+
+/// `\begin{environment}` and `\end{environment}` (with any `[placement]`
+/// option) renamed to `replacement`, or removed around the body
+fn replace_environment(latex: &str, environment: &str, replacement: Option<&str>) -> String {
+    let begin = format!("\\begin{{{}}}", environment);
+    let end = format!("\\end{{{}}}", environment);
+    let mut replaced = String::with_capacity(latex.len());
+    let mut rest = latex;
+    while let Some(start) = rest.find(&begin) {
+        replaced.push_str(&rest[..start]);
+        rest = &rest[start + begin.len()..];
+        // Float placement like [htbp] only means something to the float
+        if rest.starts_with('[')
+            && let Some(close) = rest.find(']')
+        {
+            rest = &rest[close + 1..];
+        }
+        if let Some(replacement) = replacement {
+            replaced.push_str(&format!("\\begin{{{}}}", replacement));
+        }
+    }
+    replaced.push_str(rest);
+    match replacement {
+        Some(replacement) => replaced.replace(&end, &format!("\\end{{{}}}", replacement)),
+        None => replaced.replace(&end, ""),
+    }
+}
@@ -0,0 +1,71 @@
+//! How loudly failures are reported: quietly for a one-off hiccup, with a
+//! sound once they repeat and with a dialog when they keep happening. The
+//! count of failures in a row is shared by every invocation through the
+//! state directory.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::atomic;
+use crate::dialog;
+use crate::lock::Lock;
+use crate::notify::{self, Sound};
+use crate::paths;
+use crate::settings::AppConfig;
+
+static SOUND_AFTER: AtomicU32 = AtomicU32::new(1);
+static DIALOG_AFTER: AtomicU32 = AtomicU32::new(0);
+
+/// Takes the thresholds from the config
+pub fn configure(config: &AppConfig) {
+    SOUND_AFTER.store(config.failure_sound_after, Ordering::Relaxed);
+    DIALOG_AFTER.store(config.failure_dialog_after, Ordering::Relaxed);
+}
+
+fn count_path() -> PathBuf {
+    paths::state_dir().join("failures")
+}
+
+/// Serializes updates of the count between instances; counting without it
+/// beats not reporting the failure at all
+fn lock() -> Option<Lock> {
+    Lock::exclusive(&paths::state_dir().join("failures.lock")).ok()
+}
+
+fn count() -> u32 {
+    fs::read_to_string(count_path())
+        .ok()
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Reports a failure as loudly as the failures in a row so far call for
+pub fn failed(title: &str, message: &str) {
+    let count = {
+        let _lock = lock();
+        let count = count() + 1;
+        let _ = atomic::write(&count_path(), count.to_string());
+        count
+    };
+    let dialog_after = DIALOG_AFTER.load(Ordering::Relaxed);
+    if dialog_after > 0 && count >= dialog_after {
+        crate::events::emit(
+            "notification",
+            serde_json::json!({ "title": title, "message": message, "outcome": "failure", "dialog": true }),
+        );
+        dialog::alert(title, &format!("{}\n\n({} failures in a row)", message, count));
+    } else if count >= SOUND_AFTER.load(Ordering::Relaxed) {
+        notify::send(title, message, Sound::Failure);
+    } else {
+        notify::send(title, message, Sound::Muted);
+    }
+}
+
+/// Starts the count over after a success
+pub fn succeeded() {
+    let _lock = lock();
+    if count() > 0 {
+        let _ = fs::remove_file(count_path());
+    }
+}
@@ -0,0 +1,59 @@
+//! Lifecycle event log for `--log-format`: one line per event on stderr,
+//! JSON objects for dashboards or `key=value` text.
+
+use serde_json::{Map, Value, json};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+
+/// Turns event logging on; without this `emit` does nothing
+pub fn init(format: Format) {
+    let _ = FORMAT.set(format);
+}
+
+/// Logs `event` with the fields of the `fields` object
+pub fn emit(event: &str, fields: Value) {
+    let Some(format) = FORMAT.get() else {
+        return;
+    };
+    let time = chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false);
+    let fields = match fields {
+        Value::Object(fields) => fields,
+        _ => Map::new(),
+    };
+    match format {
+        Format::Json => {
+            let mut line = Map::new();
+            line.insert("time".to_string(), json!(time));
+            line.insert("event".to_string(), json!(event));
+            line.extend(fields);
+            eprintln!("{}", Value::Object(line));
+        }
+        Format::Text => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(key, value)| match value {
+                    Value::String(text) => format!("{}={:?}", key, text),
+                    other => format!("{}={}", key, other),
+                })
+                .collect();
+            eprintln!("{} {} {}", time, event, fields.join(" "));
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Markup the result is written in when no template is set, for notes that
+//! aren't LaTeX files. Most are rewritten from the LaTeX the model returns;
+//! for Typst and AsciiMath the model is asked to write them instead.
+
+use serde::Deserialize;
+
+use crate::document;
+use crate::target::Target;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    /// The LaTeX as the model wrote it
+    #[default]
+    Latex,
+    /// Org-mode LaTeX fragments: bare math becomes `\[...\]`
+    Org,
+    /// An org `#+begin_export latex` block
+    OrgExport,
+    /// A reStructuredText `.. math::` directive, or `:math:` roles in prose
+    Rst,
+    /// Markdown with `$$...$$` displays and `$...$` inline math
+    Markdown,
+    /// MathML `<math>` elements
+    Mathml,
+    /// Typst math, written by the model
+    Typst,
+    /// AsciiMath, written by the model
+    Asciimath,
+}
+
+/// What the model is asked for when it writes the result in Typst
+const TYPST_INSTRUCTIONS: &str = "\n\nWrite the result as Typst math instead of LaTeX: math between `$` signs, \
+with a space after the opening and before the closing `$` for displayed equations, and prose as plain text.";
+
+/// What the model is asked for when it writes the result in AsciiMath
+const ASCIIMATH_INSTRUCTIONS: &str = "\n\nWrite the result as AsciiMath instead of LaTeX (e.g. `sum_(i=1)^n i^2`, \
+`(a)/(b)`), with each equation on a line of its own and prose as plain text.";
+
+impl Format {
+    /// Looks up a format by the name used in the config and on the command line
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "latex" => Some(Format::Latex),
+            "org" => Some(Format::Org),
+            "org-export" => Some(Format::OrgExport),
+            "rst" => Some(Format::Rst),
+            "markdown" => Some(Format::Markdown),
+            "mathml" => Some(Format::Mathml),
+            "typst" => Some(Format::Typst),
+            "asciimath" => Some(Format::Asciimath),
+            _ => None,
+        }
+    }
+
+    /// Whether the model writes LaTeX for this format, so LaTeX post-processing applies
+    pub fn is_latex(self) -> bool {
+        !matches!(self, Format::Typst | Format::Asciimath)
+    }
+
+    /// What is added to the prompt to get the result in this format, if the
+    /// model writes it rather than LaTeX
+    pub fn instructions(self) -> Option<&'static str> {
+        match self {
+            Format::Typst => Some(TYPST_INSTRUCTIONS),
+            Format::Asciimath => Some(ASCIIMATH_INSTRUCTIONS),
+            _ => None,
+        }
+    }
+
+    /// Rewrites `latex` in this format; Typst and AsciiMath are already in it
+    pub fn apply(self, latex: &str) -> String {
+        let latex = latex.trim();
+        match self {
+            Format::Latex | Format::Typst | Format::Asciimath => latex.to_string(),
+            // The same rewrite as for KaTeX, which is what most Markdown renderers use
+            Format::Markdown => Target::Katex.apply(latex),
+            Format::Mathml => Target::Mathml.apply(latex),
+            Format::Org if document::is_bare_math(latex) => format!("\\[\n{}\n\\]", latex),
+            Format::Org => replace_dollars(latex, ("\\(", "\\)"), ("\\[", "\\]")),
+            Format::OrgExport => format!("#+begin_export latex\n{}\n#+end_export", latex),
+            Format::Rst => match display_body(latex) {
+                Some(body) => {
+                    let indented: Vec<String> = body.lines().map(|line| format!("   {}", line)).collect();
+                    format!(".. math::\n\n{}", indented.join("\n"))
+                }
+                None => inline_roles(latex),
+            },
+        }
+    }
+
+    /// A nomenclature table of `(symbol, meaning)` pairs in this format
+    pub fn glossary(self, entries: &[(String, String)]) -> String {
+        match self {
+            Format::Latex | Format::OrgExport => {
+                let rows: Vec<String> = entries
+                    .iter()
+                    .map(|(symbol, meaning)| format!("${}$ & {} \\\\", symbol, meaning))
+                    .collect();
+                let table = format!("\\begin{{tabular}}{{ll}}\n{}\n\\end{{tabular}}", rows.join("\n"));
+                if self == Format::OrgExport { self.apply(&table) } else { table }
+            }
+            Format::Org => {
+                let rows: Vec<String> = entries
+                    .iter()
+                    .map(|(symbol, meaning)| format!("| \\({}\\) | {} |", symbol, meaning))
+                    .collect();
+                format!("| Symbol | Meaning |\n|--------+---------|\n{}", rows.join("\n"))
+            }
+            Format::Rst => entries
+                .iter()
+                .map(|(symbol, meaning)| format!(":math:`{}`\n   {}", symbol, meaning))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            Format::Markdown | Format::Mathml => {
+                let rows: Vec<String> = entries
+                    .iter()
+                    .map(|(symbol, meaning)| format!("| {} | {} |", self.apply(&format!("${}$", symbol)), meaning))
+                    .collect();
+                format!("| Symbol | Meaning |\n|--------|---------|\n{}", rows.join("\n"))
+            }
+            Format::Typst => {
+                let cells: Vec<String> = entries
+                    .iter()
+                    .map(|(symbol, meaning)| format!("  [${}$], [{}],", symbol, meaning))
+                    .collect();
+                format!("#table(\n  columns: 2,\n{}\n)", cells.join("\n"))
+            }
+            Format::Asciimath => entries
+                .iter()
+                .map(|(symbol, meaning)| format!("{}: {}", symbol, meaning))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    /// The file extension of a file holding just a result in this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Latex => "tex",
+            Format::Org | Format::OrgExport => "org",
+            Format::Rst => "rst",
+            Format::Markdown => "md",
+            Format::Mathml => "html",
+            Format::Typst => "typ",
+            Format::Asciimath => "txt",
+        }
+    }
+
+    /// A comment line in this format
+    pub fn comment(self, text: &str) -> String {
+        match self {
+            Format::Latex | Format::OrgExport => format!("% {}", text),
+            Format::Org => format!("# {}", text),
+            Format::Rst => format!(".. {}", text),
+            // AsciiMath has no comments; it's usually embedded in HTML
+            Format::Markdown | Format::Mathml | Format::Asciimath => format!("<!-- {} -->", text),
+            Format::Typst => format!("// {}", text),
+        }
+    }
+}
+
+/// The math of a result that is a single display: bare math, `\[...\]`,
+/// `$$...$$` or a math environment
+fn display_body(latex: &str) -> Option<String> {
+    if document::is_bare_math(latex) {
+        return Some(latex.to_string());
+    }
+    for (open, close) in [("\\[", "\\]"), ("$$", "$$")] {
+        if let Some(inner) = latex.strip_prefix(open).and_then(|rest| rest.strip_suffix(close))
+            && !inner.contains(open)
+        {
+            return Some(inner.trim().to_string());
+        }
+    }
+    // Sphinx and MathJax both accept environments inside the directive
+    (latex.starts_with("\\begin{") && latex.ends_with('}')).then(|| latex.to_string())
+}
+
+/// Prose with `$...$` inline math becomes prose with `:math:` roles
+fn inline_roles(latex: &str) -> String {
+    replace_dollars(latex, (":math:`", "`"), (":math:`", "`"))
+}
+
+/// Replaces `$...$` and `$$...$$` delimiters, leaving escaped `\$` alone
+fn replace_dollars(latex: &str, inline: (&str, &str), display: (&str, &str)) -> String {
+    let mut text = String::new();
+    let mut open: Option<bool> = None; // Some(true) inside display math
+    let mut chars = latex.chars().peekable();
+    let mut escaped = false;
+    while let Some(c) = chars.next() {
+        if c != '$' || escaped {
+            escaped = c == '\\' && !escaped;
+            text.push(c);
+            continue;
+        }
+        let is_display = chars.peek() == Some(&'$');
+        if is_display {
+            chars.next();
+        }
+        match open {
+            None => {
+                text.push_str(if is_display { display.0 } else { inline.0 });
+                open = Some(is_display);
+            }
+            Some(display_open) => {
+                text.push_str(if display_open { display.1 } else { inline.1 });
+                open = None;
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dollars_are_replaced_in_pairs() {
+        let org = |latex| replace_dollars(latex, ("\\(", "\\)"), ("\\[", "\\]"));
+        assert_eq!(org("$a$ and $$b$$"), "\\(a\\) and \\[b\\]");
+        assert_eq!(org("$$a$$$b$"), "\\[a\\]\\(b\\)");
+        assert_eq!(org("costs \\$5, $x$"), "costs \\$5, \\(x\\)");
+        assert_eq!(org("\\\\$x$"), "\\\\\\(x\\)");
+    }
+
+    #[test]
+    fn single_displays_have_a_body() {
+        assert_eq!(display_body("x^2").as_deref(), Some("x^2"));
+        assert_eq!(display_body("\\[ x^2 \\]").as_deref(), Some("x^2"));
+        assert_eq!(display_body("$$x^2$$").as_deref(), Some("x^2"));
+        assert_eq!(
+            display_body("\\begin{align}a\\end{align}").as_deref(),
+            Some("\\begin{align}a\\end{align}")
+        );
+        assert_eq!(display_body("\\[a\\] and \\[b\\]"), None);
+        assert_eq!(display_body("Let $x$ be real"), None);
+    }
+
+    #[test]
+    fn inline_math_becomes_roles() {
+        assert_eq!(inline_roles("Let $x$ be \\$1"), "Let :math:`x` be \\$1");
+        assert_eq!(inline_roles("so $$y$$"), "so :math:`y`");
+    }
+
+    #[test]
+    fn org_wraps_bare_math_and_rewrites_dollars() {
+        assert_eq!(Format::Org.apply(" x^2 "), "\\[\nx^2\n\\]");
+        assert_eq!(Format::Org.apply("Let $x$ be"), "Let \\(x\\) be");
+        assert_eq!(
+            Format::OrgExport.apply("\\[x\\]\n"),
+            "#+begin_export latex\n\\[x\\]\n#+end_export"
+        );
+    }
+
+    #[test]
+    fn rst_indents_display_bodies() {
+        assert_eq!(Format::Rst.apply("x^2"), ".. math::\n\n   x^2");
+        assert_eq!(
+            Format::Rst.apply("\\[\na &= b \\\\\nc &= d\n\\]"),
+            ".. math::\n\n   a &= b \\\\\n   c &= d"
+        );
+        assert_eq!(Format::Rst.apply("Let $x$ be"), "Let :math:`x` be");
+    }
+
+    fn entries() -> Vec<(String, String)> {
+        vec![
+            ("\\alpha".to_string(), "angle".to_string()),
+            ("v".to_string(), "speed".to_string()),
+        ]
+    }
+
+    #[test]
+    fn markdown_glossary_is_a_table_of_inline_math() {
+        assert_eq!(
+            Format::Markdown.glossary(&entries()),
+            "| Symbol | Meaning |\n|--------|---------|\n| $\\alpha$ | angle |\n| $v$ | speed |"
+        );
+    }
+
+    #[test]
+    fn mathml_glossary_has_a_math_element_per_symbol() {
+        let table = Format::Mathml.glossary(&entries());
+        let rows: Vec<&str> = table.lines().skip(2).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].starts_with("| <math") && rows[0].ends_with("</math> | angle |"));
+        assert!(rows[1].contains("<mi>v</mi>") && rows[1].ends_with("| speed |"));
+    }
+
+    #[test]
+    fn typst_glossary_is_a_table_call() {
+        assert_eq!(
+            Format::Typst.glossary(&entries()),
+            "#table(\n  columns: 2,\n  [$\\alpha$], [angle],\n  [$v$], [speed],\n)"
+        );
+    }
+
+    #[test]
+    fn comments_and_extensions_match_the_markup() {
+        assert_eq!(Format::Markdown.comment("note"), "<!-- note -->");
+        assert_eq!(Format::Mathml.comment("note"), "<!-- note -->");
+        assert_eq!(Format::Typst.comment("note"), "// note");
+        assert_eq!(Format::Markdown.extension(), "md");
+        assert_eq!(Format::Mathml.extension(), "html");
+        assert_eq!(Format::Typst.extension(), "typ");
+    }
+}
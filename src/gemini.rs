@@ -0,0 +1,210 @@
+//! Google Gemini backend (the generativelanguage API).
+
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::error::Error;
+
+use crate::events;
+use crate::provider::{self, BoxFuture, Chunk, Failure, OcrProvider, Reply, Request, Usage};
+use crate::response;
+
+/// `OcrProvider` for Gemini models
+pub struct Gemini {
+    pub api_key: String,
+    /// API root the model path is appended to, e.g. ".../v1beta"
+    pub url: String,
+}
+
+/// One streamed `GenerateContentResponse`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamChunk {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    #[serde(default)]
+    usage_metadata: Option<UsageMetadata>,
+    /// Set instead of candidates when the prompt itself was refused
+    #[serde(default)]
+    prompt_feedback: Option<PromptFeedback>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    #[serde(default)]
+    content: Option<Content>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Content {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Part {
+    #[serde(default)]
+    text: Option<String>,
+    /// Thought summaries, which aren't part of the answer
+    #[serde(default)]
+    thought: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+    #[serde(default)]
+    thoughts_token_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    #[serde(default)]
+    block_reason: Option<String>,
+}
+
+/// The body of a failed request
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ApiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    message: String,
+    #[serde(default)]
+    status: Option<String>,
+}
+
+impl OcrProvider for Gemini {
+    fn name(&self) -> &'static str {
+        "Gemini"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn stream<'a>(&'a self, client: &'a reqwest::Client, request: &'a Request<'a>)
+    -> BoxFuture<'a, Result<Reply, Failure>> {
+        Box::pin(self.send(client, request))
+    }
+}
+
+impl Gemini {
+    /// The `generateContent` payload for `request`, the image inline before the
+    /// prompt. A thinking budget goes in `thinkingConfig`, which only Gemini 2.5
+    /// and later accept
+    fn payload(request: &Request) -> Value {
+        let mut parts = Vec::new();
+        if let Some(image) = request.image {
+            parts.push(json!({
+                "inline_data": {
+                    "mime_type": image.media_type,
+                    "data": BASE64.encode(image.data)
+                }
+            }));
+        }
+        parts.push(json!({ "text": request.prompt }));
+        let mut generation = json!({ "maxOutputTokens": request.limits.max_tokens });
+        if let Some(budget) = request.limits.thinking_budget {
+            // Thinking counts towards the output limit, as with Anthropic
+            generation["maxOutputTokens"] = json!(request.limits.max_tokens + budget);
+            generation["thinkingConfig"] = json!({ "thinkingBudget": budget });
+        }
+        json!({
+            "contents": [
+                {
+                    "role": "user",
+                    "parts": parts
+                }
+            ],
+            "generationConfig": generation
+        })
+    }
+
+    async fn send(&self, client: &reqwest::Client, request: &Request<'_>) -> Result<Reply, Failure> {
+        let url = format!(
+            "{}/models/{}:streamGenerateContent?alt=sse",
+            self.url.trim_end_matches('/'),
+            request.model
+        );
+        let response = provider::post(client, &url)
+            .header("x-goog-api-key", &self.api_key)
+            .json(&Self::payload(request))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Reply::new(response, decode));
+        }
+        let retry_after = provider::retry_after(response.headers());
+        let body = response.text().await?;
+        // RESOURCE_EXHAUSTED is the per-minute rate limit, UNAVAILABLE an overloaded model
+        if status.as_u16() == 429 || status.as_u16() == 503 {
+            return Err(Failure::Overloaded { retry_after });
+        }
+        Err(match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(failure) => format!(
+                "API request failed with status: {} ({}: {})",
+                status,
+                failure.error.status.unwrap_or_else(|| "error".to_string()),
+                failure.error.message
+            ),
+            Err(_) => format!("API request failed with status: {}", status),
+        }
+        .into())
+    }
+}
+
+/// The stream has no end marker, the chunk with a finish reason is the last
+fn decode(data: &str) -> Result<Vec<Chunk>, Box<dyn Error>> {
+    let chunk: StreamChunk = response::parse(data)?;
+    if let Some(reason) = chunk.prompt_feedback.and_then(|feedback| feedback.block_reason) {
+        return Err(format!("Gemini refused the request ({})", reason).into());
+    }
+    let mut chunks = Vec::new();
+    let mut finished = false;
+    for candidate in chunk.candidates {
+        let parts = candidate.content.map(|content| content.parts).unwrap_or_default();
+        chunks.extend(
+            parts
+                .into_iter()
+                .filter(|part| !part.thought)
+                .filter_map(|part| part.text)
+                .filter(|text| !text.is_empty())
+                .map(Chunk::Text),
+        );
+        match candidate.finish_reason.as_deref() {
+            None | Some("FINISH_REASON_UNSPECIFIED") => {}
+            Some("STOP") => finished = true,
+            // The text so far is kept, like a reply cut off at max_reply_bytes
+            Some("MAX_TOKENS") => {
+                eprintln!("Warning: the reply from Gemini reached its token limit, the rest was cut off");
+                events::emit("truncated", json!({ "provider": "Gemini", "reason": "max_tokens" }));
+                finished = true;
+            }
+            Some(reason) => return Err(format!("Gemini stopped the reply ({})", reason).into()),
+        }
+    }
+    if let Some(usage) = chunk.usage_metadata {
+        chunks.push(Chunk::Usage(Usage {
+            input_tokens: usage.prompt_token_count,
+            // Thinking is billed as output
+            output_tokens: usage.candidates_token_count + usage.thoughts_token_count,
+        }));
+    }
+    if finished {
+        chunks.push(Chunk::Done);
+    }
+    Ok(chunks)
+}
@@ -0,0 +1,256 @@
+//! Every delivered result, one JSON object per line in
+//! `~/.local/share/latex_ocr/history.jsonl`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use latex_ocr::normalize;
+
+use crate::atomic;
+use crate::lock::Lock;
+use crate::migrate;
+use crate::paths;
+use crate::pipeline::{self, Conversion};
+use crate::provider::Usage;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Local time of the conversion, RFC 3339
+    pub time: String,
+    /// Converted image, empty for dictation
+    pub image: String,
+    pub latex: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Free-text note attached with `history annotate`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// SHA-256 of the image as uploaded; this and the fields after it are
+    /// missing from entries written before they were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// What the model answered, when `latex` was corrected by hand afterwards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original: Option<String>,
+    /// Position among the entries recorded together, after the first, so
+    /// the same result twice doesn't share an id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<usize>,
+}
+
+impl Entry {
+    /// Short stable identifier, derived from the time, LaTeX and sequence so
+    /// entries written before ids existed have one too
+    pub fn id(&self) -> String {
+        let key = match self.sequence {
+            Some(sequence) => format!("{}\n{}\n{}", self.time, self.latex, sequence),
+            None => format!("{}\n{}", self.time, self.latex),
+        };
+        pipeline::sha256_hex(key.as_bytes())[..8].to_string()
+    }
+
+    /// The day of the conversion, as "Mar 3"
+    pub fn day(&self) -> String {
+        chrono::DateTime::parse_from_rfc3339(&self.time)
+            .map(|time| time.format("%b %-d").to_string())
+            .unwrap_or_else(|_| self.time.clone())
+    }
+
+    /// When the conversion happened, as "2024-03-03 14:05"
+    pub fn when(&self) -> String {
+        chrono::DateTime::parse_from_rfc3339(&self.time)
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|_| self.time.clone())
+    }
+}
+
+fn history_path() -> PathBuf {
+    paths::data_dir().join("history.jsonl")
+}
+
+/// Serializes history updates (appends and migrations) between instances
+fn lock() -> Result<Lock, Box<dyn Error>> {
+    Ok(Lock::exclusive(&paths::data_dir().join("history.lock"))?)
+}
+
+/// Appends the delivered conversions, returning the entries written
+pub fn record(conversions: &[Conversion]) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let path = history_path();
+    let _lock = lock()?;
+    migrate::history(&path)?;
+    let time = chrono::Local::now().to_rfc3339();
+    let mut lines = String::new();
+    if fs::metadata(&path).map_or(true, |metadata| metadata.len() == 0) {
+        lines.push_str(&migrate::history_header());
+        lines.push('\n');
+    }
+    let entries: Vec<Entry> = conversions
+        .iter()
+        .enumerate()
+        .map(|(index, conversion)| Entry {
+            time: time.clone(),
+            image: conversion.image.clone(),
+            latex: conversion.latex.trim().to_string(),
+            source: conversion.source.clone(),
+            note: None,
+            image_hash: Some(conversion.image_hash.clone()).filter(|hash| !hash.is_empty()),
+            model: Some(conversion.model.clone()),
+            prompt: Some(conversion.prompt.clone()).filter(|prompt| !prompt.is_empty()),
+            usage: Some(conversion.usage),
+            original: conversion.original.as_ref().map(|original| original.trim().to_string()),
+            sequence: (index > 0).then_some(index),
+        })
+        .collect();
+    for entry in &entries {
+        lines.push_str(&serde_json::to_string(entry)?);
+        lines.push('\n');
+    }
+    atomic::append(&path, &lines)?;
+    Ok(entries)
+}
+
+/// All entries, oldest first; lines that don't parse (like the version
+/// header) are skipped
+pub fn entries() -> Result<Vec<Entry>, Box<dyn Error>> {
+    let path = history_path();
+    {
+        // Writes replace the file atomically, so only a pending migration needs the lock
+        let _lock = lock()?;
+        migrate::history(&path)?;
+    }
+    let Ok(text) = fs::read_to_string(&path) else {
+        return Ok(Vec::new());
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// The entry `history list` numbers `number` (1 is the newest), or else the
+/// one whose id starts with `number`
+pub fn get(number: &str) -> Result<Entry, Box<dyn Error>> {
+    let mut entries = entries()?;
+    if let Ok(position) = number.parse::<usize>()
+        && (1..=entries.len()).contains(&position)
+    {
+        return Ok(entries.swap_remove(entries.len() - position));
+    }
+    let mut matches: Vec<Entry> = entries.into_iter().filter(|entry| entry.id().starts_with(number)).collect();
+    match matches.len() {
+        1 => Ok(matches.remove(0)),
+        0 => Err(format!("no history entry numbered `{}` or with that id", number).into()),
+        count => Err(format!("id `{}` matches {} entries, give more of it", number, count).into()),
+    }
+}
+
+/// Entries whose LaTeX contains `query`, either literally or once both are
+/// normalized, or whose note mentions it; newest first
+pub fn find(query: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let normalized = normalize::math(query);
+    let lowercase = query.to_lowercase();
+    let mut found: Vec<Entry> = entries()?
+        .into_iter()
+        .filter(|entry| {
+            entry.latex.contains(query)
+                || (!normalized.is_empty() && normalize::math(&entry.latex).contains(&normalized))
+                || entry.note.as_ref().is_some_and(|note| note.to_lowercase().contains(&lowercase))
+        })
+        .collect();
+    found.reverse();
+    Ok(found)
+}
+
+/// When each image that still exists was last converted, keyed by its
+/// canonical path so relative and absolute spellings agree
+pub fn last_converted() -> Result<HashMap<PathBuf, SystemTime>, Box<dyn Error>> {
+    let mut converted = HashMap::new();
+    for entry in entries()? {
+        let Ok(time) = chrono::DateTime::parse_from_rfc3339(&entry.time) else {
+            continue;
+        };
+        if entry.image.is_empty() {
+            continue;
+        }
+        if let Ok(image) = fs::canonicalize(&entry.image) {
+            let time = SystemTime::from(time);
+            converted
+                .entry(image)
+                .and_modify(|last: &mut SystemTime| *last = (*last).max(time))
+                .or_insert(time);
+        }
+    }
+    Ok(converted)
+}
+
+/// Attaches `note` to the entry whose id starts with `id`, replacing any
+/// earlier note; an empty note removes it
+pub fn annotate(id: &str, note: &str) -> Result<Entry, Box<dyn Error>> {
+    let path = history_path();
+    let _lock = lock()?;
+    migrate::history(&path)?;
+    let text = fs::read_to_string(&path).unwrap_or_default();
+    let matches: Vec<usize> = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| serde_json::from_str::<Entry>(line).is_ok_and(|entry| entry.id().starts_with(id)))
+        .map(|(index, _)| index)
+        .collect();
+    let index = match matches[..] {
+        [index] => index,
+        [] => return Err(format!("no history entry with id `{}`", id).into()),
+        _ => return Err(format!("id `{}` matches {} entries, give more of it", id, matches.len()).into()),
+    };
+
+    let mut annotated = None;
+    let mut lines = Vec::new();
+    for (line_index, line) in text.lines().enumerate() {
+        if line_index != index {
+            lines.push(line.to_string());
+            continue;
+        }
+        let mut entry: Entry = serde_json::from_str(line)?;
+        entry.note = Some(note.trim().to_string()).filter(|note| !note.is_empty());
+        lines.push(serde_json::to_string(&entry)?);
+        annotated = Some(entry);
+    }
+    atomic::write(&path, lines.join("\n") + "\n")?;
+    Ok(annotated.expect("the matched line is rewritten"))
+}
+
+/// The model's output and its correction for the latest `limit` entries that
+/// were corrected in review, newest first
+pub fn corrections(limit: usize) -> Vec<(String, String)> {
+    if limit == 0 {
+        return Vec::new();
+    }
+    entries()
+        .unwrap_or_default()
+        .into_iter()
+        .rev()
+        .filter_map(|entry| Some((entry.original?, entry.latex)))
+        .take(limit)
+        .collect()
+}
+
+/// The latest earlier entry with the same normalized LaTeX as `latex`
+pub fn duplicate_of(latex: &str) -> Option<Entry> {
+    let normalized = normalize::math(latex);
+    if normalized.is_empty() {
+        return None;
+    }
+    entries()
+        .ok()?
+        .into_iter()
+        .rev()
+        .find(|entry| normalize::math(&entry.latex) == normalized)
+}
@@ -0,0 +1,81 @@
+//! The global hotkey `latex_ocr hotkey` waits on.
+
+use std::error::Error;
+
+/// What the hotkey can run, as the subcommands of the same name
+pub const COMMANDS: &[&str] = &["capture", "convert", "snip", "paste"];
+
+/// Checks that `spec` (e.g. "CmdOrCtrl+Shift+L") names a key combination
+#[cfg(feature = "hotkey")]
+pub fn parse(spec: &str) -> Result<(), String> {
+    spec.parse::<global_hotkey::hotkey::HotKey>()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "hotkey"))]
+pub fn parse(_spec: &str) -> Result<(), String> {
+    Ok(())
+}
+
+/// Registers `spec` system-wide and calls `pressed` every time it is pressed,
+/// until the process is stopped. Blocks, and on macOS must run on the main
+/// thread, whose run loop delivers the key events
+#[cfg(feature = "hotkey")]
+pub fn listen(spec: &str, mut pressed: impl FnMut()) -> Result<(), Box<dyn Error>> {
+    use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+
+    let hotkey: global_hotkey::hotkey::HotKey = spec.parse()?;
+    // Keys are grabbed through X11, which fails silently without a display
+    #[cfg(all(unix, not(target_os = "macos")))]
+    if std::env::var_os("DISPLAY").is_none_or(|display| display.is_empty()) {
+        return Err("global hotkeys need an X11 session (Xwayland works), DISPLAY is not set".into());
+    }
+    // Dropping the manager unregisters the hotkey
+    let manager = GlobalHotKeyManager::new()?;
+    manager
+        .register(hotkey)
+        .map_err(|e| format!("cannot register {}, is another app using it? ({})", spec, e))?;
+    let events = GlobalHotKeyEvent::receiver();
+    loop {
+        #[cfg(target_os = "macos")]
+        run_loop::run_for(std::time::Duration::from_millis(100));
+        #[cfg(target_os = "macos")]
+        let event = events.try_recv().ok();
+        #[cfg(not(target_os = "macos"))]
+        let event = Some(events.recv()?);
+
+        if let Some(event) = event
+            && event.id == hotkey.id()
+            && event.state == HotKeyState::Pressed
+        {
+            pressed();
+        }
+    }
+}
+
+#[cfg(not(feature = "hotkey"))]
+pub fn listen(_spec: &str, _pressed: impl FnMut()) -> Result<(), Box<dyn Error>> {
+    Err("latex_ocr was built without the `hotkey` feature".into())
+}
+
+/// The hotkey's Carbon events only arrive while the main run loop runs
+#[cfg(all(feature = "hotkey", target_os = "macos"))]
+mod run_loop {
+    use std::ffi::c_void;
+    use std::time::Duration;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        static kCFRunLoopDefaultMode: *const c_void;
+        fn CFRunLoopRunInMode(mode: *const c_void, seconds: f64, return_after_source_handled: u8) -> i32;
+    }
+
+    /// Handles the events that arrive within `duration`, returning after the first
+    pub fn run_for(duration: Duration) {
+        // SAFETY: the mode is a constant CFString and the loop is the calling thread's
+        unsafe {
+            CFRunLoopRunInMode(kCFRunLoopDefaultMode, duration.as_secs_f64(), 1);
+        }
+    }
+}
@@ -0,0 +1,8 @@
+//! Text placed in the HTML of reports, the history viewer, Anki cards and
+//! the clipboard's HTML flavor.
+
+/// `text` with the characters HTML reads as markup escaped, for element
+/// content and quoted attribute values alike
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,100 @@
+//! Where the image `convert` works on comes from: a path on the command line,
+//! bytes piped in with `convert -`, the clipboard, or else the newest
+//! screenshot in the image directory.
+
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+
+use crate::atomic;
+use crate::clipboard;
+use crate::paths;
+
+/// The argument `convert` takes for reading the image from stdin
+pub const STDIN: &str = "-";
+
+/// An image source, in the order they are tried
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A file named on the command line
+    File(PathBuf),
+    /// Image bytes piped in
+    Stdin,
+    /// The image on the clipboard, falling back to the directory when there is none
+    Clipboard,
+    /// The newest screenshot in the image directory
+    Directory,
+}
+
+impl Source {
+    /// The source `convert` was asked for; `from_clipboard` only matters when
+    /// no image was given explicitly
+    pub fn new(path: Option<&Path>, from_clipboard: bool) -> Self {
+        match path {
+            Some(path) if path == Path::new(STDIN) => Self::Stdin,
+            Some(path) => Self::File(path.to_path_buf()),
+            None if from_clipboard => Self::Clipboard,
+            None => Self::Directory,
+        }
+    }
+
+    /// The image file to convert, if the source names one by itself; `None`
+    /// leaves the choice to the directory scan. Images that don't live in a
+    /// file yet are saved next to the history, which refers to them by path
+    pub fn image(&self) -> Result<Option<PathBuf>, String> {
+        match self {
+            Self::File(path) => Ok(Some(path.clone())),
+            Self::Stdin => save_stdin().map(Some),
+            Self::Clipboard => Ok(save_clipboard()),
+            Self::Directory => Ok(None),
+        }
+    }
+}
+
+/// Everything piped in, saved as the image it is
+fn save_stdin() -> Result<PathBuf, String> {
+    if std::io::stdin().is_terminal() {
+        return Err(format!("`convert {}` reads an image piped in, but stdin is a terminal", STDIN));
+    }
+    let mut data = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to read stdin: {}", e))?;
+    let extension = if data.starts_with(b"\x89PNG") {
+        "png"
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        "jpg"
+    } else if data.is_empty() {
+        return Err("Nothing was piped in on stdin".to_string());
+    } else {
+        return Err("What was piped in on stdin isn't a PNG or JPEG image".to_string());
+    };
+    save("stdin", extension, &data)
+}
+
+/// The clipboard's image, saved; `None` if it holds no image or can't be read
+fn save_clipboard() -> Option<PathBuf> {
+    let data = match clipboard::read_image() {
+        Ok(data) => data?,
+        Err(e) => {
+            eprintln!("Looking in the image directory, the clipboard can't be read: {}", e);
+            return None;
+        }
+    };
+    save("clipboard", "png", &data).map_err(|e| eprintln!("{}", e)).ok()
+}
+
+/// Writes `data` to a new file under the data directory's `kind` folder
+fn save(kind: &str, extension: &str, data: &[u8]) -> Result<PathBuf, String> {
+    let directory = paths::data_dir().join(kind);
+    let path = directory.join(format!(
+        "{}-{}.{}",
+        kind,
+        chrono::Local::now().format("%Y-%m-%d-%H%M%S%.3f"),
+        extension
+    ));
+    std::fs::create_dir_all(&directory)
+        .and_then(|()| atomic::write(&path, data))
+        .map_err(|e| format!("Failed to save the {} image to {}: {}", kind, path.display(), e))?;
+    Ok(path)
+}
@@ -0,0 +1,157 @@
+//! Prose in languages other than English: babel-friendly accents and quotes,
+//! the `otherlanguage` environment around results in a detected language, and
+//! markup for right-to-left scripts.
+
+use serde::Deserialize;
+
+/// What happens to accented letters and typographic quotes in the result
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Accents {
+    /// Leave them as UTF-8, for LuaLaTeX/XeLaTeX or `inputenc`
+    #[default]
+    Keep,
+    /// Accent commands (`\'e`, `\"o`, `\ss{}`) and `\enquote{}` for quotes,
+    /// which work with any engine and let babel and csquotes pick the style
+    Escape,
+    /// Plain ASCII letters (`e`, `o`, `ss`) and `\enquote{}` for quotes
+    Transliterate,
+}
+
+/// Accented letters as `(letter, accent command, base letters)`
+const LETTERS: &[(char, &str, &str)] = &[
+    ('á', "\\'a", "a"), ('à', "\\`a", "a"), ('â', "\\^a", "a"), ('ä', "\\\"a", "a"), ('ã', "\\~a", "a"),
+    ('å', "\\aa{}", "a"), ('æ', "\\ae{}", "ae"), ('ç', "\\c{c}", "c"), ('é', "\\'e", "e"), ('è', "\\`e", "e"),
+    ('ê', "\\^e", "e"), ('ë', "\\\"e", "e"), ('í', "\\'i", "i"), ('ì', "\\`i", "i"), ('î', "\\^i", "i"),
+    ('ï', "\\\"i", "i"), ('ñ', "\\~n", "n"), ('ó', "\\'o", "o"), ('ò', "\\`o", "o"), ('ô', "\\^o", "o"),
+    ('ö', "\\\"o", "o"), ('õ', "\\~o", "o"), ('ø', "\\o{}", "o"), ('œ', "\\oe{}", "oe"), ('ú', "\\'u", "u"),
+    ('ù', "\\`u", "u"), ('û', "\\^u", "u"), ('ü', "\\\"u", "u"), ('ý', "\\'y", "y"), ('ÿ', "\\\"y", "y"),
+    ('ß', "\\ss{}", "ss"), ('ł', "\\l{}", "l"), ('ś', "\\'s", "s"), ('ź', "\\'z", "z"), ('ż', "\\.z", "z"),
+    ('ć', "\\'c", "c"), ('ń', "\\'n", "n"), ('č', "\\v{c}", "c"), ('š', "\\v{s}", "s"), ('ž', "\\v{z}", "z"),
+    ('ř', "\\v{r}", "r"), ('ě', "\\v{e}", "e"), ('ą', "\\k{a}", "a"), ('ę', "\\k{e}", "e"), ('ő', "\\H{o}", "o"),
+    ('ű', "\\H{u}", "u"), ('Á', "\\'A", "A"), ('À', "\\`A", "A"), ('Â', "\\^A", "A"), ('Ä', "\\\"A", "A"),
+    ('Å', "\\AA{}", "A"), ('Æ', "\\AE{}", "AE"), ('Ç', "\\c{C}", "C"), ('É', "\\'E", "E"), ('È', "\\`E", "E"),
+    ('Ê', "\\^E", "E"), ('Í', "\\'I", "I"), ('Ñ', "\\~N", "N"), ('Ó', "\\'O", "O"), ('Ö', "\\\"O", "O"),
+    ('Ø', "\\O{}", "O"), ('Œ', "\\OE{}", "OE"), ('Ú', "\\'U", "U"), ('Ü', "\\\"U", "U"), ('Ł', "\\L{}", "L"),
+    ('Š', "\\v{S}", "S"), ('Ž', "\\v{Z}", "Z"), ('Č', "\\v{C}", "C"),
+];
+
+/// Opening and closing quote marks, in the conventions of different languages
+const QUOTES: &[(char, char)] = &[('„', '“'), ('“', '”'), ('«', '»'), ('»', '«'), ('‚', '‘'), ('‘', '’')];
+
+/// Applies `accents` to `text`
+pub fn apply(text: &str, accents: Accents) -> String {
+    if accents == Accents::Keep {
+        return text.to_string();
+    }
+    let text = enquote(text);
+    let mut converted = String::with_capacity(text.len());
+    // Every command either is a control symbol (`\'e`) or ends in braces
+    // (`\ss{}`), so the letters that follow can't run into it
+    for c in text.chars() {
+        match LETTERS.iter().find(|(letter, _, _)| *letter == c) {
+            Some((_, command, base)) => {
+                converted.push_str(if accents == Accents::Escape { command } else { base });
+            }
+            None => converted.push(c),
+        }
+    }
+    converted
+}
+
+/// Replaces matched pairs of typographic quotes with `\enquote{...}`
+fn enquote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        if let Some((_, close)) = QUOTES.iter().find(|(open, _)| *open == c)
+            && let Some(end) = after.find(*close)
+            && !after[..end].contains('\n')
+        {
+            quoted.push_str(&format!("\\enquote{{{}}}", &after[..end]));
+            rest = &after[end + close.len_utf8()..];
+            continue;
+        }
+        quoted.push(c);
+        rest = after;
+    }
+    quoted
+}
+
+/// The babel name the model reported, if it names a language other than English
+pub fn babel_name(reported: &str) -> Option<String> {
+    let name = reported.trim().trim_matches(|c: char| !c.is_ascii_alphabetic()).to_lowercase();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase()) {
+        return None;
+    }
+    match name.as_str() {
+        "english" | "american" | "british" | "none" => None,
+        _ => Some(name),
+    }
+}
+
+/// `latex` inside babel's `otherlanguage` environment for `language`
+pub fn wrap(latex: &str, language: &str) -> String {
+    format!("\\begin{{otherlanguage}}{{{}}}\n{}\n\\end{{otherlanguage}}", language, latex.trim())
+}
+
+/// How right-to-left prose is marked up, after the package that typesets it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rtl {
+    /// Leave it unmarked
+    #[default]
+    None,
+    /// `\textarabic{...}` and `\texthebrew{...}`
+    Polyglossia,
+    /// `\foreignlanguage{arabic}{...}`
+    Babel,
+    /// `\RL{...}` from the bidi package
+    Bidi,
+}
+
+/// Wraps each run of Arabic or Hebrew prose in the markup of `rtl`
+pub fn mark_rtl(text: &str, rtl: Rtl) -> String {
+    if rtl == Rtl::None {
+        return text.to_string();
+    }
+    let mut marked = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let Some(script) = rtl_script(c) else {
+            marked.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+        // The run ends at the last letter of the same script before anything
+        // that isn't prose, so spaces and punctuation between words stay inside
+        let mut end = 0;
+        for (index, c) in rest.char_indices() {
+            if rtl_script(c) == Some(script) {
+                end = index + c.len_utf8();
+            } else if !(c.is_whitespace() || c.is_numeric() || ".,;:!?'\"()-\u{060c}\u{061f}".contains(c)) {
+                break;
+            }
+        }
+        let run = &rest[..end];
+        match rtl {
+            Rtl::Polyglossia => marked.push_str(&format!("\\text{}{{{}}}", script, run)),
+            Rtl::Babel => marked.push_str(&format!("\\foreignlanguage{{{}}}{{{}}}", script, run)),
+            Rtl::Bidi | Rtl::None => marked.push_str(&format!("\\RL{{{}}}", run)),
+        }
+        rest = &rest[end..];
+    }
+    marked
+}
+
+/// "arabic" or "hebrew" for letters of those scripts
+fn rtl_script(c: char) -> Option<&'static str> {
+    match c {
+        '\u{0590}'..='\u{05FF}' | '\u{FB1D}'..='\u{FB4F}' => Some("hebrew"),
+        '\u{0600}'..='\u{06FF}' | '\u{0750}'..='\u{077F}' | '\u{FB50}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFF}' => {
+            Some("arabic")
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,6 @@
+//! The parts of latex_ocr that are useful without the rest of the tool, for
+//! scripts and other programs that handle LaTeX from a model.
+
+pub mod normalize;
+pub mod postprocess;
+pub mod stitch;
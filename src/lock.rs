@@ -0,0 +1,35 @@
+//! Advisory file locks serializing read-modify-write updates between
+//! concurrently running instances (watch, ad-hoc invocations, batches).
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// Held until dropped; closing the file releases the lock
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Blocks until this process holds the lock stored at `path`
+    pub fn exclusive(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        acquire(&file)?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// `File::lock`, which is flock on Unix and LockFileEx on Windows, tried
+/// again if a signal interrupts the wait
+fn acquire(file: &File) -> io::Result<()> {
+    loop {
+        match file.lock() {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
@@ -1,306 +1,1905 @@
-use tinyfiledialogs;
-use tinyfiledialogs::{MessageBoxIcon, YesNo};
-use std::fs;
-use std::path::Path;
-use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use mac_notification_sys::*;
-use reqwest;
-use serde_json::{json, Value};
-use std::error::Error;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
-use clipboard::{ClipboardContext, ClipboardProvider};
-use config::{Config, ConfigError, File};
-use serde::Deserialize;
-use std::path::PathBuf;
-
-#[derive(Debug, Deserialize)]
-struct AppConfig {
-    api_key: String,
-    image_directory: String,
-    model: String,
-    prompt: String,
-}
-
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            api_key: String::new(),
-            image_directory: "~/Downloads".to_string(),
-            model: "claude-3-5-haiku-20241022".to_string(),
-            prompt: "Convert the following text to latex, if there is any latex. Only output latex code corresponding to the image, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.".to_string(),
-        }
-    }
-}
-
-impl AppConfig {
-    fn load() -> Result<Self, ConfigError> {
-        // Start with default config
-        let mut settings = Config::default();
-        
-        // Add configuration from config file if it exists
-        let config_dir = if let Some(home_dir) = home::home_dir() {
-            let config_dir = home_dir.join(".config").join("latex_ocr");
-            if !config_dir.exists() {
-                let _ = fs::create_dir_all(&config_dir);
-            }
-            config_dir
-        } else {
-            PathBuf::from(".") // Fallback to current directory
-        };
-        
-        let config_path = config_dir.join("config.toml");
-        
-        // If config file doesn't exist, create a default one
-        if !config_path.exists() {
-            let default_config = r#"
-# Anthropic API key (required)
-api_key = ""
-
-# Directory to scan for recent images
-image_directory = "~/Downloads"
-
-# Model to use for image processing
-model = "claude-3-5-haiku-20241022"
-
-# Prompt to send with the image
-prompt = "Convert the following text to latex, if there is any latex. Only output latex code corresponding to the image, don't put anything else in the response. Don't nest in a code block either or preface with the words latex."
-"#;
-            let _ = fs::write(&config_path, default_config);
-        }
-        
-        // Load from config file
-        settings.merge(File::from(config_path))?;
-        
-        // Try to convert the loaded configuration into our AppConfig struct
-        settings.try_deserialize()
+
+use tokio::sync::Semaphore;
+use tokio::task::{JoinSet, LocalSet};
+
+#[cfg(feature = "anki")]
+mod anki;
+mod anthropic;
+mod atomic;
+mod cache;
+mod cancel;
+mod capture;
+mod cli;
+mod clipboard;
+mod context;
+mod dataset;
+mod derivation;
+mod dialog;
+mod dictation;
+mod discovery;
+mod document;
+mod document_class;
+mod escalation;
+mod events;
+mod format;
+mod gemini;
+mod history;
+mod hotkey;
+mod html;
+mod input;
+mod lock;
+mod math_check;
+mod language;
+mod metadata;
+mod migrate;
+#[cfg(test)]
+mod mock;
+mod numbers;
+mod notify;
+#[cfg(feature = "ollama")]
+mod ollama;
+mod openai;
+mod output;
+mod paste;
+mod paths;
+mod pipeline;
+mod preprocess;
+mod progress;
+mod provider;
+mod queue;
+mod recording;
+mod redact;
+mod refine;
+mod report;
+mod response;
+mod resume;
+mod review;
+#[cfg(all(feature = "gui", target_os = "macos"))]
+mod rtf;
+mod session;
+mod target;
+mod settings;
+mod shell;
+mod shrink;
+mod summary;
+mod template;
+mod timing;
+mod trailer;
+mod update;
+mod validate;
+mod viewer;
+mod watcher;
+mod watermark;
+
+use cli::{Args, Command};
+use context::Context;
+use metadata::Hint;
+use notify::Sound;
+use pipeline::{Conversion, Converter, prepare_image, read_image};
+use progress::{Outcome, Progress};
+use resume::Checkpoint;
+use settings::{AppConfig, Profile};
+use timing::Timing;
+
+/// Builds the HTTP client; TLS setup is the slow part, so this runs off the startup path.
+/// Only connecting has a deadline: a streamed reply can take longer than any fixed
+/// one, so `provider` times out waiting for it to start or stalling instead
+fn build_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    if args.debug {
+        response::enable_debug();
     }
-    
-    fn image_directory_expanded(&self) -> String {
-        shellexpand::tilde(&self.image_directory).to_string()
-    }
-}
-
-/// Sends an image to Claude API for analysis
-/// 
-/// # Arguments
-/// * `api_key` - Anthropic API key
-/// * `model` - Model to use (e.g., "claude-3-5-haiku-20241022")
-/// * `image_data` - Raw bytes of the image file
-/// * `image_path` - Path to the image file
-/// * `prompt` - Text prompt to send with the image
-/// 
-/// # Returns
-/// Result containing the API response text or an error
-async fn call_claude_with_image(
-    api_key: &str,
-    model: &str,
-    image_data: &[u8],
-    image_path: &str,
-    prompt: &str
-) -> Result<String, Box<dyn Error>> {
-    // Convert image to base64
-    let base64_image = BASE64.encode(image_data);
-    
-    // Determine media type based on file extension
-    let media_type = if let Some(ext) = Path::new(image_path).extension() {
-        match ext.to_string_lossy().to_lowercase().as_str() {
-            "png" => "image/png",
-            "jpg" | "jpeg" => "image/jpeg",
-            _ => "image/jpeg",  // Default to JPEG
+    if args.no_cache {
+        cache::disable();
+    }
+    if let Some(format) = args.log_format {
+        events::init(format);
+    }
+    events::emit("start", serde_json::json!({ "command": args.command.name() }));
+
+    match args.command.clone() {
+        Command::Convert { .. } => {
+            let mut timing = Timing::new(args.timing);
+            run(&mut timing, &args).await;
+            timing.report();
+        }
+        Command::ConvertMany { paths, since, separate } => run_convert_many(&args, &paths, since, separate).await,
+        Command::Capture { multi } => run_capture(&args, multi).await,
+        Command::Snip => {
+            // A snip is a single capture through the built-in `snip` profile unless one was picked
+            let profile = args.profile.clone().or_else(|| Some("snip".to_string()));
+            run_capture(&Args { profile, ..args }, false).await
+        }
+        Command::Paste => run_paste(&args).await,
+        Command::Hotkey => run_hotkey(&args),
+        Command::Watch { daemon: true } => detach_watch(),
+        Command::Watch { daemon: false } => run_watch(&args).await,
+        Command::Dictate => run_dictate(&args).await,
+        Command::Batch { inputs, document, compile, report, only_new, resume } => {
+            run_batch(&args, &inputs, document.as_deref(), compile, report.as_deref(), only_new, resume).await
+        }
+        Command::Recording { path, every, document, compile } => {
+            run_recording(&args, &path, every, document.as_deref(), compile).await
+        }
+        Command::Derivation { path } => run_derivation(&args, &path).await,
+        Command::HistoryList { limit } => list_history(limit),
+        Command::HistoryShow { number } => show_history(&number),
+        Command::HistoryCopy { number } => copy_from_history(&number),
+        Command::HistoryFind { query } => find_in_history(&query),
+        Command::HistoryAnnotate { id, note } => annotate_history(&id, &note),
+        Command::HistoryExport { anki, query } => export_history(&anki, query.as_deref()),
+        Command::HistoryDataset { path, query } => export_dataset(&path, query.as_deref()),
+        Command::SessionStart { name } => start_session(name.as_deref()),
+        Command::SessionStop { output } => stop_session(output.as_deref()),
+        Command::Config { edit } => show_config(edit),
+        Command::SelfUpdate { check_only } => self_update(check_only).await,
+        Command::Cancel => cancel_in_flight(),
+    }
+}
+
+/// Handles `latex_ocr self-update`
+async fn self_update(check_only: bool) {
+    match update::run(check_only).await {
+        Ok(update::Outcome::UpToDate(latest)) => {
+            eprintln!("latex_ocr {} is up to date (latest release {})", env!("CARGO_PKG_VERSION"), latest)
+        }
+        Ok(update::Outcome::Available(latest)) => {
+            eprintln!("latex_ocr {} is available, run `latex_ocr self-update`", latest)
+        }
+        Ok(update::Outcome::Updated(latest)) => eprintln!("Updated to latex_ocr {}", latest),
+        Err(e) => {
+            eprintln!("Self-update failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles `latex_ocr config`: prints the config file's path, or opens it in
+/// the editor with `config edit`
+fn show_config(edit: bool) {
+    let path = AppConfig::path();
+    if !edit {
+        println!("{}", path.display());
+        return;
+    }
+    // Loading writes the commented default file when there is none yet
+    let review_command = match AppConfig::load() {
+        Ok(config) => config.review_command,
+        Err(e) => {
+            eprintln!("Error loading configuration, editing it anyway: {}", e);
+            String::new()
         }
-    } else {
-        "image/jpeg"  // Default to JPEG if no extension
     };
-    
-    // Create the API request payload
-    let payload = json!({
-        "model": model,
-        "max_tokens": 1024,
-        "messages": [
-            {
-                "role": "user",
-                "content": [
-                    {
-                        "type": "image",
-                        "source": {
-                            "type": "base64",
-                            "media_type": media_type,
-                            "data": base64_image
-                        }
-                    },
-                    {
-                        "type": "text",
-                        "text": prompt
-                    }
-                ]
-            }
-        ]
-    });
-    
-    // Send the request to Anthropic API
-    let client = reqwest::Client::new();
-    let response = client.post("https://api.anthropic.com/v1/messages")
-        .header("x-api-key", api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("content-type", "application/json")
-        .json(&payload)
-        .timeout(Duration::from_secs(30))
-        .send()
-        .await?;
-    
-    // Process the response
-    if response.status().is_success() {
-        let response_json: Value = response.json().await?;
-        // Extract the content from the response
-        if let Some(content) = response_json["content"].as_array() {
-            let mut result = String::new();
-            for item in content {
-                if let Some(text) = item["text"].as_str() {
-                    result.push_str(text);
-                }
-            }
-            Ok(result)
-        } else {
-            Err("Invalid response format".into())
+    let (template, _) = review::editor(&review_command);
+    let (mut editor, command) = shell::run_template(&template, "file", &path);
+    match editor.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            eprintln!("`{}` exited with {}", command, status);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to open the config file: {}", e);
+            std::process::exit(1);
         }
-    } else {
-        Err(format!("API request failed with status: {}", response.status()).into())
     }
 }
 
-/// Copy text to clipboard
-fn copy_to_clipboard(text: &str) -> Result<(), Box<dyn Error>> {
-    let mut ctx: ClipboardContext = ClipboardProvider::new()?;
-    ctx.set_contents(text.to_owned())?;
-    Ok(())
+/// Handles `latex_ocr cancel`
+fn cancel_in_flight() {
+    match cancel::request() {
+        Ok(Some(pid)) => eprintln!("Cancelled conversion in process {}", pid),
+        Ok(None) => eprintln!("No conversion in progress"),
+        Err(e) => {
+            eprintln!("Failed to cancel conversion: {}", e);
+            std::process::exit(1);
+        }
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    // Load configuration
-    let config = match AppConfig::load() {
+/// Handles `latex_ocr history list`
+fn list_history(limit: usize) {
+    let entries = match history::entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read the history: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if entries.is_empty() {
+        eprintln!("No conversions yet");
+        return;
+    }
+    for (index, entry) in entries.iter().rev().take(limit).enumerate() {
+        let first_line = entry.latex.lines().next().unwrap_or_default();
+        let first_line: String = first_line.chars().take(60).collect();
+        println!("{:>3}  {}  {}  {}", index + 1, entry.id(), entry.when(), first_line);
+    }
+}
+
+/// Looks `number` up for `history show` and `history copy`, exiting when it isn't there
+fn history_entry(number: &str) -> history::Entry {
+    history::get(number).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Handles `latex_ocr history show`
+fn show_history(number: &str) {
+    let entry = history_entry(number);
+    println!("id:      {}", entry.id());
+    println!("time:    {}", entry.when());
+    if !entry.image.is_empty() {
+        println!("image:   {}", entry.image);
+    }
+    if let Some(hash) = &entry.image_hash {
+        println!("sha256:  {}", hash);
+    }
+    if let Some(model) = &entry.model {
+        println!("model:   {}", model);
+    }
+    if let Some(usage) = &entry.usage {
+        println!("tokens:  {} in, {} out", usage.input_tokens, usage.output_tokens);
+    }
+    if let Some(source) = &entry.source {
+        println!("source:  {}", source);
+    }
+    if let Some(note) = &entry.note {
+        println!("note:    {}", note);
+    }
+    if let Some(prompt) = &entry.prompt {
+        println!("prompt:");
+        for line in prompt.lines() {
+            println!("    {}", line);
+        }
+    }
+    println!();
+    println!("{}", entry.latex);
+}
+
+/// Handles `latex_ocr history copy`: the LaTeX as it was recorded, with the
+/// configured clipboard options
+fn copy_from_history(number: &str) {
+    let entry = history_entry(number);
+    let options = match AppConfig::load() {
+        Ok(config) => config.clipboard_options(),
+        Err(_) => clipboard::Options { rtf: false, split_equations: false, event_delay: Duration::ZERO },
+    };
+    match clipboard::copy_with(&entry.latex, None, &options) {
+        Ok(()) => eprintln!("Copied {} from {} to the {}", entry.id(), entry.when(), clipboard::DESTINATION),
+        Err(e) => {
+            eprintln!("Failed to copy: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles `latex_ocr history find`
+fn find_in_history(query: &str) {
+    let found = match history::find(query) {
+        Ok(found) => found,
+        Err(e) => {
+            eprintln!("Failed to read the history: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if found.is_empty() {
+        eprintln!("No conversion matches `{}`", query);
+        std::process::exit(1);
+    }
+    for entry in found {
+        let image = std::path::Path::new(&entry.image)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        println!("{}  {}  {}", entry.id(), entry.when(), image);
+        for line in entry.latex.lines() {
+            println!("    {}", line);
+        }
+        if let Some(note) = &entry.note {
+            println!("    note: {}", note);
+        }
+        println!();
+    }
+}
+
+/// Handles `latex_ocr history annotate`
+fn annotate_history(id: &str, note: &str) {
+    match history::annotate(id, note) {
+        Ok(entry) if entry.note.is_some() => eprintln!("Annotated {} from {}", entry.id(), entry.when()),
+        Ok(entry) => eprintln!("Removed the note from {} from {}", entry.id(), entry.when()),
+        Err(e) => {
+            eprintln!("Failed to annotate: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Every history entry, or those `history find` would list for `query`,
+/// oldest first; exits when the history can't be read
+fn exported_entries(query: Option<&str>) -> Vec<history::Entry> {
+    let entries = match query {
+        Some(query) => history::find(query).map(|mut found| {
+            found.reverse();
+            found
+        }),
+        None => history::entries(),
+    };
+    entries.unwrap_or_else(|e| {
+        eprintln!("Failed to read the history: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Handles `latex_ocr history export --anki`
+#[cfg(feature = "anki")]
+fn export_history(path: &str, query: Option<&str>) {
+    let entries = exported_entries(query);
+    let path = Path::new(path);
+    let deck = path.file_stem().map_or("latex_ocr".into(), |stem| stem.to_string_lossy());
+    match anki::export(&entries, &deck, path) {
+        Ok(0) => {
+            eprintln!("No entries to export");
+            std::process::exit(1);
+        }
+        Ok(cards) => eprintln!("Wrote {} cards to {}", cards, path.display()),
+        Err(e) => {
+            eprintln!("Failed to export {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles `latex_ocr history export --dataset`
+fn export_dataset(path: &str, query: Option<&str>) {
+    let entries = exported_entries(query);
+    let path = Path::new(path);
+    match dataset::export(&entries, path) {
+        Ok((0, 0)) => {
+            eprintln!("No corrected entries to export; results fixed in review are recorded as corrections");
+            std::process::exit(1);
+        }
+        Ok((written, 0)) => eprintln!("Wrote {} examples to {}", written, path.display()),
+        Ok((written, missing)) => eprintln!(
+            "Wrote {} examples to {}, left out {} whose image is gone",
+            written,
+            path.display(),
+            missing
+        ),
+        Err(e) => {
+            eprintln!("Failed to export {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "anki"))]
+fn export_history(_path: &str, _query: Option<&str>) {
+    eprintln!("history export --anki needs a build with the `anki` feature");
+    std::process::exit(1);
+}
+
+/// Handles `latex_ocr session start`
+fn start_session(name: Option<&str>) {
+    match session::start(name) {
+        Ok(session) => eprintln!("Session `{}` started, `latex_ocr session stop` assembles it", session.name()),
+        Err(e) => {
+            eprintln!("Failed to start session: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles `latex_ocr session stop`
+fn stop_session(output: Option<&str>) {
+    let Some(session) = session::active() else {
+        eprintln!("No session is running");
+        std::process::exit(1);
+    };
+    let preamble = AppConfig::load()
+        .map(|config| config.document_preamble)
+        .unwrap_or_else(|_| AppConfig::default().document_preamble);
+    let captures = session.entries().len();
+    match session.finish(&preamble, output.map(Path::new)) {
+        Ok(path) => {
+            eprintln!("Wrote {} captures to {}", captures, path.display());
+            notify::send("Session Complete", &format!("Wrote {}", path.display()), Sound::Success);
+        }
+        Err(e) => {
+            eprintln!("Failed to write the session: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Lets the user edit results below `review_below` confidence, or all of them
+/// with `confirm_result`; ones they discard are dropped, and results nobody
+/// needs to look at pass through
+fn review_doubtful(config: &AppConfig, conversions: &[Conversion], label: &str) -> Vec<Conversion> {
+    if config.review_below == 0 && !config.confirm_result {
+        return conversions.to_vec();
+    }
+    let mut kept = Vec::new();
+    for conversion in conversions {
+        // A missing confidence is as doubtful as a low one
+        let doubtful = config.review_below > 0
+            && conversion.confidence.is_none_or(|confidence| confidence < config.review_below);
+        if !doubtful && !config.confirm_result {
+            kept.push(conversion.clone());
+            continue;
+        }
+        events::emit(
+            "review",
+            serde_json::json!({ "image": conversion.image, "confidence": conversion.confidence }),
+        );
+        let reviewed = if doubtful {
+            review::edit(config, &conversion.latex, conversion.confidence)
+        } else {
+            review::confirm(config, &conversion.latex)
+        };
+        match reviewed {
+            Ok(Some(latex)) if latex.trim() != conversion.latex.trim() => {
+                let original = Some(conversion.latex.clone());
+                kept.push(Conversion { latex, original, ..conversion.clone() })
+            }
+            Ok(Some(_)) => kept.push(conversion.clone()),
+            Ok(None) => notify::send(
+                &format!("Result discarded{}", label),
+                "Clipboard left unchanged",
+                Sound::Failure,
+            ),
+            Err(e) => {
+                // Better an unreviewed result than none
+                eprintln!("Review failed, delivering the result as is: {}", e);
+                kept.push(conversion.clone());
+            }
+        }
+    }
+    kept
+}
+
+/// Loads the configuration, with `--directory` in place of the configured one
+/// and `--no-confirm` turning `confirm_result` off, and checks the API key,
+/// notifying about problems
+fn load_config(args: &Args) -> Option<AppConfig> {
+    let mut config = match AppConfig::load() {
         Ok(cfg) => cfg,
         Err(e) => {
             eprintln!("Error loading configuration: {}", e);
-            send_notification(
+            notify::send(
                 "Configuration Error",
-                None,
                 &format!("Error loading configuration: {}", e),
-                Some(Notification::new().sound("Blow")),
-            ).unwrap();
-            return;
+                Sound::Failure,
+            );
+            return None;
         }
     };
-    
+
     // Check if API key is provided
-    if config.api_key.trim().is_empty() {
-        eprintln!("API key is empty. Please set it in ~/.config/latex_ocr/config.toml");
-        send_notification(
+    if let Some((key_name, key)) = config.provider_key()
+        && key.trim().is_empty()
+    {
+        eprintln!("{} is empty. Please set it in ~/.config/latex_ocr/config.toml", key_name);
+        notify::send(
             "Configuration Error",
-            None,
-            "API key is not set. Please add it to the configuration file.",
-            Some(Notification::new().sound("Blow")),
-        ).unwrap();
+            &format!("{} is not set. Please add it to the configuration file.", key_name),
+            Sound::Failure,
+        );
+        return None;
+    }
+    if let Some(directory) = &args.directory {
+        config.image_directory = directory.clone();
+    }
+    if args.no_confirm {
+        config.confirm_result = false;
+    }
+    provider::set_overloaded_retries(config.overloaded_retries);
+    provider::set_max_reply_bytes(config.max_reply_bytes);
+    provider::set_client_tag(&config.client_tag);
+    escalation::configure(&config);
+    redact::configure(&config);
+    watermark::configure(&config);
+    shrink::configure(&config);
+    Some(config)
+}
+
+/// The profile picked with `--profile`, by the frontmost app in `app_profiles`
+/// or else the empty default one, with the prompt and model of the `--mode`
+/// applied on top, and those of `--prompt` and `--model` over both
+fn resolve_profile(config: &AppConfig, args: &Args) -> Option<Profile> {
+    let mut profile = match &args.profile {
+        Some(name) => match config.profile(name) {
+            Some(profile) => profile,
+            None => {
+                eprintln!("Unknown profile `{}`", name);
+                notify::send(
+                    "Configuration Error",
+                    &format!("No profile named `{}` in the configuration", name),
+                    Sound::Failure,
+                );
+                return None;
+            }
+        },
+        None if !config.app_profiles.is_empty() => {
+            let frontmost = metadata::frontmost();
+            match config.app_profile(&frontmost) {
+                Some(profile) => {
+                    events::emit(
+                        "app_profile",
+                        serde_json::json!({ "app": frontmost.app, "window": frontmost.window, "profile": profile.name }),
+                    );
+                    profile
+                }
+                None => Profile::default(),
+            }
+        }
+        None => Profile::default(),
+    };
+    if let Some(name) = &args.mode {
+        let Some(mode) = config.mode(name) else {
+            eprintln!("Unknown mode `{}`", name);
+            notify::send(
+                "Configuration Error",
+                &format!("No mode named `{}` in the configuration", name),
+                Sound::Failure,
+            );
+            return None;
+        };
+        profile.prompt = mode.prompt.or(profile.prompt);
+        profile.model = mode.model.or(profile.model);
+        profile.glossary = mode.glossary.or(profile.glossary);
+        profile.check = mode.check.or(profile.check);
+        profile.validate = mode.validate.or(profile.validate);
+        profile.mode = Some(name.clone());
+    }
+    if let Some(target) = args.target {
+        profile.clipboard_target = Some(target);
+    }
+    if let Some(format) = args.output_format {
+        profile.format = Some(format);
+    }
+    if args.preserve_layout {
+        profile.preserve_layout = Some(true);
+    }
+    if let Some(prompt) = &args.prompt {
+        profile.prompt = Some(prompt.clone());
+    }
+    if let Some(model) = &args.model {
+        profile.model = Some(model.clone());
+    }
+    Some(profile)
+}
+
+/// Waits for the HTTP client built in the background, notifying if that failed
+async fn await_client(
+    client: tokio::task::JoinHandle<reqwest::Result<reqwest::Client>>,
+) -> Option<reqwest::Client> {
+    match client.await.expect("client setup task failed") {
+        Ok(client) => Some(client),
+        Err(e) => {
+            notify::send(
+                "API Call Failed",
+                &format!("Failed to set up HTTP client: {}", e),
+                Sound::Failure,
+            );
+            None
+        }
+    }
+}
+
+/// Sends the finished results to the configured outputs and reports how that went
+async fn deliver(
+    client: &reqwest::Client,
+    config: &AppConfig,
+    profile: &Profile,
+    conversions: &[Conversion],
+    label: &str,
+) {
+    let conversions = &review_doubtful(config, conversions, label)[..];
+    if conversions.is_empty() {
+        return;
+    }
+
+    // Catch results converted before, e.g. so the same flashcard isn't made twice
+    let mut fresh = Vec::new();
+    for conversion in conversions {
+        let Some(earlier) = history::duplicate_of(&conversion.latex) else {
+            fresh.push(conversion.clone());
+            continue;
+        };
+        let skipped = if config.skip_duplicates { ", not copied" } else { "" };
+        notify::send(
+            &format!("Duplicate Result{}", label),
+            &format!("Identical to conversion from {}{}", earlier.day(), skipped),
+            Sound::Failure,
+        );
+        events::emit(
+            "duplicate",
+            serde_json::json!({ "image": conversion.image, "earlier": earlier.time, "skipped": config.skip_duplicates }),
+        );
+        if !config.skip_duplicates {
+            fresh.push(conversion.clone());
+        }
+    }
+    if fresh.is_empty() {
         return;
     }
+    let conversions = &fresh[..];
+    if config.compile_check && config.format(profile).is_latex() {
+        check_compiles(config, conversions, label);
+    }
+
+    let (delivered, failed) = output::deliver(client, config, profile, conversions).await;
+    for (destination, e) in &failed {
+        events::emit(
+            "delivery_failed",
+            serde_json::json!({ "destination": destination, "error": e.to_string() }),
+        );
+        notify::send(
+            &format!("Error{}", label),
+            &format!("Failed to send to {}: {}", destination, e),
+            Sound::Failure,
+        );
+    }
+    if !delivered.is_empty() {
+        events::emit(
+            "delivered",
+            serde_json::json!({ "destinations": delivered, "results": conversions.len() }),
+        );
+        context::remember(conversions);
+        let entries = history::record(conversions).unwrap_or_else(|e| {
+            eprintln!("Failed to add the result to the history: {}", e);
+            Vec::new()
+        });
+        let title = format!("LaTeX Conversion Complete{}", label);
+        let latex: Vec<&str> = conversions.iter().map(|conversion| conversion.latex.as_str()).collect();
+        let message = format!(
+            "LaTeX has been copied to {}\n{}",
+            delivered.join(", "),
+            summary::describe(&latex.join("\n"))
+        );
+        if config.notification_links && !entries.is_empty() {
+            if notify::send_clickable(&title, &message, Sound::Success)
+                && let Err(e) = viewer::open(&entries)
+            {
+                eprintln!("Failed to open the history entry: {}", e);
+            }
+        } else {
+            notify::send(&title, &message, Sound::Success);
+        }
+    }
+}
+
+/// Warns about results that don't compile on their own, before they are copied
+fn check_compiles(config: &AppConfig, conversions: &[Conversion], label: &str) {
+    for conversion in conversions {
+        match document::check(&config.latex_command, &config.document_preamble, &conversion.latex) {
+            Ok(None) => {}
+            Ok(Some(error)) => {
+                events::emit("compile_failed", serde_json::json!({ "image": conversion.image, "error": error }));
+                notify::send(
+                    &format!("Result Doesn't Compile{}", label),
+                    &format!("{} (delivered anyway)", error),
+                    Sound::Failure,
+                );
+            }
+            Err(e) => eprintln!("Could not check that the result compiles: {}", e),
+        }
+    }
+}
+
+async fn run(timing: &mut Timing, args: &Args) {
+    // Queue behind any conversion that is already running
+    let ticket = queue::Ticket::take().ok();
+
+    // Kick off the slow, independent setup work so it overlaps with config and discovery
+    let client = tokio::task::spawn_blocking(build_client);
+    let notifications_ready = tokio::task::spawn_blocking(notify::warm_up);
+
+    // Load configuration
+    let Some(config) = load_config(args) else {
+        return;
+    };
+    let Some(profile) = resolve_profile(&config, args) else {
+        return;
+    };
+    timing.mark("config");
+
+    // Ask for the frontmost app now, before our own dialogs take focus
+    let origin = config
+        .metadata_hints
+        .then(|| tokio::task::spawn_blocking(metadata::frontmost));
     
     // Get the image directory
     let expanded_path = config.image_directory_expanded();
-    
-    // Find the most recent image file
-    let most_recent_image = fs::read_dir(&expanded_path)
-        .expect("Failed to read directory")
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            if let Some(ext) = entry.path().extension() {
-                let ext = ext.to_string_lossy().to_lowercase();
-                ext == "png" || ext == "jpg" || ext == "jpeg"
-            } else {
-                false
-            }
-        })
-        .max_by_key(|entry| entry.metadata().unwrap().modified().unwrap());
 
-    // Process the image if found
-    if let Some(image_entry) = most_recent_image {
-        let image_path = image_entry.path();
-        match fs::read(&image_path) {
-            Ok(image_data) => {
-                // Convert image path to string for the dialog
-                let image_path_str = image_path.to_string_lossy().to_string();
-                
-                let choice = tinyfiledialogs::message_box_yes_no(
-                    "Confirm Image Processing", 
-                    &image_path_str, 
-                    MessageBoxIcon::Question, 
-                    YesNo::No
-                );
-                
-                if choice == YesNo::No {
-                    send_notification(
-                        "Cancelled request",
-                        None,
-                        "Images untouched",
-                        Some(Notification::new().sound("Blow")),
-                    )
-                    .unwrap();
+    // A named or piped image goes first, then one on the clipboard; the directory is the fallback
+    let path = match &args.command {
+        Command::Convert { path } => path.as_deref(),
+        _ => None,
+    };
+    let source = input::Source::new(path, args.from_clipboard || config.from_clipboard);
+    let given = source.clone();
+    let given_image = match tokio::task::spawn_blocking(move || given.image())
+        .await
+        .expect("image input task failed")
+    {
+        Ok(image) => image,
+        Err(e) => {
+            notify::send("Failed to read image", &e, Sound::Failure);
+            return;
+        }
+    };
+
+    // Find the most recent image file, unless one was given
+    let directory = expanded_path.clone();
+    let selection = config.selection;
+    let count = config.pick_recent.max(1);
+    let recent_images = match &given_image {
+        Some(_) => Vec::new(),
+        None => tokio::task::spawn_blocking(move || discovery::newest_images(&directory, selection, count))
+            .await
+            .expect("image discovery task failed"),
+    };
+    let most_recent_image = recent_images.first().cloned();
+    timing.mark("discovery");
+    let fresh = most_recent_image
+        .as_ref()
+        .is_some_and(|candidate| candidate.age() < metadata::FRESH);
+
+    // Picking one of several images is confirmation enough, and so is piping
+    // one in, which also leaves no stdin to answer a terminal prompt with
+    let mut picked = source == input::Source::Stdin;
+    // Refuse to auto-select a stale image, the newest file is probably an unrelated download
+    let most_recent_image = match (most_recent_image, config.max_age()) {
+        _ if given_image.is_some() => given_image,
+        (Some(candidate), Some(max_age)) if candidate.age() > max_age => {
+            let title = format!(
+                "Newest image is {} old, pick one",
+                settings::format_duration(candidate.age())
+            );
+            match dialog::pick_image(&title, &expanded_path) {
+                Some(path) => Some(path),
+                None => {
+                    notify::send("Cancelled request", "No image selected", Sound::Failure);
                     return;
                 }
-                
-                // Continue with image processing
-                match call_claude_with_image(
-                    &config.api_key,
-                    &config.model,
-                    &image_data,
-                    &image_path_str,
-                    &config.prompt
-                ).await {
-                    Ok(latex_result) => {
-                        // Copy result to clipboard
-                        if let Err(e) = copy_to_clipboard(&latex_result) {
-                            send_notification(
-                                "Error",
-                                None,
-                                &format!("Failed to copy to clipboard: {}", e),
-                                Some(Notification::new().sound("Blow")),
-                            ).unwrap();
-                        } else {
-                            send_notification(
-                                "LaTeX Conversion Complete",
-                                None,
-                                "LaTeX has been copied to clipboard",
-                                Some(Notification::new().sound("Glass")),
-                            ).unwrap();
-                        }
-                    },
-                    Err(e) => {
-                        send_notification(
-                            "API Call Failed",
-                            None,
-                            &format!("Error calling Claude API: {}", e),
-                            Some(Notification::new().sound("Blow")),
-                        ).unwrap();
-                    }
-                }
             }
-            Err(e) => {
-                send_notification(
-                    "Failed to read image",
-                    None,
-                    &e.to_string(),
-                    Some(Notification::new().sound("Blow")),
-                ).unwrap();
-                return;
+        }
+        (Some(_), _) if recent_images.len() > 1 && !args.no_confirm => {
+            let choices: Vec<String> = recent_images
+                .iter()
+                .map(|candidate| {
+                    let name = candidate.path.file_name().unwrap_or_default().to_string_lossy();
+                    format!("{} ({} ago)", name, settings::format_duration(candidate.age()))
+                })
+                .collect();
+            match dialog::choose(&format!("Convert which of the {} newest images?", choices.len()), &choices) {
+                Some(index) => {
+                    picked = true;
+                    Some(recent_images[index].path.clone())
+                }
+                None => {
+                    notify::send("Cancelled request", "No image selected", Sound::Failure);
+                    return;
+                }
             }
         }
-    } else {
-        send_notification(
+        (candidate, _) => candidate.map(|candidate| candidate.path),
+    };
+
+    // Process the image if found
+    let Some(image_path) = most_recent_image else {
+        notify::send(
             "No images found",
-            None,
             &format!("No images found in directory: {}", expanded_path),
-            Some(Notification::new().sound("Blow")),
-        )
-        .unwrap();
+            Sound::Failure,
+        );
+        return;
+    };
+
+    events::emit("image_selected", serde_json::json!({ "image": image_path, "fresh": fresh }));
+
+    // Read (and preprocess) the image while the confirmation dialog is up
+    let read_path = image_path.clone();
+    let stage = profile.preprocess.clone();
+    let image_data = tokio::spawn(async move { read_image(&read_path, stage.as_deref()).await });
+
+    // Convert image path to string for the dialog
+    let image_path_str = image_path.to_string_lossy().to_string();
+
+    // Wait for earlier invocations to finish before asking
+    let label = match &ticket {
+        Some(ticket) => {
+            let waiting = ticket.position();
+            if waiting.index > 1 {
+                notify::send(
+                    &format!("Conversion queued{}", waiting.label()),
+                    &image_path_str,
+                    Sound::Success,
+                );
+            }
+            ticket.wait_turn().await.label()
+        }
+        None => String::new(),
+    };
+    timing.mark("queue");
+
+    timing.dialog_shown();
+    let confirmed = args.no_confirm
+        || picked
+        || dialog::confirm(
+            &format!("Confirm Image Processing{}", label),
+            &image_path_str,
+        );
+    timing.mark("dialog");
+
+    if !confirmed {
+        notify::send("Cancelled request", "Images untouched", Sound::Failure);
+        return;
+    }
+
+    let image_data = match image_data.await.expect("image read task failed") {
+        Ok(data) => data,
+        Err(e) => {
+            notify::send("Failed to read image", &e, Sound::Failure);
+            return;
+        }
+    };
+
+    let Some(client) = await_client(client).await else {
         return;
+    };
+    let _ = notifications_ready.await;
+    timing.mark("setup wait");
+
+    // Spend a tiny request before the real one when the image may not be math at all
+    if config.math_check {
+        match math_check::looks_like_math(
+            &client,
+            &*config.provider(),
+            &config.math_check_model,
+            profile.check.as_deref().unwrap_or(math_check::PROMPT),
+            &image_data,
+            &image_path_str,
+        ).await {
+            Ok(true) => {}
+            Ok(false) => {
+                let convert_anyway = dialog::confirm(
+                    &format!("This doesn't look like {}", profile.mode.as_deref().unwrap_or("math")),
+                    &format!("{}\n\nConvert it anyway?", image_path_str),
+                );
+                if !convert_anyway {
+                    notify::send("Cancelled request", "Images untouched", Sound::Failure);
+                    return;
+                }
+            }
+            Err(e) => eprintln!("Math check failed, converting anyway: {}", e),
+        }
+        timing.mark("math check");
+    }
+
+    // Only a fresh screenshot was taken from the app that is still in front
+    let hint = match origin {
+        Some(origin) => {
+            let origin = origin.await.unwrap_or_default();
+            let origin = if fresh { origin } else { Hint::default() };
+            Some(origin.with_file(&image_path))
+        }
+        None => None,
+    };
+
+    // Continue with image processing
+    let context = Context::gather(args, &config).text(hint.as_ref());
+    let converter = Converter {
+        client: &client,
+        config: &config,
+        profile: &profile,
+        context: context.as_deref(),
+    };
+    let result = converter.convert(&image_data, &image_path_str).await;
+    timing.mark("api call");
+
+    match result {
+        Some(Ok(conversion)) => {
+            deliver(&client, &config, &profile, std::slice::from_ref(&conversion), &label).await;
+            refine(&converter, &image_data, &image_path_str, &conversion, &label).await;
+        }
+        Some(Err(e)) => {
+            escalation::failed(
+                &format!("API Call Failed{}", label),
+                &format!("Error calling Claude API: {}", e),
+            );
+        }
+        None => {
+            notify::send(
+                &format!("Conversion cancelled{}", label),
+                "Clipboard left unchanged",
+                Sound::Failure,
+            );
+        }
+    }
+}
+
+/// Handles `latex_ocr capture`: select screen regions, convert each one and
+/// copy the results in selection order
+async fn run_capture(args: &Args, multi: bool) {
+    let client = tokio::task::spawn_blocking(build_client);
+    let notifications_ready = tokio::task::spawn_blocking(notify::warm_up);
+
+    let Some(config) = load_config(args) else {
+        return;
+    };
+    let Some(profile) = resolve_profile(&config, args) else {
+        return;
+    };
+
+    // The app being captured from, asked before the selection overlay takes focus
+    let hint = config.metadata_hints.then(|| Hint {
+        capture_type: Some("selection".to_string()),
+        ..metadata::frontmost()
+    });
+
+    // Keep selecting until the user cancels a selection (or after one, without --multi)
+    let mut regions = capture::Regions::default();
+    loop {
+        match regions.select_next(&config.capture_command) {
+            Ok(true) if multi => continue,
+            Ok(_) => break,
+            Err(e) => {
+                notify::send("Capture Failed", &e.to_string(), Sound::Failure);
+                return;
+            }
+        }
+    }
+    let regions = regions.paths();
+    if regions.is_empty() {
+        notify::send("Cancelled request", "No region selected", Sound::Failure);
+        return;
+    }
+
+    let ticket = queue::Ticket::take().ok();
+    if let Some(ticket) = &ticket {
+        ticket.wait_turn().await;
+    }
+    let Some(client) = await_client(client).await else {
+        return;
+    };
+    let _ = notifications_ready.await;
+    let context = Context::gather(args, &config).text(hint.as_ref());
+    let converter = Converter {
+        client: &client,
+        config: &config,
+        profile: &profile,
+        context: context.as_deref(),
+    };
+
+    let mut results = Vec::new();
+    for (index, region) in regions.iter().enumerate() {
+        let region_label = if regions.len() > 1 {
+            format!(" (region {} of {})", index + 1, regions.len())
+        } else {
+            String::new()
+        };
+        let image_data = match read_image(region, profile.preprocess.as_deref()).await {
+            Ok(data) => data,
+            Err(e) => {
+                notify::send(&format!("Failed to read image{}", region_label), &e, Sound::Failure);
+                return;
+            }
+        };
+        let region_str = region.to_string_lossy().to_string();
+        match converter.convert(&image_data, &region_str).await {
+            Some(Ok(conversion)) => results.push(conversion),
+            Some(Err(e)) => {
+                escalation::failed(
+                    &format!("API Call Failed{}", region_label),
+                    &format!("Error calling Claude API: {}", e),
+                );
+                return;
+            }
+            None => {
+                notify::send("Conversion cancelled", "Clipboard left unchanged", Sound::Failure);
+                return;
+            }
+        }
+    }
+
+    deliver(&client, &config, &profile, &results, "").await;
+}
+
+/// Handles `latex_ocr paste`: converts the image on the clipboard and pastes
+/// the LaTeX straight into the frontmost app
+async fn run_paste(args: &Args) {
+    let client = tokio::task::spawn_blocking(build_client);
+    let notifications_ready = tokio::task::spawn_blocking(notify::warm_up);
+
+    let Some(config) = load_config(args) else {
+        return;
+    };
+    let Some(profile) = resolve_profile(&config, args) else {
+        return;
+    };
+
+    let image_data = match clipboard::read_image() {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            notify::send("Nothing to paste", "The clipboard doesn't hold an image", Sound::Failure);
+            return;
+        }
+        Err(e) => {
+            notify::send("Failed to read clipboard", &e.to_string(), Sound::Failure);
+            return;
+        }
+    };
+    let image_data = match prepare_image(image_data, profile.preprocess.as_deref()) {
+        Ok(data) => data,
+        Err(e) => {
+            notify::send("Failed to read image", &e, Sound::Failure);
+            return;
+        }
+    };
+
+    let Some(client) = await_client(client).await else {
+        return;
+    };
+    let _ = notifications_ready.await;
+    let context = Context::gather(args, &config).text(None);
+    let converter = Converter {
+        client: &client,
+        config: &config,
+        profile: &profile,
+        context: context.as_deref(),
+    };
+    let conversion = match converter.convert(&image_data, "clipboard.png").await {
+        Some(Ok(conversion)) => conversion,
+        Some(Err(e)) => {
+            escalation::failed(
+                "API Call Failed",
+                &format!("Error calling Claude API: {}", e),
+            );
+            return;
+        }
+        None => {
+            notify::send("Conversion cancelled", "Clipboard left unchanged", Sound::Failure);
+            return;
+        }
+    };
+
+    // Paste always goes through the clipboard, whatever outputs are configured
+    let pasted = conversion
+        .render(config.template(&profile), config.format(&profile))
+        .and_then(|text| clipboard::copy_with(&text, conversion.alt_text.as_deref(), &config.clipboard_options()));
+    if let Err(e) = pasted {
+        notify::send("Error", &format!("Failed to copy to clipboard: {}", e), Sound::Failure);
+        return;
+    }
+    context::remember(std::slice::from_ref(&conversion));
+    if let Err(e) = history::record(std::slice::from_ref(&conversion)) {
+        eprintln!("Failed to add the result to the history: {}", e);
+    }
+    // Give the pasteboard a moment to settle before the target app reads it
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    if let Err(e) = paste::into_frontmost() {
+        notify::send(
+            "Paste Failed",
+            &format!("LaTeX is on the {}, but pasting failed: {}", clipboard::DESTINATION, e),
+            Sound::Failure,
+        );
+    }
+}
+
+/// Handles `latex_ocr recording`: samples the recording, drops frames that
+/// look like the one before and converts the rest as a batch
+async fn run_recording(args: &Args, path: &str, every: Option<u32>, document: Option<&str>, compile: bool) {
+    let Some(config) = load_config(args) else {
+        return;
+    };
+    let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+    let frames = match recording::frames(&path, every.unwrap_or(config.recording_frame_seconds)) {
+        Ok(frames) => frames,
+        Err(e) => {
+            eprintln!("Failed to read frames from {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    if frames.is_empty() {
+        eprintln!("No frames found in {}", path.display());
+        std::process::exit(1);
+    }
+    let distinct = recording::distinct(&frames, config.recording_similarity);
+    eprintln!("{} of {} frames differ from the one before, converting those", distinct.len(), frames.len());
+    let inputs: Vec<String> = distinct.iter().map(|frame| frame.to_string_lossy().to_string()).collect();
+    run_batch(args, &inputs, document, compile, None, false, false).await
+}
+
+/// Handles `latex_ocr derivation`: converts each distinct frame of the
+/// animation in order and delivers their steps as one numbered `align`
+async fn run_derivation(args: &Args, path: &str) {
+    let client = tokio::task::spawn_blocking(build_client);
+    let Some(config) = load_config(args) else {
+        return;
+    };
+    let Some(profile) = resolve_profile(&config, args) else {
+        return;
+    };
+    if !config.format(&profile).is_latex() {
+        eprintln!("`derivation` assembles an align environment, but results are written in another format");
+        std::process::exit(1);
+    }
+    let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+    let frames = match derivation::frames(&path) {
+        Ok(frames) => recording::distinct(&frames, config.recording_similarity),
+        Err(e) => {
+            eprintln!("Failed to read frames from {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    if frames.is_empty() {
+        eprintln!("No frames found in {}", path.display());
+        std::process::exit(1);
+    }
+
+    let Some(client) = await_client(client).await else {
+        return;
+    };
+    let hint = config.metadata_hints.then(|| Hint::default().with_file(&path));
+    let context = match Context::gather(args, &config).text(hint.as_ref()) {
+        Some(text) => format!("{}\n\n{}", derivation::CONTEXT, text),
+        None => derivation::CONTEXT.to_string(),
+    };
+    let converter = Converter {
+        client: &client,
+        config: &config,
+        profile: &profile,
+        context: Some(&context),
+    };
+    let mut steps = Vec::new();
+    let mut progress = Progress::new(frames.len());
+    for frame in &frames {
+        progress.start(frame);
+        let image_data = match read_image(frame, profile.preprocess.as_deref()).await {
+            Ok(data) => data,
+            Err(e) => {
+                progress.finish_image(frame, Outcome::Skipped(e));
+                continue;
+            }
+        };
+        match converter.convert(&image_data, &frame.to_string_lossy()).await {
+            Some(Ok(conversion)) => {
+                steps.push(conversion);
+                progress.finish_image(frame, Outcome::Converted);
+            }
+            Some(Err(e)) => progress.finish_image(frame, Outcome::Failed(e.to_string())),
+            None => {
+                progress.finish();
+                notify::send("Conversion cancelled", "Clipboard left unchanged", Sound::Failure);
+                return;
+            }
+        }
+    }
+    progress.finish();
+    let Some(first) = steps.first() else {
+        notify::send("Derivation Failed", "No frame could be converted", Sound::Failure);
+        return;
+    };
+    let latex: Vec<String> = steps.iter().map(|step| step.latex.clone()).collect();
+    let animation = std::fs::read(&path).unwrap_or_default();
+    let conversion = Conversion {
+        latex: derivation::assemble(&latex),
+        image: path.to_string_lossy().to_string(),
+        image_hash: pipeline::sha256_hex(&animation),
+        confidence: steps.iter().filter_map(|step| step.confidence).min(),
+        ..first.clone()
+    };
+    let label = format!(" ({} of {} frames)", steps.len(), frames.len());
+    deliver(&client, &config, &profile, &[conversion], &label).await;
+}
+
+/// Handles `latex_ocr batch`: converts every image under `inputs` in order and
+/// delivers them together, or assembles them into one document. Progress is
+/// checkpointed so that `resume` can skip the images an interrupted run finished.
+/// `only_new` leaves out images the history has a result for that is newer than the file
+async fn run_batch(
+    args: &Args,
+    inputs: &[String],
+    document: Option<&str>,
+    compile: bool,
+    report: Option<&str>,
+    only_new: bool,
+    resume: bool,
+) {
+    let client = tokio::task::spawn_blocking(build_client);
+    let Some(config) = load_config(args) else {
+        return;
+    };
+    let Some(profile) = resolve_profile(&config, args) else {
+        return;
+    };
+    if document.is_some() && !config.format(&profile).is_latex() {
+        eprintln!("--document assembles LaTeX, but results are written in another format");
+        std::process::exit(1);
+    }
+    let checkpoint = if resume {
+        Checkpoint::load()
+    } else {
+        Checkpoint::start(inputs, document, compile, report, only_new).map(Some)
+    };
+    let mut checkpoint = match checkpoint {
+        Ok(Some(checkpoint)) => checkpoint,
+        Ok(None) => {
+            eprintln!("There is no interrupted batch to resume");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to keep track of the batch: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let (inputs, document, compile) = (checkpoint.inputs.clone(), checkpoint.document.clone(), checkpoint.compile);
+    let (report, only_new) = (checkpoint.report.clone(), checkpoint.only_new);
+    let document = document.as_deref();
+
+    // Directories contribute their images oldest first, files are taken as given
+    let mut images = Vec::new();
+    for input in &inputs {
+        let path = PathBuf::from(shellexpand::tilde(input).as_ref());
+        if path.is_dir() {
+            match discovery::images_in(&path, config.selection) {
+                Ok(found) => images.extend(found.into_iter().map(|candidate| candidate.path)),
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            images.push(path);
+        }
+    }
+    if images.is_empty() {
+        eprintln!("No images found in {}", inputs.join(", "));
+        std::process::exit(1);
+    }
+    if only_new {
+        let converted = history::last_converted().unwrap_or_else(|e| {
+            eprintln!("Failed to read history, converting everything: {}", e);
+            Default::default()
+        });
+        let total = images.len();
+        images.retain(|image| {
+            let last = std::fs::canonicalize(image).ok().and_then(|image| converted.get(&image).copied());
+            let modified = std::fs::metadata(image).and_then(|metadata| metadata.modified()).ok();
+            match (last, modified) {
+                (Some(last), Some(modified)) => modified > last,
+                (Some(_), None) => false,
+                (None, _) => true,
+            }
+        });
+        if images.is_empty() {
+            eprintln!("Nothing new among the {} images since the last run", total);
+            checkpoint.clear();
+            return;
+        }
+        eprintln!("{} of {} images are new or changed", images.len(), total);
+    }
+
+    let Some(client) = await_client(client).await else {
+        return;
+    };
+    let context = Context::gather(args, &config);
+
+    let mut conversions = Vec::new();
+    let mut progress = Progress::new(images.len());
+    for image in &images {
+        progress.start(image);
+        let hint = config.metadata_hints.then(|| Hint::default().with_file(image));
+        let context = context.text(hint.as_ref());
+        let converter = Converter {
+            client: &client,
+            config: &config,
+            profile: &profile,
+            context: context.as_deref(),
+        };
+        let image_data = match read_image(image, profile.preprocess.as_deref()).await {
+            Ok(data) => data,
+            Err(e) => {
+                progress.finish_image(image, Outcome::Skipped(e));
+                continue;
+            }
+        };
+        let path = image.to_string_lossy();
+        if let Some(done) = checkpoint.find(&path, &pipeline::sha256_hex(&image_data)) {
+            conversions.push(done.clone());
+            progress.finish_image(image, Outcome::Resumed);
+            continue;
+        }
+        match converter.convert(&image_data, &path).await {
+            Some(Ok(conversion)) => {
+                if let Err(e) = checkpoint.record(&conversion) {
+                    eprintln!("Failed to save batch progress: {}", e);
+                }
+                conversions.push(conversion);
+                progress.finish_image(image, Outcome::Converted);
+            }
+            Some(Err(e)) => progress.finish_image(image, Outcome::Failed(e.to_string())),
+            None => {
+                progress.finish();
+                eprintln!("Batch cancelled; `latex_ocr batch --resume` picks up where it stopped");
+                return;
+            }
+        }
+    }
+    progress.finish();
+    let missing = images.len() - conversions.len();
+    if conversions.is_empty() {
+        notify::send("Batch Failed", "No image could be converted", Sound::Failure);
+        return;
+    }
+    let label = format!(" ({} of {} images)", conversions.len(), images.len());
+    if let Some(report) = &report {
+        let title = Path::new(document.unwrap_or(report))
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match report::write(Path::new(report), &title, &conversions) {
+            Ok(()) => eprintln!("Wrote the report to {}", report),
+            Err(e) => eprintln!("Failed to write the report {}: {}", report, e),
+        }
+    }
+
+    let Some(document) = document else {
+        deliver(&client, &config, &profile, &conversions, &label).await;
+        complete(checkpoint, missing);
+        return;
+    };
+    let sections: Vec<document::Section> = conversions
+        .iter()
+        .map(|conversion| document::Section {
+            title: Path::new(&conversion.image).file_stem().and_then(|stem| stem.to_str()),
+            latex: &conversion.latex,
+        })
+        .collect();
+    let title = Path::new(document)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let path = Path::new(document);
+    if let Err(e) = atomic::write(path, document::assemble(&config.document_preamble, &title, &sections)) {
+        notify::send("Batch Failed", &format!("Failed to write {}: {}", document, e), Sound::Failure);
+        return;
+    }
+    // Nothing else records these, and `--only-new` goes by the history
+    if let Err(e) = history::record(&conversions) {
+        eprintln!("Failed to add the results to history: {}", e);
+    }
+    if compile && let Err(e) = document::compile(&config.latex_command, path) {
+        notify::send(
+            &format!("Document Doesn't Compile{}", label),
+            &format!("{}: {}", document, e),
+            Sound::Failure,
+        );
+        return;
+    }
+    notify::send(
+        &format!("Batch Complete{}", label),
+        &format!("Wrote {}", document),
+        Sound::Success,
+    );
+    complete(checkpoint, missing);
+}
+
+/// Forgets a delivered batch, unless `missing` images failed and could still be retried
+fn complete(checkpoint: Checkpoint, missing: usize) {
+    if missing == 0 {
+        checkpoint.clear();
+    } else {
+        eprintln!("`latex_ocr batch --resume` retries the {} that didn't convert", missing);
+    }
+}
+
+/// The fast path: once `quick` has been delivered, converts the image again
+/// with `refine_model` and offers that result only if it reads differently
+async fn refine(
+    converter: &Converter<'_>,
+    image_data: &[u8],
+    image_path: &str,
+    quick: &Conversion,
+    label: &str,
+) {
+    let model = converter
+        .profile
+        .refine_model
+        .clone()
+        .unwrap_or_else(|| converter.config.refine_model.clone());
+    if model.trim().is_empty() || model == quick.model {
+        return;
+    }
+    let profile = Profile { model: Some(model.clone()), ..converter.profile.clone() };
+    let refiner = Converter { profile: &profile, ..*converter };
+    let refined = match refiner.convert(image_data, image_path).await {
+        Some(Ok(refined)) => refined,
+        Some(Err(e)) => {
+            eprintln!("Refinement with {} failed: {}", model, e);
+            return;
+        }
+        None => return,
+    };
+    let disagrees = refine::disagrees(&quick.latex, &refined.latex);
+    events::emit(
+        "refined",
+        serde_json::json!({ "image": image_path, "model": model, "disagrees": disagrees }),
+    );
+    if !disagrees {
+        return;
+    }
+    let diff = refine::diff(quick.latex.trim(), refined.latex.trim());
+    if notify::ask(&format!("{} reads it differently{}", model, label), &diff, "Use it", "Keep") {
+        deliver(converter.client, converter.config, &profile, &[refined], label).await;
+    }
+}
+
+/// Handles `convert` with several images or `--all-since`: converts up to
+/// `convert_jobs` of them at once, then delivers the results together in
+/// their order, or with `separate` writes each next to its image, and sums
+/// up how it went in one notification. Cancelling one conversion stops them all
+async fn run_convert_many(args: &Args, paths: &[PathBuf], since: Option<Duration>, separate: bool) {
+    let client = tokio::task::spawn_blocking(build_client);
+    let Some(config) = load_config(args) else {
+        return;
+    };
+    let Some(profile) = resolve_profile(&config, args) else {
+        return;
+    };
+
+    // Directories contribute their images oldest first, like in a batch
+    let mut images = Vec::new();
+    for path in paths {
+        if path == Path::new(input::STDIN) {
+            eprintln!("`convert {}` reads a single image from stdin, not one among others", input::STDIN);
+            std::process::exit(1);
+        }
+        if path.is_dir() {
+            match discovery::images_in(path, config.selection) {
+                Ok(found) => images.extend(found.into_iter().map(|candidate| candidate.path)),
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            images.push(path.clone());
+        }
+    }
+    if let Some(since) = since {
+        let directory = config.image_directory_expanded();
+        match discovery::images_in(Path::new(&directory), config.selection) {
+            Ok(found) => images.extend(
+                found
+                    .into_iter()
+                    .filter(|candidate| candidate.age() <= since)
+                    .map(|candidate| candidate.path),
+            ),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", directory, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut seen = HashSet::new();
+    images.retain(|image| seen.insert(image.clone()));
+    if images.is_empty() {
+        let within = since.map(|since| format!(" from the last {}", settings::format_duration(since)));
+        notify::send("No images found", &format!("No images to convert{}", within.unwrap_or_default()), Sound::Failure);
+        return;
+    }
+
+    let Some(client) = await_client(client).await else {
+        return;
+    };
+    let context = Context::gather(args, &config);
+    let jobs = Arc::new(Semaphore::new(config.convert_jobs));
+    let config = Arc::new(config);
+    let profile = Arc::new(profile);
+    // Providers aren't `Send`, so the conversions share this thread, which is
+    // all waiting on replies anyway
+    let local = LocalSet::new();
+    let mut tasks = JoinSet::new();
+    for (index, image) in images.iter().enumerate() {
+        let hint = config.metadata_hints.then(|| Hint::default().with_file(image));
+        let context = context.text(hint.as_ref());
+        let (client, config, profile, jobs) = (client.clone(), config.clone(), profile.clone(), jobs.clone());
+        let image = image.clone();
+        let task = async move {
+            let _job = jobs.acquire_owned().await.expect("the job semaphore is never closed");
+            let converter = Converter {
+                client: &client,
+                config: &config,
+                profile: &profile,
+                context: context.as_deref(),
+            };
+            // `None` when it was cancelled
+            let outcome = match read_image(&image, profile.preprocess.as_deref()).await {
+                Ok(image_data) => converter
+                    .convert(&image_data, &image.to_string_lossy())
+                    .await
+                    .map(|result| result.map_err(|e| Outcome::Failed(e.to_string()))),
+                Err(e) => Some(Err(Outcome::Skipped(e))),
+            };
+            (index, image, outcome)
+        };
+        tasks.spawn_local_on(task, &local);
+    }
+
+    let mut converted = Vec::new();
+    let mut progress = Progress::new(images.len());
+    while let Some(joined) = local.run_until(tasks.join_next()).await {
+        let (index, image, outcome) = joined.expect("conversion task panicked");
+        let Some(outcome) = outcome else {
+            // The images still waiting for a turn would otherwise start regardless
+            tasks.abort_all();
+            while local.run_until(tasks.join_next()).await.is_some() {}
+            notify::send("Conversion cancelled", "Clipboard left unchanged", Sound::Failure);
+            return;
+        };
+        match outcome {
+            Ok(conversion) => {
+                converted.push((index, conversion));
+                progress.finish_image(&image, Outcome::Converted);
+            }
+            Err(outcome) => progress.finish_image(&image, outcome),
+        }
+    }
+    progress.finish();
+    converted.sort_by_key(|(index, _)| *index);
+    let conversions: Vec<Conversion> = converted.into_iter().map(|(_, conversion)| conversion).collect();
+    if conversions.is_empty() {
+        notify::send(
+            "Conversion Failed",
+            &format!("None of the {} images could be converted", images.len()),
+            Sound::Failure,
+        );
+        return;
+    }
+    let label = format!(" ({} of {} images)", conversions.len(), images.len());
+    if separate {
+        write_separately(&config, &profile, &conversions, &label);
+    } else {
+        deliver(&client, &config, &profile, &conversions, &label).await;
+    }
+}
+
+/// Writes each result to a file named after its image, in the result format
+fn write_separately(config: &AppConfig, profile: &Profile, conversions: &[Conversion], label: &str) {
+    let conversions = review_doubtful(config, conversions, label);
+    let template = config.template(profile);
+    let format = config.format(profile);
+    let mut written = Vec::new();
+    let mut failed = Vec::new();
+    for conversion in conversions {
+        let path = Path::new(&conversion.image).with_extension(format.extension());
+        let result = conversion
+            .render(template, format)
+            .and_then(|text| atomic::write(&path, text).map_err(Into::into));
+        match result {
+            Ok(()) => written.push((path, conversion)),
+            Err(e) => failed.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    if written.is_empty() {
+        notify::send(&format!("Error{}", label), &failed.join("\n"), Sound::Failure);
+        return;
+    }
+    let conversions: Vec<Conversion> = written.iter().map(|(_, conversion)| conversion.clone()).collect();
+    if let Err(e) = history::record(&conversions) {
+        eprintln!("Failed to add the results to history: {}", e);
+    }
+    let names: Vec<String> = written
+        .iter()
+        .map(|(path, _)| path.file_name().unwrap_or_default().to_string_lossy().to_string())
+        .collect();
+    let mut message = format!("Wrote {}", names.join(", "));
+    if !failed.is_empty() {
+        message.push_str(&format!("\nFailed to write {}", failed.join(", ")));
+    }
+    notify::send(&format!("LaTeX Conversion Complete{}", label), &message, Sound::Success);
+}
+
+/// Reads, converts and delivers one image file, notifying about any failure
+async fn convert_file(
+    client: &reqwest::Client,
+    config: &AppConfig,
+    profile: &Profile,
+    path: &Path,
+    label: &str,
+    context: Option<&str>,
+) {
+    let image_data = match read_image(path, profile.preprocess.as_deref()).await {
+        Ok(data) => data,
+        Err(e) => {
+            notify::send(&format!("Failed to read image{}", label), &e, Sound::Failure);
+            return;
+        }
+    };
+    let path_str = path.to_string_lossy().to_string();
+    let converter = Converter { client, config, profile, context };
+    match converter.convert(&image_data, &path_str).await {
+        Some(Ok(conversion)) => {
+            deliver(client, config, profile, std::slice::from_ref(&conversion), label).await;
+            refine(&converter, &image_data, &path_str, &conversion, label).await;
+        }
+        Some(Err(e)) => escalation::failed(
+            &format!("API Call Failed{}", label),
+            &format!("Error calling Claude API: {}", e),
+        ),
+        None => notify::send(
+            &format!("Conversion cancelled{}", label),
+            "Clipboard left unchanged",
+            Sound::Failure,
+        ),
+    }
+}
+
+/// Handles `latex_ocr watch`: stays resident and offers to convert every new
+/// screenshot in the image directory through an actionable notification
+async fn run_watch(args: &Args) {
+    let Some(config) = load_config(args) else {
+        return;
+    };
+    let Some(profile) = resolve_profile(&config, args) else {
+        return;
+    };
+    let Some(client) = await_client(tokio::task::spawn_blocking(build_client)).await else {
+        return;
+    };
+    notify::warm_up();
+
+    let directory = config.image_directory_expanded();
+    let (_watcher, mut new_images) = match watcher::watch(Path::new(&directory)) {
+        Ok(watch) => watch,
+        Err(e) => {
+            notify::send(
+                "Watch Failed",
+                &format!("Cannot watch {}: {}", directory, e),
+                Sound::Failure,
+            );
+            return;
+        }
+    };
+    let context = Context::gather(args, &config);
+    let burst_window = Duration::from_millis(config.watch_burst_ms);
+    let request_interval = match config.watch_requests_per_minute {
+        0 => Duration::ZERO,
+        rate => Duration::from_secs(60) / rate,
+    };
+    let mut next_request = tokio::time::Instant::now();
+    eprintln!("Watching {} for new screenshots, ctrl-c to stop", directory);
+
+    loop {
+        let path = tokio::select! {
+            path = new_images.recv() => match path {
+                Some(path) => path,
+                None => break,
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        // The screenshot was just taken, so the app in front is where it came from
+        let filtered = !config.watch_apps.is_empty();
+        let frontmost = (config.metadata_hints || filtered).then(metadata::frontmost);
+
+        // A burst (e.g. a scripted export) is offered once and converted one at a time
+        let mut burst = vec![path];
+        while burst.len() < config.watch_burst_max {
+            tokio::select! {
+                next = tokio::time::timeout(burst_window, new_images.recv()) => match next {
+                    Ok(Some(path)) => burst.push(path),
+                    _ => break,
+                },
+                _ = tokio::signal::ctrl_c() => return,
+            }
+        }
+        let names: Vec<String> = burst
+            .iter()
+            .map(|path| path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default())
+            .collect();
+        let (question, message) = match names.len() {
+            1 => ("Convert this screenshot?".to_string(), names[0].clone()),
+            count => (format!("Convert these {} screenshots?", count), names.join(", ")),
+        };
+        if let Some(hint) = frontmost.as_ref().filter(|_| filtered) {
+            if !hint.app_is_one_of(&config.watch_apps) {
+                let app = hint.app.as_deref().unwrap_or("an unknown app");
+                eprintln!("Ignoring {} from {}", message, app);
+                continue;
+            }
+        } else if config.watch_confirm && !args.no_confirm {
+            let asking = tokio::task::spawn_blocking(move || notify::ask(&question, &message, "Convert", "Skip"));
+            let accepted = tokio::select! {
+                accepted = asking => accepted.unwrap_or(false),
+                _ = tokio::signal::ctrl_c() => return,
+            };
+            if !accepted {
+                continue;
+            }
+        }
+        let frontmost = frontmost.filter(|_| config.metadata_hints);
+        let total = burst.len();
+        for (index, path) in burst.iter().enumerate() {
+            let converting = async {
+                if !discovery::settle(path).await {
+                    eprintln!("Skipping {}, it didn't finish being written", path.display());
+                    return;
+                }
+                tokio::time::sleep_until(next_request).await;
+                next_request = tokio::time::Instant::now() + request_interval;
+                let label = queue::Position { index: index + 1, total }.label();
+                let hint = frontmost.clone().map(|hint| hint.with_file(path));
+                let context = context.text(hint.as_ref());
+                convert_file(&client, &config, &profile, path, &label, context.as_deref()).await;
+            };
+            tokio::select! {
+                _ = converting => {}
+                _ = tokio::signal::ctrl_c() => return,
+            }
+        }
+    }
+}
+
+/// Handles `latex_ocr hotkey`: every press of `hotkey` runs `hotkey_command`
+/// as its own invocation, with the same flags this one was given
+fn run_hotkey(args: &Args) {
+    let Some(config) = load_config(args) else {
+        return;
+    };
+    // Everything but the `hotkey` subcommand itself is passed on
+    let mut flags: Vec<_> = std::env::args_os().skip(1).collect();
+    if let Some(index) = cli::subcommand_index(&flags) {
+        flags.remove(index);
+    }
+    eprintln!(
+        "Press {} to run `latex_ocr {}`, ctrl-c to stop",
+        config.hotkey, config.hotkey_command
+    );
+    let mut running: Vec<std::process::Child> = Vec::new();
+    let listening = hotkey::listen(&config.hotkey, || {
+        // Not waited on, so a press while one is running queues another; the
+        // ones that finished are reaped at the next press
+        running.retain_mut(|child| matches!(child.try_wait(), Ok(None)));
+        let started = std::env::current_exe().and_then(|exe| {
+            std::process::Command::new(exe)
+                .args(&flags)
+                .arg(&config.hotkey_command)
+                .stdin(std::process::Stdio::null())
+                .spawn()
+        });
+        match started {
+            Ok(child) => running.push(child),
+            Err(e) => notify::send(
+                "Hotkey Failed",
+                &format!("Cannot start {}: {}", config.hotkey_command, e),
+                Sound::Failure,
+            ),
+        }
+    });
+    if let Err(e) = listening {
+        eprintln!("Hotkey failed: {}", e);
+        notify::send("Hotkey Failed", &e.to_string(), Sound::Failure);
+    }
+}
+
+/// Handles `latex_ocr watch --daemon`: runs the same command line without
+/// `--daemon` as a background process that outlives the terminal, its output
+/// in watch.log and its pid in watch.pid in the state directory
+fn detach_watch() {
+    let state = paths::state_dir();
+    let log_path = state.join("watch.log");
+    let started = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .and_then(|log| {
+            let mut command = std::process::Command::new(std::env::current_exe()?);
+            command
+                .args(std::env::args_os().skip(1).filter(|arg| arg != "--daemon"))
+                .stdin(std::process::Stdio::null())
+                .stdout(log.try_clone()?)
+                .stderr(log);
+            // Its own process group, so ctrl-c and the terminal closing don't reach it
+            #[cfg(unix)]
+            std::os::unix::process::CommandExt::process_group(&mut command, 0);
+            command.spawn()
+        });
+    match started {
+        Ok(child) => {
+            let _ = std::fs::write(state.join("watch.pid"), format!("{}\n", child.id()));
+            println!("Watching in the background (pid {}), output in {}", child.id(), log_path.display());
+        }
+        Err(e) => {
+            eprintln!("Cannot start watching in the background: {}", e);
+            notify::send("Watch Failed", &format!("Cannot start in the background: {}", e), Sound::Failure);
+        }
+    }
+}
+
+/// Handles `latex_ocr --dictate`: record, transcribe, convert the spoken math
+async fn run_dictate(args: &Args) {
+    let client = tokio::task::spawn_blocking(build_client);
+    let Some(config) = load_config(args) else {
+        return;
+    };
+    notify::warm_up();
+
+    notify::send(
+        "Recording",
+        &format!("Dictate your math, recording for {}s", config.dictation_seconds),
+        Sound::Success,
+    );
+    let recording_config = config.clone();
+    let recording = match tokio::task::spawn_blocking(move || {
+        dictation::record(&recording_config).map_err(|e| e.to_string())
+    })
+    .await
+    .expect("recording task failed")
+    {
+        Ok(recording) => recording,
+        Err(e) => {
+            notify::send("Recording Failed", &e, Sound::Failure);
+            return;
+        }
+    };
+
+    let Some(client) = await_client(client).await else {
+        return;
+    };
+    let transcript = match dictation::transcribe(&client, &config, &recording).await {
+        Ok(transcript) if !transcript.is_empty() => transcript,
+        Ok(_) => {
+            notify::send("Transcription Failed", "Nothing was heard", Sound::Failure);
+            return;
+        }
+        Err(e) => {
+            notify::send("Transcription Failed", &e.to_string(), Sound::Failure);
+            return;
+        }
+    };
+
+    let prompt = format!("{}{}", dictation::PROMPT, transcript);
+    let request = provider::Request::text(&config.model, &prompt, provider::Limits::reply(provider::MAX_TOKENS));
+    match provider::complete_with_usage(&*config.provider(), &client, &request).await
+    {
+        Ok((latex, usage)) => {
+            let conversion = Conversion {
+                latex,
+                source: None,
+                glossary: Vec::new(),
+                alt_text: None,
+                confidence: None,
+                language: None,
+                image: String::new(),
+                image_hash: String::new(),
+                model: config.model.clone(),
+                profile: "dictation".to_string(),
+                prompt,
+                usage,
+                original: None,
+            };
+            deliver(&client, &config, &Profile::default(), &[conversion], "").await
+        }
+        Err(e) => escalation::failed(
+            "API Call Failed",
+            &format!("Error calling Claude API: {}", e),
+        ),
     }
 }
@@ -0,0 +1,27 @@
+//! Cheap first-pass check that an image contains something worth converting,
+//! so a photo or UI screenshot picked by accident doesn't cost a full request.
+
+use std::error::Error;
+
+use crate::provider::{self, Limits, OcrProvider, Request};
+
+/// The question asked unless the mode or profile brings its own
+pub const PROMPT: &str = "Does this image contain mathematical notation, equations, tables or other content that can be typeset in LaTeX? Reply with a single word: yes or no.";
+
+/// The reply is a single word, this is plenty
+const MAX_TOKENS: u32 = 5;
+
+/// Asks `model` the yes/no `question` about the image, normally whether it
+/// looks like math; anything but a clear "no" counts as yes
+pub async fn looks_like_math(
+    client: &reqwest::Client,
+    provider: &dyn OcrProvider,
+    model: &str,
+    question: &str,
+    image_data: &[u8],
+    image_path: &str,
+) -> Result<bool, Box<dyn Error>> {
+    let request = Request::image(model, question, image_data, image_path, Limits::reply(MAX_TOKENS));
+    let reply = provider::complete(provider, client, &request).await?;
+    Ok(!reply.trim().to_lowercase().starts_with("no"))
+}
@@ -0,0 +1,117 @@
+//! Where a screenshot came from, sent along as a prompt hint ("a window of
+//! paper.pdf in Preview") so context-dependent notation is read right.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+/// A screenshot this young was most likely taken from the app that is still frontmost
+pub const FRESH: Duration = Duration::from_secs(120);
+
+/// What is known about the screenshot's origin
+#[derive(Debug, Clone, Default)]
+pub struct Hint {
+    pub app: Option<String>,
+    pub window: Option<String>,
+    /// "selection", "window" or "display", from the screenshot's metadata
+    pub capture_type: Option<String>,
+}
+
+impl Hint {
+    /// The hint as a prompt sentence, if anything is known
+    pub fn describe(&self) -> Option<String> {
+        let mut origin = String::new();
+        if let Some(window) = &self.window {
+            origin.push_str(&format!(" of \"{}\"", window));
+        }
+        if let Some(app) = &self.app {
+            origin.push_str(&format!(" in {}", app));
+        }
+        let capture = self
+            .capture_type
+            .as_deref()
+            .map(|kind| format!(" ({} capture)", kind))
+            .unwrap_or_default();
+        if origin.is_empty() && capture.is_empty() {
+            return None;
+        }
+        Some(format!("Hint: this is a screenshot{}{}.", origin, capture))
+    }
+
+    /// Adds what the screenshot file's metadata says
+    pub fn with_file(mut self, path: &Path) -> Self {
+        self.capture_type = capture_type(path);
+        self
+    }
+
+    /// Whether the screenshot came from the app `name`, ignoring case
+    pub fn app_is(&self, name: &str) -> bool {
+        self.app.as_deref().is_some_and(|app| name.trim().eq_ignore_ascii_case(app))
+    }
+
+    /// Whether the screenshot came from one of `apps`
+    pub fn app_is_one_of(&self, apps: &[String]) -> bool {
+        apps.iter().any(|wanted| self.app_is(wanted))
+    }
+
+    /// Whether the window title contains `name`, ignoring case
+    pub fn window_mentions(&self, name: &str) -> bool {
+        let name = name.trim().to_lowercase();
+        !name.is_empty() && self.window.as_deref().is_some_and(|window| window.to_lowercase().contains(&name))
+    }
+}
+
+/// The frontmost app and its window title
+#[cfg(target_os = "macos")]
+pub fn frontmost() -> Hint {
+    const SCRIPT: &str = r#"tell application "System Events"
+    set frontApp to first application process whose frontmost is true
+    set windowName to ""
+    try
+        set windowName to name of front window of frontApp
+    end try
+    return (name of frontApp) & linefeed & windowName
+end tell"#;
+    let Some(output) = run("osascript", &["-e", SCRIPT]) else {
+        return Hint::default();
+    };
+    let mut lines = output.lines().map(str::to_string);
+    Hint {
+        app: lines.next().filter(|app| !app.is_empty()),
+        window: lines.next().filter(|window| !window.is_empty()),
+        capture_type: None,
+    }
+}
+
+/// The focused window's class and title under X11
+#[cfg(not(target_os = "macos"))]
+pub fn frontmost() -> Hint {
+    Hint {
+        app: run("xdotool", &["getactivewindow", "getwindowclassname"]),
+        window: run("xdotool", &["getactivewindow", "getwindowname"]),
+        capture_type: None,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn capture_type(path: &Path) -> Option<String> {
+    let path = path.to_string_lossy();
+    run("mdls", &["-raw", "-name", "kMDItemScreenCaptureType", &path])
+        .filter(|kind| kind != "(null)")
+}
+
+#[cfg(not(target_os = "macos"))]
+fn capture_type(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Trimmed stdout of a successful command, `None` on any failure or empty output
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|text| !text.is_empty())
+}
@@ -0,0 +1,185 @@
+//! Versioned on-disk formats. Files written by an older latex_ocr are upgraded
+//! in place after a backup; files from a newer one are refused rather than
+//! misread.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::atomic;
+
+/// Format version of `config.toml` written by this build
+pub const CONFIG_VERSION: u32 = 1;
+/// Format version of `history.jsonl` written by this build
+pub const HISTORY_VERSION: u32 = 1;
+
+/// Rewrites a file's text from one version to the next
+type Step = fn(&str) -> String;
+
+/// `CONFIG_STEPS[n]` upgrades version `n` to `n + 1`
+const CONFIG_STEPS: [Step; CONFIG_VERSION as usize] = [config_v0_to_v1];
+/// `HISTORY_STEPS[n]` upgrades version `n` to `n + 1`
+const HISTORY_STEPS: [Step; HISTORY_VERSION as usize] = [history_v0_to_v1];
+
+/// Brings `config.toml` to `CONFIG_VERSION`
+pub fn config(path: &Path) -> Result<(), String> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Ok(());
+    };
+    let version = config_version(&text)?;
+    upgrade(path, &text, version, CONFIG_VERSION, &CONFIG_STEPS)
+}
+
+/// Brings `history.jsonl` to `HISTORY_VERSION`
+pub fn history(path: &Path) -> Result<(), String> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Ok(());
+    };
+    if text.trim().is_empty() {
+        return Ok(());
+    }
+    upgrade(path, &text, history_version(&text), HISTORY_VERSION, &HISTORY_STEPS)
+}
+
+/// The first line of a history file, which records its version
+pub fn history_header() -> String {
+    format!("{{\"version\":{}}}", HISTORY_VERSION)
+}
+
+fn upgrade(path: &Path, text: &str, version: u32, current: u32, steps: &[Step]) -> Result<(), String> {
+    if version > current {
+        return Err(format!(
+            "{} is format version {} but this latex_ocr only understands up to {}; update latex_ocr",
+            path.display(),
+            version,
+            current
+        ));
+    }
+    if version == current {
+        return Ok(());
+    }
+
+    let backup = with_suffix(path, &format!(".v{}.bak", version));
+    fs::copy(path, &backup).map_err(|e| format!("cannot back up {}: {}", path.display(), e))?;
+    let upgraded = steps[version as usize..current as usize]
+        .iter()
+        .fold(text.to_string(), |text, step| step(&text));
+
+    atomic::write(path, upgraded).map_err(|e| format!("cannot upgrade {}: {}", path.display(), e))?;
+    eprintln!(
+        "Upgraded {} from format version {} to {} (backup in {})",
+        path.display(),
+        version,
+        current,
+        backup.display()
+    );
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// The top-level `version = N` of a config file, 0 for files from before versioning
+fn config_version(text: &str) -> Result<u32, String> {
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            break;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if key.trim() == "version" {
+            return value
+                .trim()
+                .parse()
+                .map_err(|_| format!("config version `{}` is not a number", value.trim()));
+        }
+    }
+    Ok(0)
+}
+
+/// The version in a history file's header line, 0 for files without one
+fn history_version(text: &str) -> u32 {
+    let first = text.lines().next().unwrap_or_default();
+    serde_json::from_str::<serde_json::Value>(first)
+        .ok()
+        .filter(|header| header.get("latex").is_none())
+        .and_then(|header| header["version"].as_u64())
+        .map_or(0, |version| version as u32)
+}
+
+fn config_v0_to_v1(text: &str) -> String {
+    format!("# Format version of this file, managed by latex_ocr\nversion = 1\n\n{}", text)
+}
+
+fn history_v0_to_v1(text: &str) -> String {
+    format!("{}\n{}", history_header(), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file of its own in the temp directory, removed with its backups on drop
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str, text: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("latex_ocr-migrate-{}-{}", std::process::id(), name));
+            fs::write(&path, text).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(with_suffix(&self.0, ".v0.bak"));
+        }
+    }
+
+    #[test]
+    fn config_versions_are_read_before_any_table() {
+        assert_eq!(config_version("version = 3\nmodel = \"x\""), Ok(3));
+        assert_eq!(config_version("model = \"x\"\n[profiles.a]\nversion = 2"), Ok(0));
+        assert!(config_version("version = \"one\"").is_err());
+    }
+
+    #[test]
+    fn history_versions_come_from_the_header() {
+        assert_eq!(history_version(&format!("{}\n{{\"latex\":\"x\"}}", history_header())), HISTORY_VERSION);
+        // An entry on the first line is a file from before versioning
+        assert_eq!(history_version("{\"latex\":\"x\",\"version\":5}"), 0);
+        assert_eq!(history_version(""), 0);
+    }
+
+    #[test]
+    fn old_configs_are_upgraded_with_a_backup() {
+        let scratch = Scratch::new("config.toml", "model = \"x\"\n");
+        config(&scratch.0).unwrap();
+        let upgraded = fs::read_to_string(&scratch.0).unwrap();
+        assert_eq!(config_version(&upgraded), Ok(CONFIG_VERSION));
+        assert!(upgraded.ends_with("model = \"x\"\n"));
+        assert_eq!(fs::read_to_string(with_suffix(&scratch.0, ".v0.bak")).unwrap(), "model = \"x\"\n");
+        // Upgrading again changes nothing
+        config(&scratch.0).unwrap();
+        assert_eq!(fs::read_to_string(&scratch.0).unwrap(), upgraded);
+    }
+
+    #[test]
+    fn old_histories_get_a_header() {
+        let scratch = Scratch::new("history.jsonl", "{\"latex\":\"x\"}\n");
+        history(&scratch.0).unwrap();
+        assert_eq!(fs::read_to_string(&scratch.0).unwrap(), format!("{}\n{{\"latex\":\"x\"}}\n", history_header()));
+    }
+
+    #[test]
+    fn newer_files_are_refused() {
+        let scratch = Scratch::new("newer.toml", &format!("version = {}\n", CONFIG_VERSION + 1));
+        let error = config(&scratch.0).unwrap_err();
+        assert!(error.contains("update latex_ocr"), "{}", error);
+        assert_eq!(fs::read_to_string(&scratch.0).unwrap(), format!("version = {}\n", CONFIG_VERSION + 1));
+    }
+}
@@ -0,0 +1,70 @@
+//! Test harness: a local server standing in for the provider APIs, answering
+//! with the canned replies in tests/fixtures, so requests can be followed
+//! end to end without a network or real keys.
+
+use std::path::Path;
+
+use wiremock::matchers::method;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use crate::provider::Backend;
+use crate::settings::AppConfig;
+
+pub struct MockApi {
+    server: MockServer,
+}
+
+/// The contents of tests/fixtures/`name`
+pub fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+impl MockApi {
+    pub async fn start() -> Self {
+        Self { server: MockServer::start().await }
+    }
+
+    /// A config sending `backend`'s requests here, with a made-up key
+    pub fn config(&self, backend: Backend) -> AppConfig {
+        AppConfig {
+            provider: backend,
+            api_key: "test-key".to_string(),
+            anthropic_url: self.server.uri(),
+            openai_api_key: "test-key".to_string(),
+            openai_url: self.server.uri(),
+            gemini_api_key: "test-key".to_string(),
+            gemini_url: self.server.uri(),
+            ollama_url: self.server.uri(),
+            ..AppConfig::default()
+        }
+    }
+
+    /// Streams the fixture `name` in answer to every request
+    pub async fn stream(&self, name: &str) {
+        let reply = ResponseTemplate::new(200)
+            .insert_header("content-type", "text/event-stream")
+            .set_body_string(fixture(name));
+        Mock::given(method("POST")).respond_with(reply).mount(&self.server).await;
+    }
+
+    /// Fails the next `times` requests with `status` and the fixture `name`,
+    /// asking for the retry right away; later requests get whatever else is mounted
+    pub async fn fail(&self, status: u16, name: &str, times: u64) {
+        let reply = ResponseTemplate::new(status)
+            .insert_header("content-type", "application/json")
+            .insert_header("retry-after", "0")
+            .set_body_string(fixture(name));
+        Mock::given(method("POST"))
+            .respond_with(reply)
+            .up_to_n_times(times)
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// How many requests the server has seen
+    pub async fn requests(&self) -> usize {
+        self.server.received_requests().await.map_or(0, |requests| requests.len())
+    }
+}
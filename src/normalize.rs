@@ -0,0 +1,219 @@
+//! Normalized math structure, so results that only differ in spelling
+//! (`\dfrac` vs `\frac`, spacing, delimiters) compare equal, and the cleanup
+//! every model reply goes through before it becomes a result.
+
+/// Commands with a preferred spelling
+const ALIASES: &[(&str, &str)] = &[
+    ("\\dfrac", "\\frac"),
+    ("\\tfrac", "\\frac"),
+    ("\\le", "\\leq"),
+    ("\\ge", "\\geq"),
+    ("\\ne", "\\neq"),
+    ("\\to", "\\rightarrow"),
+    ("\\gets", "\\leftarrow"),
+    ("\\land", "\\wedge"),
+    ("\\lor", "\\vee"),
+    ("\\lbrace", "\\{"),
+    ("\\rbrace", "\\}"),
+];
+
+/// Commands that only affect presentation
+const DROPPED: &[&str] = &[
+    "\\displaystyle", "\\textstyle", "\\left", "\\right", "\\bigl", "\\bigr", "\\Bigl",
+    "\\Bigr", "\\big", "\\Big", "\\quad", "\\qquad", "\\,", "\\;", "\\:", "\\!", "\\ ",
+];
+
+/// Normalizes `latex` for comparison: math delimiters, presentation-only
+/// commands and whitespace are dropped, aliases are spelled one way and
+/// braces around single-character arguments are removed
+pub fn math(latex: &str) -> String {
+    let mut tokens = Vec::new();
+    let mut chars = latex.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let mut command = String::from("\\");
+            while let Some(&next) = chars.peek() {
+                if next.is_ascii_alphabetic() {
+                    command.push(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if command.len() == 1
+                && let Some(next) = chars.next()
+            {
+                command.push(next);
+            }
+            tokens.push(command);
+        } else if !c.is_whitespace() {
+            tokens.push(c.to_string());
+        }
+    }
+
+    let tokens: Vec<String> = tokens
+        .into_iter()
+        .filter(|token| !DROPPED.contains(&token.as_str()) && !is_delimiter(token))
+        .map(|token| {
+            ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == token)
+                .map_or(token, |(_, canonical)| canonical.to_string())
+        })
+        .collect();
+
+    // `{x}` -> `x` for a single token argument, until `{{x}}` is `x` too
+    let mut tokens = tokens;
+    loop {
+        let mut unwrapped = Vec::with_capacity(tokens.len());
+        let mut index = 0;
+        while index < tokens.len() {
+            if tokens[index] == "{"
+                && tokens.get(index + 2).is_some_and(|token| token == "}")
+                && !matches!(tokens[index + 1].as_str(), "{" | "}")
+            {
+                unwrapped.push(tokens[index + 1].clone());
+                index += 3;
+            } else {
+                unwrapped.push(tokens[index].clone());
+                index += 1;
+            }
+        }
+        if unwrapped.len() == tokens.len() {
+            break;
+        }
+        tokens = unwrapped;
+    }
+
+    let mut normalized = String::new();
+    let mut after_command = false;
+    for token in &tokens {
+        // `\alpha b` must not come out as the different command `\alphab`
+        if after_command && token.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            normalized.push(' ');
+        }
+        normalized.push_str(token);
+        after_command = token.len() > 1
+            && token.starts_with('\\')
+            && token[1..].chars().all(|c| c.is_ascii_alphabetic());
+    }
+    normalized
+}
+
+/// `reply` without the Markdown code fence some models wrap LaTeX in despite
+/// being asked not to; anything after the closing fence (e.g. a trailer) stays
+pub fn strip_fences(reply: &str) -> String {
+    let trimmed = reply.trim_start();
+    let Some(opening) = trimmed.strip_prefix("```") else {
+        return reply.to_string();
+    };
+    // The info string, e.g. "latex", runs to the end of the opening line
+    let Some((info, body)) = opening.split_once('\n') else {
+        return reply.to_string();
+    };
+    if info.contains('`') {
+        return reply.to_string();
+    }
+    let mut inside = Vec::new();
+    let mut lines = body.lines();
+    for line in lines.by_ref() {
+        if line.trim() == "```" {
+            let after: Vec<&str> = lines.collect();
+            let mut stripped = inside.join("\n");
+            if !after.is_empty() {
+                stripped.push('\n');
+                stripped.push_str(&after.join("\n"));
+            }
+            return stripped;
+        }
+        inside.push(line);
+    }
+    // Never closed, most likely the reply was cut off
+    inside.join("\n")
+}
+
+fn is_delimiter(token: &str) -> bool {
+    matches!(token, "$" | "\\[" | "\\]" | "\\(" | "\\)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Pieces LaTeX replies are made of, including the ones normalization rewrites
+    fn latex() -> impl Strategy<Value = String> {
+        let token = prop_oneof![
+            "[a-zA-Z0-9]",
+            Just("{".to_string()),
+            Just("}".to_string()),
+            Just(" ".to_string()),
+            Just("$".to_string()),
+            "[-+=^_(),.|]",
+            "\\\\[a-zA-Z]{1,6}",
+            prop::sample::select(vec![
+                "\\dfrac", "\\le", "\\left(", "\\right)", "\\,", "\\{", "\\}", "\\[", "\\]", "\\\\", "\\lbrace",
+            ])
+            .prop_map(str::to_string),
+        ];
+        prop::collection::vec(token, 0..40).prop_map(|tokens| tokens.concat())
+    }
+
+    /// Whether the unescaped braces in `text` pair up
+    fn balanced(text: &str) -> bool {
+        let mut depth = 0i32;
+        let mut escaped = false;
+        for c in text.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            if depth < 0 {
+                return false;
+            }
+        }
+        depth == 0
+    }
+
+    proptest! {
+        #[test]
+        fn math_is_idempotent(text in latex()) {
+            let once = math(&text);
+            prop_assert_eq!(math(&once), once);
+        }
+
+        #[test]
+        fn math_keeps_braces_balanced(text in latex().prop_filter("balanced", |text| balanced(text))) {
+            prop_assert!(balanced(&math(&text)), "{:?} -> {:?}", text, math(&text));
+        }
+
+        #[test]
+        fn fences_are_stripped(body in latex(), info in prop::sample::select(vec!["", "latex", "tex"])) {
+            let fenced = format!("```{}\n{}\n```", info, body);
+            prop_assert_eq!(strip_fences(&fenced), body);
+        }
+
+        #[test]
+        fn stripping_keeps_what_follows_the_fence(
+            body in latex(),
+            after in latex().prop_filter("something after", |after| !after.is_empty()),
+        ) {
+            let fenced = format!("```latex\n{}\n```\n{}", body, after);
+            prop_assert_eq!(strip_fences(&fenced), format!("{}\n{}", body, after));
+        }
+
+        #[test]
+        fn unfenced_replies_are_left_alone(text in latex()) {
+            prop_assert_eq!(strip_fences(&text), text);
+        }
+    }
+
+    #[test]
+    fn commands_stay_separate_from_the_letters_after_them() {
+        assert_ne!(math("\\alpha b"), math("\\alphab"));
+        assert_eq!(math("{{x}}"), math("x"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Desktop notifications, backed by the macOS notification center with the
+//! `macos-notify` feature, by the freedesktop notification service or Windows
+//! toasts with `desktop-notify`, and by stderr everywhere else.
+
+/// Which system sound accompanies a notification
+#[derive(Debug, Clone, Copy)]
+pub enum Sound {
+    Success,
+    Failure,
+    /// A failure without the sound, for ones not worth interrupting for
+    Muted,
+    /// Work still under way, shown without a sound
+    Progress,
+}
+
+#[cfg(all(target_os = "macos", feature = "macos-notify"))]
+mod backend {
+    use super::Sound;
+    use mac_notification_sys::*;
+
+    pub fn warm_up() {
+        let bundle = get_bundle_identifier_or_default("use_default");
+        let _ = set_application(&bundle);
+    }
+
+    fn sound_name(sound: Sound) -> Option<&'static str> {
+        match sound {
+            Sound::Success => Some("Glass"),
+            Sound::Failure => Some("Blow"),
+            Sound::Muted | Sound::Progress => None,
+        }
+    }
+
+    pub fn send(title: &str, message: &str, sound: Sound) {
+        let mut options = Notification::new();
+        if let Some(sound) = sound_name(sound) {
+            options.sound(sound);
+        }
+        if let Err(e) = send_notification(title, None, message, Some(&options)) {
+            eprintln!("{}: {} (notification failed: {})", title, message, e);
+        }
+    }
+
+    pub fn send_clickable(title: &str, message: &str, sound: Sound) -> bool {
+        let mut notification = Notification::new();
+        notification.title(title).message(message).wait_for_click(true);
+        if let Some(sound) = sound_name(sound) {
+            notification.sound(sound);
+        }
+        let response = notification.send();
+        match response {
+            Ok(NotificationResponse::Click) => true,
+            Ok(_) => false,
+            Err(e) => {
+                eprintln!("{}: {} (notification failed: {})", title, message, e);
+                false
+            }
+        }
+    }
+
+    pub fn ask(title: &str, message: &str, yes: &str, no: &str) -> bool {
+        let response = Notification::new()
+            .title(title)
+            .message(message)
+            .main_button(MainButton::SingleAction(yes))
+            .close_button(no)
+            .send();
+        match response {
+            Ok(NotificationResponse::ActionButton(_) | NotificationResponse::Click) => true,
+            Ok(_) => false,
+            // Fall back to a dialog rather than silently dropping the question
+            Err(_) => crate::dialog::confirm(title, message),
+        }
+    }
+}
+
+#[cfg(all(not(target_os = "macos"), feature = "desktop-notify"))]
+mod backend {
+    use super::Sound;
+    use notify_rust::Notification;
+
+    pub fn warm_up() {}
+
+    /// Names from the freedesktop sound theme; Windows picks its own by name
+    fn sound_name(sound: Sound) -> Option<&'static str> {
+        match sound {
+            Sound::Success => Some("complete"),
+            Sound::Failure => Some("dialog-warning"),
+            Sound::Muted | Sound::Progress => None,
+        }
+    }
+
+    fn notification(title: &str, message: &str, sound: Sound) -> Notification {
+        let mut notification = Notification::new();
+        notification.appname("latex_ocr").summary(title).body(message);
+        if let Some(sound) = sound_name(sound) {
+            notification.sound_name(sound);
+        }
+        notification
+    }
+
+    pub fn send(title: &str, message: &str, sound: Sound) {
+        if let Err(e) = notification(title, message, sound).show() {
+            eprintln!("{}: {} (notification failed: {})", title, message, e);
+        }
+    }
+
+    pub fn send_clickable(title: &str, message: &str, sound: Sound) -> bool {
+        let mut notification = notification(title, message, sound);
+        notification.action("default", "Open");
+        match notification.show() {
+            Ok(handle) => {
+                let mut clicked = false;
+                handle.wait_for_action(|action| clicked = action == "default");
+                clicked
+            }
+            Err(e) => {
+                eprintln!("{}: {} (notification failed: {})", title, message, e);
+                false
+            }
+        }
+    }
+
+    /// Whether notifications can have buttons; some Linux notification servers show none
+    fn has_actions() -> bool {
+        #[cfg(unix)]
+        return notify_rust::get_capabilities().is_ok_and(|capabilities| capabilities.iter().any(|c| c == "actions"));
+        #[cfg(not(unix))]
+        return true;
+    }
+
+    pub fn ask(title: &str, message: &str, yes: &str, no: &str) -> bool {
+        if !has_actions() {
+            return crate::dialog::confirm(title, message);
+        }
+        let mut notification = notification(title, message, Sound::Muted);
+        notification.action("yes", yes).action("no", no);
+        match notification.show() {
+            Ok(handle) => {
+                let mut accepted = false;
+                handle.wait_for_action(|action| accepted = action == "yes");
+                accepted
+            }
+            // Fall back to a dialog rather than silently dropping the question
+            Err(_) => crate::dialog::confirm(title, message),
+        }
+    }
+}
+
+#[cfg(not(any(
+    all(target_os = "macos", feature = "macos-notify"),
+    all(not(target_os = "macos"), feature = "desktop-notify")
+)))]
+mod backend {
+    use super::Sound;
+
+    pub fn warm_up() {}
+
+    pub fn send(title: &str, message: &str, _sound: Sound) {
+        eprintln!("{}: {}", title, message);
+    }
+
+    /// Nothing to click on stderr
+    pub fn send_clickable(title: &str, message: &str, sound: Sound) -> bool {
+        send(title, message, sound);
+        false
+    }
+
+    pub fn ask(title: &str, message: &str, _yes: &str, _no: &str) -> bool {
+        crate::dialog::confirm(title, message)
+    }
+}
+
+/// Registers the notification application ahead of the first notification,
+/// since the bundle lookup is slow enough to be noticeable
+pub fn warm_up() {
+    backend::warm_up();
+}
+
+/// Shows a notification with the given title, body and sound
+pub fn send(title: &str, message: &str, sound: Sound) {
+    crate::events::emit(
+        "notification",
+        serde_json::json!({ "title": title, "message": message, "outcome": outcome(sound) }),
+    );
+    backend::send(title, message, sound);
+}
+
+/// Like `send`, but waits until the notification is clicked or dismissed and
+/// says whether it was clicked; stderr notifications are never clicked
+pub fn send_clickable(title: &str, message: &str, sound: Sound) -> bool {
+    crate::events::emit(
+        "notification",
+        serde_json::json!({ "title": title, "message": message, "outcome": outcome(sound), "clickable": true }),
+    );
+    backend::send_clickable(title, message, sound)
+}
+
+/// Asks a yes/no question through an actionable notification with `yes` and
+/// `no` buttons, falling back to a dialog; blocks until answered
+pub fn ask(title: &str, message: &str, yes: &str, no: &str) -> bool {
+    backend::ask(title, message, yes, no)
+}
+
+fn outcome(sound: Sound) -> &'static str {
+    match sound {
+        Sound::Success => "success",
+        Sound::Failure | Sound::Muted => "failure",
+        Sound::Progress => "progress",
+    }
+}
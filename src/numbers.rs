@@ -0,0 +1,177 @@
+//! Locale-aware number normalization, so a table screenshotted from a
+//! European spreadsheet (`1.234,56`) comes out with plain decimal points
+//! (`1234.56`).
+
+/// How a locale writes numbers
+#[derive(Debug, Clone, Copy)]
+pub struct Locale {
+    /// Spellings of the thousands separator, longest first
+    groups: &'static [&'static str],
+    /// Spellings of the decimal separator, longest first
+    decimals: &'static [&'static str],
+}
+
+const POINT: &[&str] = &["."];
+/// `{,}` is how LaTeX sources usually keep a decimal comma from spacing out
+const COMMA: &[&str] = &["{,}", ","];
+const SPACES: &[&str] = &["\\,", "\\ ", "~", "\u{202f}", "\u{a0}", " "];
+
+impl Locale {
+    /// Looks up a locale by name, e.g. "de", "de_DE", "fr-CH" or "en"
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().replace('_', "-").to_lowercase();
+        let (language, region) = name.split_once('-').unwrap_or((&name, ""));
+        let locale = match (language, region) {
+            // Swiss German and Italian group with apostrophes
+            ("de" | "it", "ch") => Self { groups: &["'", "\u{2019}"], decimals: POINT },
+            ("en" | "ja" | "zh" | "ko" | "he" | "th", _) => Self { groups: &[","], decimals: POINT },
+            ("de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" | "el" | "ro", _) => {
+                Self { groups: &["."], decimals: COMMA }
+            }
+            ("fr" | "sv" | "nb" | "nn" | "no" | "fi" | "pl" | "cs" | "sk" | "ru" | "uk" | "hu", _) => {
+                Self { groups: SPACES, decimals: COMMA }
+            }
+            _ => return None,
+        };
+        Some(locale)
+    }
+
+    /// Rewrites every number written the locale's way with a decimal point and
+    /// no grouping. Comma-separated lists like `1,2,3` are left alone, since
+    /// they can't be decimals.
+    pub fn normalize(&self, latex: &str) -> String {
+        let mut normalized = String::with_capacity(latex.len());
+        let mut rest = latex;
+        let mut previous: Option<char> = None;
+        while let Some(c) = rest.chars().next() {
+            // Subscripts and superscripts like `x_{1,2}` are indices, not numbers
+            if (rest.starts_with("_{") || rest.starts_with("^{"))
+                && let Some(length) = braced_length(&rest[1..])
+            {
+                normalized.push_str(&rest[..1 + length]);
+                rest = &rest[1 + length..];
+                previous = Some('}');
+                continue;
+            }
+            // Only start at the first digit of a number, not in `x2` or `\alpha1`
+            let starts_number = c.is_ascii_digit()
+                && !previous.is_some_and(|p| p.is_alphanumeric() || p == '.' || p == ',' || p == '\'');
+            if starts_number && let Some((number, length)) = self.number(rest) {
+                normalized.push_str(&number);
+                rest = &rest[length..];
+                previous = number.chars().last();
+                continue;
+            }
+            normalized.push(c);
+            rest = &rest[c.len_utf8()..];
+            previous = Some(c);
+        }
+        normalized
+    }
+
+    /// The number at the start of `text` in normal form, with how many bytes it took
+    fn number(&self, text: &str) -> Option<(String, usize)> {
+        let mut digits = String::new();
+        let mut at = take_digits(text, &mut digits);
+        while let Some(separator) = starts_with_any(&text[at..], self.groups) {
+            let after = at + separator.len();
+            let group = text[after..].bytes().take_while(u8::is_ascii_digit).count();
+            if group != 3 {
+                break;
+            }
+            at = take_digits(&text[after..], &mut digits) + after;
+        }
+        if let Some(separator) = starts_with_any(&text[at..], self.decimals) {
+            let after = at + separator.len();
+            let mut fraction = String::new();
+            let end = after + take_digits(&text[after..], &mut fraction);
+            let is_list = starts_with_any(&text[end..], self.decimals)
+                .is_some_and(|next| text[end + next.len()..].starts_with(|c: char| c.is_ascii_digit()));
+            if !fraction.is_empty() && !is_list {
+                digits.push('.');
+                digits.push_str(&fraction);
+                at = end;
+            } else if is_list {
+                // Leave the whole list as it was written
+                return None;
+            }
+        }
+        Some((digits, at))
+    }
+}
+
+/// Length of the `{...}` group `text` starts with, nested braces included
+fn braced_length(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn take_digits(text: &str, into: &mut String) -> usize {
+    let count = text.bytes().take_while(u8::is_ascii_digit).count();
+    into.push_str(&text[..count]);
+    count
+}
+
+fn starts_with_any<'a>(text: &str, prefixes: &[&'a str]) -> Option<&'a str> {
+    prefixes.iter().copied().find(|prefix| text.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(locale: &str, latex: &str) -> String {
+        Locale::parse(locale).unwrap().normalize(latex)
+    }
+
+    #[test]
+    fn grouped_decimals_become_plain() {
+        assert_eq!(normalize("de", "1.234,56"), "1234.56");
+        assert_eq!(normalize("de_DE", "x = 1.234.567{,}8"), "x = 1234567.8");
+        assert_eq!(normalize("fr", "1\\,234,5"), "1234.5");
+        assert_eq!(normalize("de-CH", "1'234.5"), "1234.5");
+        assert_eq!(normalize("en", "1,234.56"), "1234.56");
+    }
+
+    #[test]
+    fn lists_are_left_alone() {
+        assert_eq!(normalize("de", "1,2,3"), "1,2,3");
+        assert_eq!(normalize("de", "\\{1,2,3\\}"), "\\{1,2,3\\}");
+    }
+
+    #[test]
+    fn indices_are_left_alone() {
+        assert_eq!(normalize("de", "x_{1,2} + y^{3,4}"), "x_{1,2} + y^{3,4}");
+        assert_eq!(normalize("de", "\\alpha1,5 + x2,5"), "\\alpha1,5 + x2,5");
+    }
+
+    #[test]
+    fn groups_need_three_digits() {
+        assert_eq!(normalize("de", "1.23"), "1.23");
+        assert_eq!(normalize("de", "2,5"), "2.5");
+    }
+
+    #[test]
+    fn unknown_locales_are_none() {
+        assert!(Locale::parse("xx").is_none());
+        assert!(Locale::parse("").is_none());
+    }
+}
@@ -0,0 +1,136 @@
+//! Local Ollama backend, for vision models like llava or qwen2.5vl running on
+//! this machine: conversions work offline and cost nothing.
+
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::time::Duration;
+
+use crate::provider::{self, BoxFuture, Chunk, Failure, Framing, OcrProvider, Reply, Request, Usage};
+use crate::response;
+
+/// Loading a model into memory on first use can take minutes, far longer
+/// than the client's usual timeout
+const TIMEOUT: Duration = Duration::from_secs(600);
+
+/// `OcrProvider` for models served by Ollama
+pub struct Ollama {
+    /// Server address, e.g. "http://localhost:11434"
+    pub url: String,
+}
+
+/// One line of a streamed `/api/chat` reply; the last has `done` and the counts
+#[derive(Debug, Deserialize)]
+struct StreamLine {
+    #[serde(default)]
+    message: Option<Message>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
+    /// Set instead when the model fails after streaming started
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    #[serde(default)]
+    content: String,
+}
+
+/// The body of a failed request
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+impl OcrProvider for Ollama {
+    fn name(&self) -> &'static str {
+        "Ollama"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn stream<'a>(&'a self, client: &'a reqwest::Client, request: &'a Request<'a>)
+    -> BoxFuture<'a, Result<Reply, Failure>> {
+        Box::pin(self.send(client, request))
+    }
+
+    fn price(&self, _model: &str) -> Option<(u64, u64)> {
+        Some((0, 0))
+    }
+
+    fn start_timeout(&self) -> Duration {
+        TIMEOUT
+    }
+}
+
+impl Ollama {
+    /// The `/api/chat` payload for `request`, the image attached to the message
+    fn payload(request: &Request) -> Value {
+        let mut message = json!({
+            "role": "user",
+            "content": request.prompt
+        });
+        if let Some(image) = request.image {
+            message["images"] = json!([BASE64.encode(image.data)]);
+        }
+        json!({
+            "model": request.model,
+            "stream": true,
+            "messages": [message],
+            "options": { "num_predict": request.limits.max_tokens }
+        })
+    }
+
+    async fn send(&self, client: &reqwest::Client, request: &Request<'_>) -> Result<Reply, Failure> {
+        let url = format!("{}/api/chat", self.url.trim_end_matches('/'));
+        let response = provider::post(client, &url)
+            .timeout(TIMEOUT)
+            .json(&Self::payload(request))
+            .send()
+            .await
+            .map_err(|e| if e.is_connect() {
+                format!("no Ollama server at {}, is `ollama serve` running? ({})", self.url, e).into()
+            } else {
+                Failure::from(e)
+            })?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Reply::framed(response, Framing::JsonLines, decode));
+        }
+        let body = response.text().await?;
+        Err(match serde_json::from_str::<ErrorResponse>(&body) {
+            // e.g. "model "llava" not found, try pulling it first"
+            Ok(failure) => format!("API request failed with status: {} ({})", status, failure.error),
+            Err(_) => format!("API request failed with status: {}", status),
+        }
+        .into())
+    }
+}
+
+fn decode(data: &str) -> Result<Vec<Chunk>, Box<dyn Error>> {
+    let line: StreamLine = response::parse(data)?;
+    if let Some(error) = line.error {
+        return Err(error.into());
+    }
+    let mut chunks: Vec<Chunk> = line
+        .message
+        .map(|message| message.content)
+        .filter(|text| !text.is_empty())
+        .map(Chunk::Text)
+        .into_iter()
+        .collect();
+    if line.done {
+        chunks.push(Chunk::Usage(Usage { input_tokens: line.prompt_eval_count, output_tokens: line.eval_count }));
+        chunks.push(Chunk::Done);
+    }
+    Ok(chunks)
+}
@@ -0,0 +1,203 @@
+//! OpenAI Chat Completions backend (GPT-4o and the like), also usable with
+//! servers that speak the same API.
+
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::error::Error;
+
+use crate::provider::{self, BoxFuture, Chunk, Failure, OcrProvider, Reply, Request, Usage};
+use crate::response;
+
+/// Models that reason before answering, which take a `reasoning_effort`
+const REASONING_MODELS: &[&str] = &["o1", "o3", "o4", "gpt-5"];
+
+/// `OcrProvider` for OpenAI models
+pub struct OpenAi {
+    pub api_key: String,
+    /// Chat Completions endpoint
+    pub url: String,
+}
+
+/// One streamed chunk; the last one before `[DONE]` has the usage and no choices
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<Choice>,
+    #[serde(default)]
+    usage: Option<ChunkUsage>,
+    /// Set instead when the request fails after streaming started
+    #[serde(default)]
+    error: Option<ApiError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    #[serde(default)]
+    delta: Delta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Delta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+/// The body of a failed request
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ApiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    message: String,
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    #[serde(default)]
+    code: Option<String>,
+}
+
+impl OcrProvider for OpenAi {
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn stream<'a>(&'a self, client: &'a reqwest::Client, request: &'a Request<'a>)
+    -> BoxFuture<'a, Result<Reply, Failure>> {
+        Box::pin(self.send(client, request))
+    }
+}
+
+impl OpenAi {
+    /// The Chat Completions payload for `request`, the image as a data URL.
+    /// Reasoning models are given an effort for the thinking budget instead,
+    /// other models ignore it
+    fn payload(request: &Request) -> Value {
+        let content = match request.image {
+            Some(image) => json!([
+                {
+                    "type": "text",
+                    "text": request.prompt
+                },
+                {
+                    "type": "image_url",
+                    "image_url": {
+                        "url": format!("data:{};base64,{}", image.media_type, BASE64.encode(image.data))
+                    }
+                }
+            ]),
+            None => json!(request.prompt),
+        };
+        let mut payload = json!({
+            "model": request.model,
+            "stream": true,
+            "stream_options": { "include_usage": true },
+            "max_tokens": request.limits.max_tokens,
+            "messages": [
+                {
+                    "role": "user",
+                    "content": content
+                }
+            ]
+        });
+        // Reasoning models only take the output limit as `max_completion_tokens`,
+        // and their reasoning counts towards it
+        if is_reasoning(request.model) {
+            let budget = request.limits.thinking_budget;
+            payload.as_object_mut().expect("the payload is an object").remove("max_tokens");
+            payload["max_completion_tokens"] = json!(request.limits.max_tokens + budget.unwrap_or(0));
+            if let Some(budget) = budget {
+                payload["reasoning_effort"] = json!(reasoning_effort(budget));
+            }
+        }
+        if let Some(tag) = provider::client_tag() {
+            payload["user"] = json!(tag);
+        }
+        payload
+    }
+
+    async fn send(&self, client: &reqwest::Client, request: &Request<'_>) -> Result<Reply, Failure> {
+        let response = provider::post(client, &self.url)
+            .bearer_auth(&self.api_key)
+            .json(&Self::payload(request))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Reply::new(response, decode));
+        }
+        let retry_after = provider::retry_after(response.headers());
+        let body = response.text().await?;
+        let failure = serde_json::from_str::<ErrorResponse>(&body);
+        // Rate limits pass, an exhausted quota doesn't
+        let out_of_quota = failure
+            .as_ref()
+            .is_ok_and(|failure| failure.error.code.as_deref() == Some("insufficient_quota"));
+        if (status.as_u16() == 429 && !out_of_quota) || status.as_u16() == 503 {
+            return Err(Failure::Overloaded { retry_after });
+        }
+        Err(match failure {
+            Ok(failure) => format!(
+                "API request failed with status: {} ({}: {})",
+                status,
+                failure.error.code.or(failure.error.kind).unwrap_or_else(|| "error".to_string()),
+                failure.error.message
+            ),
+            Err(_) => format!("API request failed with status: {}", status),
+        }
+        .into())
+    }
+}
+
+fn is_reasoning(model: &str) -> bool {
+    REASONING_MODELS.iter().any(|prefix| {
+        model.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with(['-', '.']))
+    })
+}
+
+/// The effort closest to spending `budget` tokens on reasoning
+fn reasoning_effort(budget: u32) -> &'static str {
+    match budget {
+        0..4096 => "low",
+        4096..16384 => "medium",
+        _ => "high",
+    }
+}
+
+fn decode(data: &str) -> Result<Vec<Chunk>, Box<dyn Error>> {
+    if data.trim() == "[DONE]" {
+        return Ok(vec![Chunk::Done]);
+    }
+    let chunk: StreamChunk = response::parse(data)?;
+    if let Some(error) = chunk.error {
+        return Err(error.message.into());
+    }
+    let mut chunks: Vec<Chunk> = chunk
+        .choices
+        .into_iter()
+        .filter_map(|choice| choice.delta.content)
+        .filter(|text| !text.is_empty())
+        .map(Chunk::Text)
+        .collect();
+    if let Some(usage) = chunk.usage {
+        chunks.push(Chunk::Usage(Usage {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+        }));
+    }
+    Ok(chunks)
+}
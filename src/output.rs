@@ -0,0 +1,189 @@
+//! Where results go: the clipboard unless `[[outputs]]` are configured, each
+//! rendering the result in its own format.
+
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::Duration;
+
+use crate::clipboard;
+use crate::format::Format;
+use crate::pipeline::Conversion;
+use crate::settings::{AppConfig, Profile};
+use crate::target::Target;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Clipboard,
+    /// Append to a file
+    File,
+    /// POST JSON to a URL
+    Webhook,
+}
+
+/// One `[[outputs]]` entry
+#[derive(Debug, Clone, Deserialize)]
+pub struct Output {
+    pub kind: Kind,
+    /// File results are appended to (`file`)
+    #[serde(default)]
+    pub path: String,
+    /// Endpoint receiving the results (`webhook`)
+    #[serde(default)]
+    pub url: String,
+    /// Replaces the profile's or top-level `template` for this output
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Replaces the profile's or top-level `format` for this output
+    #[serde(default)]
+    pub format: Option<Format>,
+    /// Replaces the top-level `provenance` for this output (file outputs only)
+    #[serde(default)]
+    pub provenance: Option<bool>,
+}
+
+impl Output {
+    fn clipboard() -> Self {
+        Self {
+            kind: Kind::Clipboard,
+            path: String::new(),
+            url: String::new(),
+            template: None,
+            format: None,
+            provenance: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self.kind {
+            Kind::File if self.path.trim().is_empty() => Err("file output needs a path".to_string()),
+            Kind::Webhook if self.url.trim().is_empty() => Err("webhook output needs a url".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Where this output goes, for notifications
+    fn destination(&self) -> &str {
+        match self.kind {
+            Kind::Clipboard => clipboard::DESTINATION,
+            Kind::File => &self.path,
+            Kind::Webhook => &self.url,
+        }
+    }
+
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        config: &AppConfig,
+        conversions: &[Conversion],
+        template: Option<&str>,
+        format: Format,
+        target: Target,
+    ) -> Result<(), Box<dyn Error>> {
+        let template = self.template.as_deref().or(template);
+        let format = self.format.unwrap_or(format);
+        let text = render_all(conversions, template, format)?;
+        match self.kind {
+            Kind::Clipboard => {
+                let alts: Vec<&str> = conversions
+                    .iter()
+                    .filter_map(|conversion| conversion.alt_text.as_deref())
+                    .collect();
+                let alt = Some(alts.join(" ")).filter(|alt| !alt.is_empty());
+                clipboard::copy_with(&target.apply(&text), alt.as_deref(), &config.clipboard_options())
+            }
+            Kind::File => {
+                // Provenance travels with snippets in files, never on the clipboard
+                let text = if self.provenance.unwrap_or(config.provenance) {
+                    let blocks = conversions
+                        .iter()
+                        .map(|conversion| {
+                            let text = conversion.render(template, format)?;
+                            Ok(format!("{}\n{}", conversion.provenance(format), text.trim()))
+                        })
+                        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+                    blocks.join("\n\n")
+                } else {
+                    text
+                };
+                let path = shellexpand::tilde(&self.path).to_string();
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}\n", text.trim_end())?;
+                Ok(())
+            }
+            Kind::Webhook => {
+                let results: Vec<_> = conversions
+                    .iter()
+                    .map(|conversion| {
+                        json!({
+                            "latex": conversion.latex.trim(),
+                            "source": conversion.source,
+                            "glossary": conversion.glossary,
+                            "alt_text": conversion.alt_text,
+                            "image": conversion.image,
+                        })
+                    })
+                    .collect();
+                client
+                    .post(&self.url)
+                    .json(&json!({ "text": text, "results": results }))
+                    .timeout(Duration::from_secs(30))
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Renders every conversion with `template` or in `format` and joins them in order
+pub fn render_all(
+    conversions: &[Conversion],
+    template: Option<&str>,
+    format: Format,
+) -> Result<String, Box<dyn Error>> {
+    let rendered = conversions
+        .iter()
+        .map(|conversion| conversion.render(template, format).map(|text| text.trim().to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rendered.join("\n\n"))
+}
+
+/// Sends `conversions` to every configured output; returns the destinations
+/// that succeeded and the failures
+pub async fn deliver(
+    client: &reqwest::Client,
+    config: &AppConfig,
+    profile: &Profile,
+    conversions: &[Conversion],
+) -> (Vec<String>, Vec<(String, Box<dyn Error>)>) {
+    let default_outputs = [Output::clipboard()];
+    let outputs = if config.outputs.is_empty() {
+        &default_outputs[..]
+    } else {
+        &config.outputs[..]
+    };
+    let template = config.template(profile);
+    let format = config.format(profile);
+    // Looking for the frontmost app is slow, only do it when there's a clipboard to write
+    // Clipboard targets rewrite LaTeX, which results in Typst or AsciiMath aren't
+    let target = if format.is_latex() && outputs.iter().any(|output| output.kind == Kind::Clipboard) {
+        config.clipboard_target(profile)
+    } else {
+        Target::Latex
+    };
+
+    let mut delivered = Vec::new();
+    let mut failed = Vec::new();
+    for output in outputs {
+        match output.send(client, config, conversions, template, format, target).await {
+            Ok(()) => delivered.push(output.destination().to_string()),
+            Err(e) => failed.push((output.destination().to_string(), e)),
+        }
+    }
+    (delivered, failed)
+}
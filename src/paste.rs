@@ -0,0 +1,43 @@
+//! Pasting into the frontmost app for `latex_ocr paste`.
+
+use std::error::Error;
+
+/// Presses Cmd-V so the frontmost app pastes what was just copied; needs the
+/// Accessibility permission
+#[cfg(all(feature = "gui", target_os = "macos"))]
+pub fn into_frontmost() -> Result<(), Box<dyn Error>> {
+    use core_graphics::event::{CGEvent, CGEventFlags, CGEventTapLocation, CGKeyCode};
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+
+    // kVK_ANSI_V
+    const KEY_V: CGKeyCode = 9;
+
+    let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+        .map_err(|_| "failed to create keyboard event source")?;
+    for key_down in [true, false] {
+        let event = CGEvent::new_keyboard_event(source.clone(), KEY_V, key_down)
+            .map_err(|_| "failed to create keyboard event")?;
+        event.set_flags(CGEventFlags::CGEventFlagCommand);
+        event.post(CGEventTapLocation::HID);
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "gui", not(target_os = "macos")))]
+pub fn into_frontmost() -> Result<(), Box<dyn Error>> {
+    let status = std::process::Command::new("xdotool")
+        .args(["key", "--clearmodifiers", "ctrl+v"])
+        .status()
+        .map_err(|e| format!("pasting needs xdotool: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("xdotool exited with {}", status).into())
+    }
+}
+
+/// Headless builds print the result instead, there is nothing to paste into
+#[cfg(not(feature = "gui"))]
+pub fn into_frontmost() -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! Locations of the tool's config, history and runtime state.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// `~/.config/latex_ocr`, created on first use
+pub fn config_dir() -> PathBuf {
+    ensure(home_subdir(&[".config", "latex_ocr"]))
+}
+
+/// `~/.local/state/latex_ocr` (or `$XDG_STATE_HOME/latex_ocr`), for files
+/// shared between concurrently running instances
+pub fn state_dir() -> PathBuf {
+    let dir = match std::env::var_os("XDG_STATE_HOME") {
+        Some(base) if !base.is_empty() => PathBuf::from(base).join("latex_ocr"),
+        _ => home_subdir(&[".local", "state", "latex_ocr"]),
+    };
+    ensure(dir)
+}
+
+/// `~/.local/share/latex_ocr` (or `$XDG_DATA_HOME/latex_ocr`), for the history
+pub fn data_dir() -> PathBuf {
+    let dir = match std::env::var_os("XDG_DATA_HOME") {
+        Some(base) if !base.is_empty() => PathBuf::from(base).join("latex_ocr"),
+        _ => home_subdir(&[".local", "share", "latex_ocr"]),
+    };
+    ensure(dir)
+}
+
+/// `~/.cache/latex_ocr` (or `$XDG_CACHE_HOME/latex_ocr`), for results that
+/// can be thrown away
+pub fn cache_dir() -> PathBuf {
+    let dir = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(base) if !base.is_empty() => PathBuf::from(base).join("latex_ocr"),
+        _ => home_subdir(&[".cache", "latex_ocr"]),
+    };
+    ensure(dir)
+}
+
+fn home_subdir(components: &[&str]) -> PathBuf {
+    match home::home_dir() {
+        Some(home_dir) => components.iter().fold(home_dir, |path, part| path.join(part)),
+        None => PathBuf::from("."), // Fallback to current directory
+    }
+}
+
+fn ensure(dir: PathBuf) -> PathBuf {
+    if !dir.exists() {
+        let _ = fs::create_dir_all(&dir);
+    }
+    dir
+}
+
+/// Where the system saves screenshots: the `com.apple.screencapture location`
+/// default on macOS (the Desktop when it isn't set), `~/Downloads` elsewhere
+pub fn screenshot_dir() -> PathBuf {
+    #[cfg(target_os = "macos")]
+    {
+        let configured = std::process::Command::new("defaults")
+            .args(["read", "com.apple.screencapture", "location"])
+            .stderr(std::process::Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .filter(|location| !location.is_empty());
+        if let Some(location) = configured {
+            let dir = PathBuf::from(shellexpand::tilde(&location).as_ref());
+            if dir.is_dir() {
+                return dir;
+            }
+        }
+        home_subdir(&["Desktop"])
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        home_subdir(&["Downloads"])
+    }
+}
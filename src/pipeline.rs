@@ -0,0 +1,748 @@
+//! The conversion itself: prompt assembly, the API request and turning the
+//! reply into the final result.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use latex_ocr::postprocess::{self, Transform};
+use latex_ocr::stitch::{self, Tile};
+
+use crate::cache;
+use crate::cancel;
+use crate::events;
+use crate::discovery;
+use crate::document;
+use crate::document_class;
+use crate::escalation;
+use crate::history;
+use crate::format::Format;
+use crate::language::{self, Rtl};
+use crate::numbers::Locale;
+use crate::preprocess;
+use crate::provider::{self, Limits, OcrProvider, Request, Usage};
+use crate::redact;
+use crate::watermark;
+use crate::settings::{AppConfig, Profile};
+use crate::shrink;
+use crate::template;
+use crate::trailer;
+use crate::validate;
+
+const SOURCE_KEY: &str = "source";
+const SOURCE_DESCRIPTION: &str = "the citation, DOI or arXiv identifier of the document shown, if one is visible";
+const SYMBOL_KEY: &str = "symbol";
+const SYMBOL_DESCRIPTION: &str = "a symbol used in the LaTeX and its likely meaning, as `symbol | meaning` with the symbol in LaTeX; one line per symbol";
+
+const ALT_KEY: &str = "alt";
+const ALT_DESCRIPTION: &str = "a plain-English description of the content for screen reader alt text, e.g. \"x squared plus y squared equals r squared\", on one line";
+const CONFIDENCE_KEY: &str = "confidence";
+const CONFIDENCE_DESCRIPTION: &str = "how confident you are that the transcription is exactly right, as a whole number from 0 to 100";
+const LANGUAGE_KEY: &str = "language";
+const LANGUAGE_DESCRIPTION: &str = "the babel name of the language any prose is written in (e.g. ngerman, french, spanish), if there is prose and it isn't English";
+
+/// Models sometimes reverse right-to-left text into visual order
+const RTL_INSTRUCTIONS: &str = " Write any Arabic or Hebrew text in logical (reading) order as plain text, without reversing it or adding direction markup.";
+
+/// Pause before silently retrying a failed conversion
+const RETRY_PAUSE: std::time::Duration = std::time::Duration::from_secs(2);
+
+const LAYOUT_INSTRUCTIONS: &str = " Keep the page's layout instead of writing everything as one stream: put text set in two or more columns in a multicols environment (from the multicol package), blocks placed side by side in minipages, figures and tables in figure and table environments where they appear, with [t], [b] or [h] after where they sit on the page and any caption, and footnotes as \\footnote{...} at the mark they belong to.";
+
+const CONTEXT_INTRO: &str = "\n\nContext for resolving ambiguous symbols; reuse its notation where it applies but don't transcribe it:\n";
+
+const CORRECTIONS_INTRO: &str = "\n\nEarlier results of yours that were corrected by hand; write the same notation the same way:";
+
+/// A converted image, rendered per output when it is delivered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversion {
+    pub latex: String,
+    /// Citation extracted with `cite_source`
+    pub source: Option<String>,
+    /// `(symbol, meaning)` pairs from the glossary mode
+    pub glossary: Vec<(String, String)>,
+    /// Plain-English description for screen readers, asked for with `alt_text`
+    pub alt_text: Option<String>,
+    /// How sure the model was of the result (0-100), asked for with `review_below`
+    pub confidence: Option<u32>,
+    /// Babel name of the prose language found with `detect_language`, never English
+    pub language: Option<String>,
+    /// Path of the converted image, empty for input that isn't one
+    pub image: String,
+    /// SHA-256 of the image as uploaded, empty for input that isn't one
+    pub image_hash: String,
+    pub model: String,
+    /// Profile and mode it was converted with, see `Profile::describe`
+    pub profile: String,
+    /// The prompt sent with the image
+    #[serde(default)]
+    pub prompt: String,
+    /// Tokens the request that produced it used
+    #[serde(default)]
+    pub usage: Usage,
+    /// The model's LaTeX, when `latex` is a correction of it made in review
+    #[serde(default)]
+    pub original: Option<String>,
+}
+
+impl Conversion {
+    /// Comment lines recording where the result came from
+    pub fn provenance(&self, format: Format) -> String {
+        let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let fields = [
+            ("converted", time.as_str()),
+            ("image", self.image.as_str()),
+            ("model", self.model.as_str()),
+            ("profile", self.profile.as_str()),
+            ("sha256", self.image_hash.as_str()),
+        ];
+        let lines: Vec<String> = fields
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(key, value)| format.comment(&format!("{}: {}", key, value)))
+            .collect();
+        lines.join("\n")
+    }
+
+    /// The result as delivered: wrapped in `template` if there is one, otherwise
+    /// written in `format` followed by any `source:` and `alt:` comments
+    pub fn render(&self, template: Option<&str>, format: Format) -> Result<String, Box<dyn Error>> {
+        if let Some(template) = template {
+            let fields = template::Fields {
+                latex: self.latex.trim(),
+                source: self.source.as_deref().unwrap_or(""),
+                image: &self.image,
+                glossary: &self.glossary,
+                language: self.language.as_deref().unwrap_or(""),
+                alt: self.alt_text.as_deref().unwrap_or(""),
+            };
+            return template::render(template, &fields).map_err(|e| format!("template: {}", e).into());
+        }
+        let mut text = match &self.language {
+            Some(language) if format == Format::Latex && !document::is_bare_math(&self.latex) => {
+                language::wrap(&self.latex, language)
+            }
+            _ => format.apply(&self.latex),
+        };
+        if !self.glossary.is_empty() {
+            text.push_str("\n\n");
+            text.push_str(&format.glossary(&self.glossary));
+        }
+        if let Some(source) = &self.source {
+            text.push('\n');
+            text.push_str(&format.comment(&format!("source: {}", source)));
+        }
+        if let Some(alt) = &self.alt_text {
+            text.push('\n');
+            text.push_str(&format.comment(&format!("alt: {}", alt)));
+        }
+        Ok(text)
+    }
+}
+
+/// Everything a conversion needs besides the image
+pub struct Converter<'a> {
+    pub client: &'a reqwest::Client,
+    pub config: &'a AppConfig,
+    pub profile: &'a Profile,
+    /// Extra text that helps read the image, see `context::Context`
+    pub context: Option<&'a str>,
+}
+
+impl Converter<'_> {
+    fn cite_source(&self) -> bool {
+        self.profile.cite_source.unwrap_or(self.config.cite_source)
+    }
+
+    fn preserve_layout(&self) -> bool {
+        self.profile.preserve_layout.unwrap_or(self.config.preserve_layout)
+    }
+
+    fn model(&self) -> &str {
+        self.profile.model.as_deref().unwrap_or(&self.config.model)
+    }
+
+    /// How numbers in the reply are written, if they should be normalized
+    fn number_locale(&self) -> Option<Locale> {
+        let name = self.profile.number_locale.as_deref().unwrap_or(&self.config.number_locale);
+        Locale::parse(name)
+    }
+
+    fn format(&self) -> Format {
+        self.config.format(self.profile)
+    }
+
+    fn glossary(&self) -> bool {
+        self.profile.glossary.unwrap_or(false)
+    }
+
+    /// Extended thinking budget, `None` when off (a budget of 0)
+    fn thinking_budget(&self) -> Option<u32> {
+        let budget = self.profile.thinking_budget.unwrap_or(self.config.thinking_budget);
+        Some(budget).filter(|budget| *budget > 0)
+    }
+
+    /// Everything besides the image, model and prompt that shapes a result,
+    /// so a cached one is only reused where asking again would give the same
+    fn cache_settings(&self) -> String {
+        let config = self.config;
+        let upload = (
+            config.upload_trim,
+            config.upload_grayscale,
+            config.upload_max_dimension,
+            config.upload_format.trim(),
+            config.upload_quality,
+        );
+        format!(
+            "{:?}",
+            (
+                config.provider,
+                self.thinking_budget(),
+                self.format(),
+                &config.postprocess,
+                self.number_locale(),
+                config.accents,
+                config.rtl_markup,
+                &config.document_class,
+                &self.profile.validate,
+                (config.tile_height, config.tile_overlap),
+                upload,
+            )
+        )
+    }
+
+    /// The prompt sent with the image, including any trailer requests
+    pub fn prompt(&self) -> String {
+        let mut prompt = self
+            .profile
+            .prompt
+            .clone()
+            .unwrap_or_else(|| self.config.prompt.clone());
+        if let Some(instructions) = self.format().instructions() {
+            prompt.push_str(instructions);
+        }
+        if self.config.rtl_markup != Rtl::None && self.format().is_latex() {
+            prompt.push_str(RTL_INSTRUCTIONS);
+        }
+        if self.preserve_layout() && self.format().is_latex() {
+            prompt.push_str(LAYOUT_INSTRUCTIONS);
+        }
+        if let Some(instructions) = document_class::instructions(&self.config.document_class)
+            && self.format().is_latex()
+        {
+            prompt.push_str(&instructions);
+        }
+        if let Some(context) = self.context {
+            prompt.push_str(&format!("{}{}", CONTEXT_INTRO, context));
+        }
+        let corrections = history::corrections(self.config.correction_examples);
+        if !corrections.is_empty() {
+            prompt.push_str(CORRECTIONS_INTRO);
+            for (original, corrected) in corrections {
+                prompt.push_str(&format!("\nYou wrote: {}\nCorrected: {}", original, corrected));
+            }
+        }
+        let mut trailer_items = Vec::new();
+        if self.cite_source() {
+            trailer_items.push((SOURCE_KEY, SOURCE_DESCRIPTION));
+        }
+        if self.glossary() {
+            trailer_items.push((SYMBOL_KEY, SYMBOL_DESCRIPTION));
+        }
+        if self.config.alt_text {
+            trailer_items.push((ALT_KEY, ALT_DESCRIPTION));
+        }
+        if self.config.review_below > 0 {
+            trailer_items.push((CONFIDENCE_KEY, CONFIDENCE_DESCRIPTION));
+        }
+        if self.config.detect_language {
+            trailer_items.push((LANGUAGE_KEY, LANGUAGE_DESCRIPTION));
+        }
+        if !trailer_items.is_empty() {
+            prompt.push_str(&trailer::instructions(&trailer_items));
+        }
+        prompt
+    }
+
+    /// Sends the conversion request, racing `race_model` against the model
+    /// when one is set; returns `None` if Ctrl-C or `latex_ocr cancel` aborted it
+    pub async fn convert(
+        &self,
+        image_data: &[u8],
+        image_path: &str,
+    ) -> Option<Result<Conversion, Box<dyn Error>>> {
+        let in_flight = cancel::InFlight::begin();
+        let prompt = self.prompt();
+        let settings = self.cache_settings();
+        if let Some(cached) = cache::lookup(image_data, self.model(), &prompt, &settings, image_path) {
+            events::emit("cached", serde_json::json!({ "image": image_path, "model": cached.model }));
+            eprintln!("{} was converted before, using that result (--no-cache asks again)", image_path);
+            escalation::succeeded();
+            return Some(Ok(cached));
+        }
+        let rival = Some(self.config.race_model.as_str())
+            .filter(|rival| !rival.trim().is_empty() && *rival != self.model());
+        let provider = self.config.provider();
+        let result = tokio::select! {
+            result = self.retried(&*provider, self.model(), rival, image_data, image_path, &prompt) => result,
+            // Offline the request would only fail once it timed out
+            e = provider::unreachable(&*provider) => match self.config.offline_provider() {
+                Some(local) => {
+                    let model = &self.config.offline_model;
+                    events::emit(
+                        "offline",
+                        serde_json::json!({ "image": image_path, "model": model, "error": e.to_string() }),
+                    );
+                    self.retried(&*local, model, None, image_data, image_path, &prompt).await
+                }
+                None => Err(e),
+            },
+            _ = in_flight.cancelled() => {
+                events::emit("cancelled", serde_json::json!({ "image": image_path }));
+                return None;
+            }
+        };
+        if let Ok(conversion) = &result {
+            // An offline fallback's result shouldn't stand in for the model's later
+            if conversion.model == self.model() || rival == Some(conversion.model.as_str()) {
+                cache::store(image_data, self.model(), &prompt, &settings, conversion);
+            }
+            escalation::succeeded();
+        }
+        Some(result)
+    }
+
+    /// Requests with `model`, raced against `rival` if given, retried up to
+    /// `failure_retries` times
+    async fn retried(
+        &self,
+        provider: &dyn OcrProvider,
+        model: &str,
+        rival: Option<&str>,
+        image_data: &[u8],
+        image_path: &str,
+        prompt: &str,
+    ) -> Result<Conversion, Box<dyn Error>> {
+        let run = || async {
+            let attempt = self.attempt(provider, model, image_data, image_path, prompt);
+            let Some(rival) = rival else {
+                return attempt.await;
+            };
+            let result = race(attempt, self.attempt(provider, rival, image_data, image_path, prompt)).await;
+            if let Ok(conversion) = &result {
+                events::emit("race_won", serde_json::json!({ "image": image_path, "model": conversion.model }));
+            }
+            result
+        };
+        let mut result = run().await;
+        for retry in 1..=self.config.failure_retries {
+            let Err(e) = &result else {
+                break;
+            };
+            events::emit(
+                "retry",
+                serde_json::json!({ "image": image_path, "retry": retry, "error": e.to_string() }),
+            );
+            tokio::time::sleep(RETRY_PAUSE).await;
+            result = run().await;
+        }
+        result
+    }
+
+    /// One request with `model`, or one per tile of a tall image, accepted
+    /// only if it passes the profile's validator
+    async fn attempt(
+        &self,
+        provider: &dyn OcrProvider,
+        model: &str,
+        image_data: &[u8],
+        image_path: &str,
+        prompt: &str,
+    ) -> Result<Conversion, Box<dyn Error>> {
+        let conversion = match preprocess::tiles(image_data, self.config.tile_height, self.config.tile_overlap) {
+            Some(tiles) => self.stitched(provider, model, &tiles, image_data, image_path, prompt).await?,
+            None => {
+                let (reply, usage) = self.request(provider, model, image_data, image_path, prompt).await?;
+                Conversion {
+                    prompt: prompt.to_string(),
+                    usage,
+                    ..self.finish(&reply, model, image_data, image_path)
+                }
+            }
+        };
+        if let Some(validator) = &self.profile.validate {
+            validate::apply(validator, &conversion.latex)?;
+        }
+        Ok(conversion)
+    }
+
+    /// The tiles of a tall image converted one after the other, their results
+    /// merged where they overlap
+    async fn stitched(
+        &self,
+        provider: &dyn OcrProvider,
+        model: &str,
+        tiles: &[Vec<u8>],
+        image_data: &[u8],
+        image_path: &str,
+        prompt: &str,
+    ) -> Result<Conversion, Box<dyn Error>> {
+        let mut parts = Vec::new();
+        let mut usage = Usage::default();
+        for (index, tile) in tiles.iter().enumerate() {
+            events::emit(
+                "tile",
+                serde_json::json!({ "image": image_path, "tile": index + 1, "tiles": tiles.len() }),
+            );
+            let (reply, tile_usage) = self.request(provider, model, tile, image_path, prompt).await?;
+            usage.input_tokens += tile_usage.input_tokens;
+            usage.output_tokens += tile_usage.output_tokens;
+            parts.push(self.finish(&reply, model, tile, image_path));
+        }
+        let merged: Vec<Tile> = parts
+            .iter()
+            .map(|part| Tile { latex: &part.latex, confidence: part.confidence })
+            .collect();
+        let latex = stitch::merge(&merged);
+        let confidence = parts.iter().filter_map(|part| part.confidence).min();
+        let first = parts.swap_remove(0);
+        Ok(Conversion {
+            latex,
+            confidence,
+            image_hash: sha256_hex(image_data),
+            prompt: prompt.to_string(),
+            usage,
+            ..first
+        })
+    }
+
+    /// Sends `image_data` to `model`, returning the reply and what it used
+    async fn request(
+        &self,
+        provider: &dyn OcrProvider,
+        model: &str,
+        image_data: &[u8],
+        image_path: &str,
+        prompt: &str,
+    ) -> Result<(String, Usage), Box<dyn Error>> {
+        let shrunk;
+        let image_data = if shrink::enabled() {
+            shrunk = shrink::apply(image_data).map_err(|e| format!("Shrinking the image failed: {}", e))?;
+            events::emit(
+                "shrunk",
+                serde_json::json!({ "image": image_path, "bytes": image_data.len(), "sent": shrunk.len() }),
+            );
+            &shrunk[..]
+        } else {
+            image_data
+        };
+        events::emit(
+            "request",
+            serde_json::json!({ "image": image_path, "model": model, "profile": self.profile.describe() }),
+        );
+        let started = std::time::Instant::now();
+        let limits = Limits { max_tokens: provider::MAX_TOKENS, thinking_budget: self.thinking_budget() };
+        let request = Request::image(model, prompt, image_data, image_path, limits);
+        let reply = provider::complete_with_usage(provider, self.client, &request).await;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match &reply {
+            Ok((reply, _)) => events::emit(
+                "response",
+                serde_json::json!({ "image": image_path, "model": model, "ms": elapsed_ms, "chars": reply.len() }),
+            ),
+            Err(e) => events::emit(
+                "request_failed",
+                serde_json::json!({ "image": image_path, "model": model, "ms": elapsed_ms, "error": e.to_string() }),
+            ),
+        }
+        reply
+    }
+
+    /// Runs `postprocess` over the raw reply and splits the trailer off it
+    fn finish(&self, reply: &str, model: &str, image_data: &[u8], image_path: &str) -> Conversion {
+        let transforms: Vec<Transform> = self
+            .config
+            .postprocess
+            .iter()
+            .filter_map(|name| Transform::parse(name))
+            .filter(|transform| self.format().is_latex() || !transform.is_latex_only())
+            .collect();
+        let (latex, items) = trailer::split(&postprocess::apply(&transforms, reply));
+        // The rest of the cleanup is for LaTeX, which Typst and AsciiMath results aren't
+        let latex = if self.format().is_latex() {
+            let latex = match self.number_locale() {
+                Some(locale) => locale.normalize(&latex),
+                None => latex,
+            };
+            let latex = language::apply(&latex, self.config.accents);
+            let latex = language::mark_rtl(&latex, self.config.rtl_markup);
+            document_class::adapt(&latex, &self.config.document_class)
+        } else {
+            latex
+        };
+        let alt_text = if self.config.alt_text {
+            trailer::value(&items, ALT_KEY).map(str::to_string)
+        } else {
+            None
+        };
+        let confidence = trailer::value(&items, CONFIDENCE_KEY)
+            .and_then(|value| value.trim().trim_end_matches('%').parse().ok());
+        let language = if self.config.detect_language {
+            trailer::value(&items, LANGUAGE_KEY).and_then(language::babel_name)
+        } else {
+            None
+        };
+        let source = if self.cite_source() {
+            trailer::value(&items, SOURCE_KEY).map(str::to_string)
+        } else {
+            None
+        };
+        let glossary = if self.glossary() {
+            trailer::values(&items, SYMBOL_KEY)
+                .into_iter()
+                .filter_map(|entry| entry.split_once('|'))
+                .map(|(symbol, meaning)| (symbol.trim().to_string(), meaning.trim().to_string()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Conversion {
+            latex,
+            source,
+            glossary,
+            alt_text,
+            confidence,
+            language,
+            image: image_path.to_string(),
+            image_hash: sha256_hex(image_data),
+            model: model.to_string(),
+            profile: self.profile.describe(),
+            prompt: String::new(),
+            usage: Usage::default(),
+            original: None,
+        }
+    }
+}
+
+/// The first of two attempts to succeed; the other is dropped, which cancels
+/// its request. Fails only if both do, with the later error
+async fn race<T, E>(
+    first: impl Future<Output = Result<T, E>>,
+    second: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    tokio::pin!(first);
+    tokio::pin!(second);
+    tokio::select! {
+        result = &mut first => match result {
+            Ok(value) => Ok(value),
+            Err(_) => second.await,
+        },
+        result = &mut second => match result {
+            Ok(value) => Ok(value),
+            Err(_) => first.await,
+        },
+    }
+}
+
+/// Hex SHA-256 of `data`
+pub fn sha256_hex(data: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Reads an image once it has been fully written and runs the profile's
+/// preprocessing stage over it
+pub async fn read_image(path: &Path, stage: Option<&str>) -> Result<Vec<u8>, String> {
+    if !discovery::settle(path).await && path.exists() {
+        eprintln!("{} is still changing, reading it anyway", path.display());
+    }
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    prepare_image(data, stage)
+}
+
+/// Strips watermarks and runs the profile's preprocessing stage over image
+/// bytes obtained elsewhere, then masks what `redact` lists
+pub fn prepare_image(data: Vec<u8>, stage: Option<&str>) -> Result<Vec<u8>, String> {
+    let data = if watermark::enabled() {
+        watermark::apply(&data).map_err(|e| format!("Stripping the watermark failed: {}", e))?
+    } else {
+        data
+    };
+    let data = match stage {
+        Some(stage) => preprocess::apply(stage, &data)
+            .map_err(|e| format!("Preprocessing failed: {}", e))?,
+        None => data,
+    };
+    if !redact::enabled() {
+        return Ok(data);
+    }
+    redact::apply(&data).map_err(|e| format!("Redaction failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockApi;
+    use crate::provider::Backend;
+    use std::time::Duration;
+
+    const IMAGE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const LATEX: &str = "\\int_0^1 x^2 \\, dx = \\frac{1}{3}";
+
+    /// One conversion of `IMAGE` through `backend`, as the mock server answers it
+    async fn convert(api: &MockApi, backend: Backend) -> Result<Conversion, Box<dyn Error>> {
+        let config = api.config(backend);
+        let profile = Profile::default();
+        let client = reqwest::Client::new();
+        let converter = Converter { client: &client, config: &config, profile: &profile, context: None };
+        converter.attempt(&*config.provider(), &config.model, IMAGE, "integral.png", &converter.prompt()).await
+    }
+
+    #[tokio::test]
+    async fn anthropic_reply_is_streamed_into_the_result() {
+        let api = MockApi::start().await;
+        api.stream("anthropic_success.sse").await;
+        let conversion = convert(&api, Backend::Anthropic).await.unwrap();
+        assert_eq!(conversion.latex, LATEX);
+        assert_eq!(conversion.image, "integral.png");
+        assert_eq!(conversion.image_hash, sha256_hex(IMAGE));
+        assert_eq!(api.requests().await, 1);
+    }
+
+    #[tokio::test]
+    async fn openai_reply_is_streamed_into_the_result() {
+        let api = MockApi::start().await;
+        api.stream("openai_success.sse").await;
+        let conversion = convert(&api, Backend::OpenAi).await.unwrap();
+        assert_eq!(conversion.latex, LATEX);
+        assert_eq!(api.requests().await, 1);
+    }
+
+    #[tokio::test]
+    async fn gemini_reply_is_streamed_into_the_result() {
+        let api = MockApi::start().await;
+        api.stream("gemini_success.sse").await;
+        let conversion = convert(&api, Backend::Gemini).await.unwrap();
+        assert_eq!(conversion.latex, LATEX);
+        assert_eq!(api.requests().await, 1);
+    }
+
+    #[tokio::test]
+    async fn gemini_unavailable_is_retried() {
+        let api = MockApi::start().await;
+        api.fail(503, "gemini_unavailable.json", 1).await;
+        api.stream("gemini_success.sse").await;
+        let conversion = convert(&api, Backend::Gemini).await.unwrap();
+        assert_eq!(conversion.latex, LATEX);
+        assert_eq!(api.requests().await, 2);
+    }
+
+    #[tokio::test]
+    async fn gemini_refusal_fails_with_the_reason() {
+        let api = MockApi::start().await;
+        api.stream("gemini_blocked.sse").await;
+        let e = convert(&api, Backend::Gemini).await.unwrap_err();
+        assert!(e.to_string().contains("PROHIBITED_CONTENT"), "{}", e);
+    }
+
+    #[cfg(feature = "ollama")]
+    #[tokio::test]
+    async fn ollama_reply_is_streamed_into_the_result() {
+        let api = MockApi::start().await;
+        api.stream("ollama_success.ndjson").await;
+        let conversion = convert(&api, Backend::Ollama).await.unwrap();
+        assert_eq!(conversion.latex, LATEX);
+        assert_eq!(api.requests().await, 1);
+    }
+
+    #[cfg(feature = "ollama")]
+    #[tokio::test]
+    async fn ollama_missing_model_fails_with_the_servers_error() {
+        let api = MockApi::start().await;
+        api.fail(404, "ollama_not_found.json", 1).await;
+        let e = convert(&api, Backend::Ollama).await.unwrap_err();
+        assert!(e.to_string().contains("try pulling it first"), "{}", e);
+        assert_eq!(api.requests().await, 1);
+    }
+
+    #[tokio::test]
+    async fn overloaded_requests_are_retried() {
+        let api = MockApi::start().await;
+        api.fail(529, "anthropic_overloaded.json", 2).await;
+        api.stream("anthropic_success.sse").await;
+        let conversion = convert(&api, Backend::Anthropic).await.unwrap();
+        assert_eq!(conversion.latex, LATEX);
+        assert_eq!(api.requests().await, 3);
+    }
+
+    #[tokio::test]
+    async fn overloaded_requests_give_up_after_the_retries() {
+        let api = MockApi::start().await;
+        api.fail(529, "anthropic_overloaded.json", 10).await;
+        let e = convert(&api, Backend::Anthropic).await.unwrap_err();
+        assert!(e.to_string().contains("still overloaded after 3 retries"), "{}", e);
+        assert_eq!(api.requests().await, 4);
+    }
+
+    #[tokio::test]
+    async fn anthropic_rate_limit_is_retried() {
+        let api = MockApi::start().await;
+        api.fail(429, "anthropic_rate_limited.json", 1).await;
+        api.stream("anthropic_success.sse").await;
+        let conversion = convert(&api, Backend::Anthropic).await.unwrap();
+        assert_eq!(conversion.latex, LATEX);
+        assert_eq!(api.requests().await, 2);
+    }
+
+    #[tokio::test]
+    async fn openai_rate_limit_is_retried() {
+        let api = MockApi::start().await;
+        api.fail(429, "openai_rate_limited.json", 1).await;
+        api.stream("openai_success.sse").await;
+        let conversion = convert(&api, Backend::OpenAi).await.unwrap();
+        assert_eq!(conversion.latex, LATEX);
+        assert_eq!(api.requests().await, 2);
+    }
+
+    #[tokio::test]
+    async fn openai_exhausted_quota_is_not_retried() {
+        let api = MockApi::start().await;
+        api.fail(429, "openai_quota.json", 1).await;
+        api.stream("openai_success.sse").await;
+        let e = convert(&api, Backend::OpenAi).await.unwrap_err();
+        assert!(e.to_string().contains("insufficient_quota"), "{}", e);
+        assert_eq!(api.requests().await, 1);
+    }
+
+    #[tokio::test]
+    async fn malformed_events_fail_with_the_parse_error() {
+        let api = MockApi::start().await;
+        api.stream("anthropic_malformed.sse").await;
+        let e = convert(&api, Backend::Anthropic).await.unwrap_err();
+        assert!(e.to_string().contains("unexpected response shape"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn truncated_replies_fail_instead_of_returning_part() {
+        let api = MockApi::start().await;
+        api.stream("anthropic_truncated.sse").await;
+        let e = convert(&api, Backend::Anthropic).await.unwrap_err();
+        assert!(e.to_string().contains("ended before it was complete"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn unreachable_providers_are_reported_right_away() {
+        let api = MockApi::start().await;
+        let reachable = api.config(Backend::Anthropic);
+        let provider = reachable.provider();
+        // Never resolves while the server is there
+        assert!(tokio::time::timeout(Duration::from_millis(500), provider::unreachable(&*provider)).await.is_err());
+
+        // A port nothing listens on any more
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = AppConfig { anthropic_url: format!("http://127.0.0.1:{}", port), ..reachable };
+        let e = provider::unreachable(&*config.provider()).await;
+        assert!(e.to_string().contains("cannot reach 127.0.0.1"), "{}", e);
+    }
+}
@@ -0,0 +1,199 @@
+//! The cleanup a model reply goes through before it is a result, as small
+//! transforms applied in the order `postprocess` lists them, so chatter like
+//! "Here is the LaTeX:" and ```` ```latex ```` fences never reach the clipboard.
+
+use crate::normalize;
+
+/// What `postprocess` can list
+pub const NAMES: &[&str] = &["preamble", "fences", "display-brackets", "display-dollars", "trim"];
+
+/// Words a line of chatter before the result mentions, lowercased
+const LEAD_INS: &[&str] = &["latex", "here", "sure", "certainly", "of course", "transcription", "transcribed"];
+
+/// One step of the cleanup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    /// Drops lines of prose before the result that introduce it
+    Preamble,
+    /// Unwraps a Markdown code fence
+    Fences,
+    /// Writes `$$...$$` displays as `\[...\]`
+    DisplayBrackets,
+    /// Writes `\[...\]` displays as `$$...$$`
+    DisplayDollars,
+    /// Drops whitespace at the ends and at the end of each line
+    Trim,
+}
+
+impl Transform {
+    /// The transform called `name` in `postprocess`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim() {
+            "preamble" => Some(Self::Preamble),
+            "fences" => Some(Self::Fences),
+            "display-brackets" => Some(Self::DisplayBrackets),
+            "display-dollars" => Some(Self::DisplayDollars),
+            "trim" => Some(Self::Trim),
+            _ => None,
+        }
+    }
+
+    /// Whether it only makes sense for LaTeX, not Typst or AsciiMath, whose
+    /// `$` means something else
+    pub fn is_latex_only(self) -> bool {
+        matches!(self, Self::DisplayBrackets | Self::DisplayDollars)
+    }
+
+    pub fn apply(self, reply: &str) -> String {
+        match self {
+            Self::Preamble => strip_preamble(reply),
+            Self::Fences => normalize::strip_fences(reply),
+            Self::DisplayBrackets => display_brackets(reply),
+            Self::DisplayDollars => display_dollars(reply),
+            Self::Trim => trim(reply),
+        }
+    }
+}
+
+/// `reply` after each of `transforms` in turn
+pub fn apply(transforms: &[Transform], reply: &str) -> String {
+    transforms.iter().fold(reply.to_string(), |text, transform| transform.apply(&text))
+}
+
+/// `reply` without the lines before the result that only introduce it, e.g.
+/// "Sure! Here is the LaTeX:"; a line with any math in it is kept
+fn strip_preamble(reply: &str) -> String {
+    let mut lines = reply.trim_start().lines().peekable();
+    let mut dropped = false;
+    while let Some(line) = lines.peek() {
+        if !is_lead_in(line) {
+            break;
+        }
+        lines.next();
+        dropped = true;
+        while lines.peek().is_some_and(|line| line.trim().is_empty()) {
+            lines.next();
+        }
+    }
+    if !dropped {
+        return reply.to_string();
+    }
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+fn is_lead_in(line: &str) -> bool {
+    let line = line.trim();
+    let lowercase = line.to_lowercase();
+    let prose = !line.is_empty() && !line.contains(['\\', '$', '{', '}', '^', '_', '=']);
+    let introduces = line.ends_with(':')
+        || ["sure", "certainly", "of course"].iter().any(|word| lowercase.starts_with(word));
+    prose && introduces && LEAD_INS.iter().any(|word| lowercase.contains(word))
+}
+
+/// `latex` with `$$...$$` written as `\[...\]`; left alone if its `$$` don't pair up
+fn display_brackets(latex: &str) -> String {
+    let mut converted = String::with_capacity(latex.len());
+    let mut open = false;
+    let mut rest = latex;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("\\\\") || rest.starts_with("\\$") {
+            converted.push_str(&rest[..2]);
+            rest = &rest[2..];
+        } else if let Some(after) = rest.strip_prefix("$$") {
+            converted.push_str(if open { "\\]" } else { "\\[" });
+            open = !open;
+            rest = after;
+        } else {
+            converted.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if open { latex.to_string() } else { converted }
+}
+
+/// `latex` with `\[...\]` written as `$$...$$`; the `\\[2pt]` of a line break
+/// isn't a display
+fn display_dollars(latex: &str) -> String {
+    let mut converted = String::with_capacity(latex.len());
+    let mut rest = latex;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("\\\\") {
+            converted.push_str("\\\\");
+            rest = &rest[2..];
+        } else if rest.starts_with("\\[") || rest.starts_with("\\]") {
+            converted.push_str("$$");
+            rest = &rest[2..];
+        } else {
+            converted.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    converted
+}
+
+fn trim(reply: &str) -> String {
+    reply.trim().lines().map(str::trim_end).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(names: &[&str]) -> Vec<Transform> {
+        names.iter().map(|name| Transform::parse(name).unwrap()).collect()
+    }
+
+    #[test]
+    fn every_name_parses() {
+        for name in NAMES {
+            assert!(Transform::parse(name).is_some(), "{}", name);
+        }
+        assert_eq!(Transform::parse("fence"), None);
+    }
+
+    #[test]
+    fn lead_ins_are_dropped() {
+        assert_eq!(strip_preamble("Here is the LaTeX:\n\nx^2"), "x^2");
+        assert_eq!(strip_preamble("Sure! Here's the transcription.\nHere is the LaTeX code:\nx^2\ny"), "x^2\ny");
+    }
+
+    #[test]
+    fn text_that_is_part_of_the_result_stays() {
+        for reply in ["Proof:\nx^2", "Here $x$ is:\ny", "\\text{Here is the LaTeX:}", "x^2\nHere is the LaTeX:"] {
+            assert_eq!(strip_preamble(reply), reply);
+        }
+    }
+
+    #[test]
+    fn dollar_displays_become_brackets() {
+        assert_eq!(display_brackets("$$a$$ and $$b$$"), "\\[a\\] and \\[b\\]");
+        assert_eq!(display_brackets("\\$$x$ costs \\$5"), "\\$$x$ costs \\$5");
+        assert_eq!(display_brackets("$$a$$ $$b"), "$$a$$ $$b");
+    }
+
+    #[test]
+    fn bracket_displays_become_dollars() {
+        assert_eq!(display_dollars("\\[a\\]"), "$$a$$");
+        assert_eq!(display_dollars("a \\\\[2pt] b"), "a \\\\[2pt] b");
+    }
+
+    #[test]
+    fn display_conversions_undo_each_other() {
+        let latex = "\\[\\begin{aligned} a &= b \\\\[1ex] c &= d \\end{aligned}\\]";
+        assert_eq!(display_brackets(&display_dollars(latex)), latex);
+    }
+
+    #[test]
+    fn trimming_keeps_indentation() {
+        assert_eq!(trim("\n  a  \n    b\t\n\n"), "a\n    b");
+    }
+
+    #[test]
+    fn transforms_compose_in_order() {
+        let reply = "Certainly! Here is the LaTeX:\n```latex\n$$x^2$$  \n```\n";
+        assert_eq!(apply(&pipeline(&["preamble", "fences", "display-brackets", "trim"]), reply), "\\[x^2\\]");
+        // The fence isn't at the start until the preamble is gone
+        assert!(apply(&pipeline(&["fences", "preamble", "trim"]), reply).starts_with("```latex"));
+        assert_eq!(apply(&[], reply), reply);
+    }
+}
@@ -0,0 +1,142 @@
+//! Image preprocessing applied before upload, selected by a profile's
+//! `preprocess` setting.
+
+use std::error::Error;
+
+/// Runs the named preprocessing stage over an encoded image and returns the re-encoded result
+#[cfg(feature = "preprocess")]
+pub fn apply(stage: &str, image_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let image = image::load_from_memory(image_data)?.to_rgb8();
+    let processed = match stage {
+        "whiteboard" => whiteboard::enhance(&image),
+        other => return Err(format!("unknown preprocessing stage `{}`", other).into()),
+    };
+    encode_png(&processed)
+}
+
+#[cfg(not(feature = "preprocess"))]
+pub fn apply(stage: &str, _image_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    Err(format!(
+        "preprocessing stage `{}` needs a build with the `preprocess` feature",
+        stage
+    )
+    .into())
+}
+
+/// `image_data` cut top to bottom into tiles `height` pixels tall, each
+/// starting `overlap` pixels above the end of the one before; `None` when
+/// the image isn't taller than a tile, or can't be decoded
+#[cfg(feature = "preprocess")]
+pub fn tiles(image_data: &[u8], height: u32, overlap: u32) -> Option<Vec<Vec<u8>>> {
+    if height == 0 {
+        return None;
+    }
+    let image = image::load_from_memory(image_data).ok()?;
+    if image.height() <= height {
+        return None;
+    }
+    let image = image.to_rgb8();
+    let mut tiles = Vec::new();
+    let mut top = 0;
+    loop {
+        let tile_height = height.min(image.height() - top);
+        let tile = image::imageops::crop_imm(&image, 0, top, image.width(), tile_height).to_image();
+        tiles.push(encode_png(&tile).ok()?);
+        if top + tile_height >= image.height() {
+            return Some(tiles);
+        }
+        top += height - overlap.min(height / 2);
+    }
+}
+
+/// Without image decoding every image is sent whole
+#[cfg(not(feature = "preprocess"))]
+pub fn tiles(_image_data: &[u8], _height: u32, _overlap: u32) -> Option<Vec<Vec<u8>>> {
+    None
+}
+
+#[cfg(feature = "preprocess")]
+pub fn encode_png(image: &image::RgbImage) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut encoded, image::ImageFormat::Png)?;
+    Ok(encoded.into_inner())
+}
+
+/// Whiteboard photos: uneven lighting, glare spots and a grey-ish board
+/// behind colored marker strokes.
+#[cfg(feature = "preprocess")]
+mod whiteboard {
+    use image::imageops::{self, FilterType};
+    use image::{Rgb, RgbImage};
+
+    /// Downscale factor for the background estimate; strokes vanish at this size
+    const BACKGROUND_SCALE: u32 = 16;
+    /// After flattening, channels at least this bright on every channel are board
+    const WHITE_POINT: f32 = 215.0;
+    /// ...and anything darker than this is full-strength ink
+    const BLACK_POINT: f32 = 60.0;
+
+    pub fn enhance(image: &RgbImage) -> RgbImage {
+        let background = estimate_background(image);
+        let mut out = RgbImage::new(image.width(), image.height());
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let board = background.get_pixel(x, y);
+            out.put_pixel(x, y, clean(flatten(pixel, board)));
+        }
+        out
+    }
+
+    /// Smooth picture of the empty board, including lighting gradients and glare
+    fn estimate_background(image: &RgbImage) -> RgbImage {
+        let (width, height) = image.dimensions();
+        let small = imageops::resize(
+            image,
+            (width / BACKGROUND_SCALE).max(1),
+            (height / BACKGROUND_SCALE).max(1),
+            FilterType::Triangle,
+        );
+        // Brightest neighbour wins so thin strokes don't darken the estimate
+        let small = dilate(&small);
+        let small = imageops::blur(&small, 2.0);
+        imageops::resize(&small, width, height, FilterType::Triangle)
+    }
+
+    fn dilate(image: &RgbImage) -> RgbImage {
+        let (width, height) = image.dimensions();
+        RgbImage::from_fn(width, height, |x, y| {
+            let mut brightest = [0u8; 3];
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let neighbour = image.get_pixel(nx, ny);
+                    for channel in 0..3 {
+                        brightest[channel] = brightest[channel].max(neighbour[channel]);
+                    }
+                }
+            }
+            Rgb(brightest)
+        })
+    }
+
+    /// Divides out the background, which whitens the board and evens out glare
+    fn flatten(pixel: &Rgb<u8>, board: &Rgb<u8>) -> [f32; 3] {
+        let mut flat = [0.0; 3];
+        for channel in 0..3 {
+            let board = board[channel].max(1) as f32;
+            flat[channel] = (pixel[channel] as f32 / board * 255.0).min(255.0);
+        }
+        flat
+    }
+
+    /// Snaps near-white to white and stretches the remaining marker colors to full strength
+    fn clean(flat: [f32; 3]) -> Rgb<u8> {
+        if flat.iter().all(|&value| value >= WHITE_POINT) {
+            return Rgb([255, 255, 255]);
+        }
+        let mut out = [0u8; 3];
+        for channel in 0..3 {
+            let stretched = (flat[channel] - BLACK_POINT) / (WHITE_POINT - BLACK_POINT) * 255.0;
+            out[channel] = stretched.clamp(0.0, 255.0) as u8;
+        }
+        Rgb(out)
+    }
+}
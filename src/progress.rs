@@ -0,0 +1,118 @@
+//! Progress of `latex_ocr batch`: a live bar with the running token and cost
+//! totals, one status line per image, and a summary table at the end.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::path::Path;
+
+use crate::provider::{self, Spend};
+
+const TEMPLATE: &str = "[{bar:30}] {pos}/{len} {elapsed} elapsed, ETA {eta} {wide_msg}";
+
+/// What became of one image
+pub enum Outcome {
+    Converted,
+    /// Converted by the interrupted run this one resumes
+    Resumed,
+    /// The image couldn't be read
+    Skipped(String),
+    /// The conversion failed
+    Failed(String),
+}
+
+pub struct Progress {
+    bar: ProgressBar,
+    /// Spend before the batch, so the totals only count this run
+    start: Spend,
+    results: Vec<(String, Outcome)>,
+}
+
+impl Progress {
+    /// A bar over `total` images; it stays hidden when stderr isn't a terminal
+    pub fn new(total: usize) -> Self {
+        // The bar stands in for the spinner each reply would show
+        provider::hide_live_progress();
+        let bar = ProgressBar::new(total as u64);
+        if let Ok(style) = ProgressStyle::with_template(TEMPLATE) {
+            bar.set_style(style.progress_chars("=> "));
+        }
+        Self { bar, start: provider::spend(), results: Vec::new() }
+    }
+
+    /// Shows `image` as the one being converted
+    pub fn start(&self, image: &Path) {
+        if self.bar.is_hidden() {
+            eprintln!("[{}/{}] {}", self.results.len() + 1, self.bar.length().unwrap_or(0), image.display());
+        }
+        self.bar.set_message(format!("{}  {}", name(image), totals(&self.spend())));
+    }
+
+    /// Records how `image` went and moves the bar on
+    pub fn finish_image(&mut self, image: &Path, outcome: Outcome) {
+        let line = match &outcome {
+            Outcome::Converted => format!("  ok      {}", image.display()),
+            Outcome::Resumed => format!("  resumed {}", image.display()),
+            Outcome::Skipped(reason) => format!("  skipped {}: {}", image.display(), reason),
+            Outcome::Failed(reason) => format!("  failed  {}: {}", image.display(), reason),
+        };
+        self.println(&line);
+        self.results.push((name(image), outcome));
+        self.bar.inc(1);
+        self.bar.set_message(totals(&self.spend()));
+    }
+
+    /// Clears the bar and prints the summary table
+    pub fn finish(self) {
+        self.bar.finish_and_clear();
+        let width = self.results.iter().map(|(image, _)| image.chars().count()).max().unwrap_or(0).max(5);
+        eprintln!("{:<8} {:<width$}  detail", "status", "image");
+        let mut converted = 0;
+        for (image, outcome) in &self.results {
+            let (status, detail) = match outcome {
+                Outcome::Converted => {
+                    converted += 1;
+                    ("ok", "")
+                }
+                Outcome::Resumed => {
+                    converted += 1;
+                    ("resumed", "")
+                }
+                Outcome::Skipped(reason) => ("skipped", reason.as_str()),
+                Outcome::Failed(reason) => ("failed", reason.as_str()),
+            };
+            // Keep each row on one line however long the error is
+            let detail = detail.lines().next().unwrap_or_default();
+            eprintln!("{:<8} {:<width$}  {}", status, image, detail);
+        }
+        eprintln!(
+            "{} of {} converted, {}",
+            converted,
+            self.results.len(),
+            totals(&self.spend())
+        );
+    }
+
+    fn println(&self, line: &str) {
+        if self.bar.is_hidden() {
+            eprintln!("{}", line);
+        } else {
+            self.bar.println(line);
+        }
+    }
+
+    fn spend(&self) -> Spend {
+        provider::spend().since(&self.start)
+    }
+}
+
+fn name(image: &Path) -> String {
+    image.file_name().unwrap_or(image.as_os_str()).to_string_lossy().to_string()
+}
+
+/// "12345 tokens, $0.0412"
+fn totals(spend: &Spend) -> String {
+    let tokens = spend.input_tokens + spend.output_tokens;
+    match spend.cost {
+        Some(cost) => format!("{} tokens, ${:.4}", tokens, cost),
+        None => format!("{} tokens", tokens),
+    }
+}
@@ -0,0 +1,678 @@
+//! What every model backend provides: a request goes out and the reply comes
+//! back as a stream of text deltas followed by the tokens it used. Retrying
+//! when the backend is over capacity, cancellation (dropping the stream) and
+//! usage accounting work the same for all of them on top of that.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::future::Future;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::events;
+use crate::notify::{self, Sound};
+
+/// Reply length limit for a full conversion
+pub const MAX_TOKENS: u32 = 1024;
+
+/// Wait before the first retry of an overloaded request; doubles each time
+const OVERLOADED_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Longest wait between retries; a backend asking for more fails the request instead
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Size a single event of a reply may grow to before the reply counts as broken,
+/// whatever `max_reply_bytes` is; stops a reply that never ends an event
+const MAX_EVENT_BYTES: usize = 16 * 1024 * 1024;
+
+/// How long a provider may take to start answering a request, unless it
+/// sets its own `start_timeout`
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a streamed reply may go without sending anything
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a reply has to be under way before a notification says so, when
+/// there is no terminal to show a spinner on
+const PROGRESS_NOTIFICATION_AFTER: Duration = Duration::from_secs(5);
+
+/// How long connecting to a provider may take before it counts as unreachable
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+static OVERLOADED_RETRIES: AtomicU32 = AtomicU32::new(3);
+
+/// How many times an overloaded request is retried before giving up
+pub fn set_overloaded_retries(retries: u32) {
+    OVERLOADED_RETRIES.store(retries, Ordering::Relaxed);
+}
+
+static LIVE_PROGRESS: AtomicBool = AtomicBool::new(true);
+
+/// Stops showing how far replies have got, for callers with a progress display of their own
+pub fn hide_live_progress() {
+    LIVE_PROGRESS.store(false, Ordering::Relaxed);
+}
+
+static MAX_REPLY_BYTES: AtomicU64 = AtomicU64::new(64 * 1024);
+
+/// How much of a reply is kept before the rest is cut off; 0 keeps it all
+pub fn set_max_reply_bytes(bytes: u64) {
+    MAX_REPLY_BYTES.store(bytes, Ordering::Relaxed);
+}
+
+static CLIENT_TAG: OnceLock<String> = OnceLock::new();
+
+/// Tags every request with `tag`, if it isn't empty; the first call wins
+pub fn set_client_tag(tag: &str) {
+    let tag = tag.trim();
+    if !tag.is_empty() {
+        let _ = CLIENT_TAG.set(tag.to_string());
+    }
+}
+
+/// What `client_tag` is set to, for the providers' user metadata
+pub fn client_tag() -> Option<&'static str> {
+    CLIENT_TAG.get().map(String::as_str)
+}
+
+/// A POST to `url` with the User-Agent naming this tool and the client tag
+pub fn post(client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+    let agent = concat!("latex_ocr/", env!("CARGO_PKG_VERSION"));
+    let agent = match client_tag() {
+        Some(tag) => format!("{} ({})", agent, tag),
+        None => agent.to_string(),
+    };
+    client.post(url).header(reqwest::header::USER_AGENT, agent)
+}
+
+static INPUT_TOKENS: AtomicU64 = AtomicU64::new(0);
+static OUTPUT_TOKENS: AtomicU64 = AtomicU64::new(0);
+/// In units of $10^-8, so prices in cents per million tokens multiply exactly
+static COST: AtomicU64 = AtomicU64::new(0);
+static UNPRICED: AtomicBool = AtomicBool::new(false);
+
+/// Tokens used and what they cost, over every request this process made
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Spend {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// In dollars at list price; `None` once a model without a known price was used
+    pub cost: Option<f64>,
+}
+
+impl Spend {
+    /// What was spent after `earlier` was taken
+    pub fn since(&self, earlier: &Spend) -> Spend {
+        Spend {
+            input_tokens: self.input_tokens - earlier.input_tokens,
+            output_tokens: self.output_tokens - earlier.output_tokens,
+            cost: self.cost.zip(earlier.cost).map(|(now, then)| now - then),
+        }
+    }
+}
+
+/// The running totals so far
+pub fn spend() -> Spend {
+    Spend {
+        input_tokens: INPUT_TOKENS.load(Ordering::Relaxed),
+        output_tokens: OUTPUT_TOKENS.load(Ordering::Relaxed),
+        cost: (!UNPRICED.load(Ordering::Relaxed)).then(|| COST.load(Ordering::Relaxed) as f64 / 1e8),
+    }
+}
+
+/// List price of `model` in cents per million input and output tokens
+fn price(model: &str) -> Option<(u64, u64)> {
+    let price = match model {
+        m if m.contains("claude-3-haiku") => (25, 125),
+        m if m.contains("claude-3-5-haiku") => (80, 400),
+        m if m.contains("haiku") => (100, 500),
+        m if m.contains("opus-4-0") || m.contains("opus-4-1") || m.contains("claude-3-opus") => (1500, 7500),
+        m if m.contains("opus") => (500, 2500),
+        m if m.contains("sonnet") => (300, 1500),
+        m if m.contains("gpt-4o-mini") => (15, 60),
+        m if m.contains("gpt-4o") => (250, 1000),
+        m if m.contains("gpt-4.1-nano") => (10, 40),
+        m if m.contains("gpt-4.1-mini") => (40, 160),
+        m if m.contains("gpt-4.1") => (200, 800),
+        m if m.contains("gemini-2.5-flash-lite") => (10, 40),
+        m if m.contains("gemini-2.5-flash") => (30, 250),
+        m if m.contains("gemini-2.5-pro") => (125, 1000),
+        m if m.contains("gemini-2.0-flash") && !m.contains("lite") => (10, 40),
+        _ => return None,
+    };
+    Some(price)
+}
+
+fn record(provider: &dyn OcrProvider, model: &str, usage: &Usage) {
+    INPUT_TOKENS.fetch_add(usage.input_tokens, Ordering::Relaxed);
+    OUTPUT_TOKENS.fetch_add(usage.output_tokens, Ordering::Relaxed);
+    match provider.price(model) {
+        Some((input, output)) => {
+            COST.fetch_add(usage.input_tokens * input + usage.output_tokens * output, Ordering::Relaxed);
+        }
+        None => UNPRICED.store(true, Ordering::Relaxed),
+    }
+}
+
+/// Which backend conversions are sent to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Anthropic,
+    OpenAi,
+    Gemini,
+    /// A local Ollama server, with the `ollama` feature
+    Ollama,
+}
+
+/// Tokens one request used
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+}
+
+/// How long a reply may get
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_tokens: u32,
+    /// Tokens of extended thinking before answering; `None` answers directly
+    pub thinking_budget: Option<u32>,
+}
+
+impl Limits {
+    /// A direct answer of at most `max_tokens`
+    pub const fn reply(max_tokens: u32) -> Self {
+        Self { max_tokens, thinking_budget: None }
+    }
+}
+
+/// An image sent along with the prompt
+#[derive(Debug, Clone, Copy)]
+pub struct Image<'a> {
+    pub data: &'a [u8],
+    pub media_type: &'static str,
+}
+
+/// One prompt, with or without an image
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    pub model: &'a str,
+    pub prompt: &'a str,
+    pub image: Option<Image<'a>>,
+    pub limits: Limits,
+}
+
+impl<'a> Request<'a> {
+    /// A text-only prompt
+    pub fn text(model: &'a str, prompt: &'a str, limits: Limits) -> Self {
+        Self { model, prompt, image: None, limits }
+    }
+
+    /// A prompt about the image read from `image_path`
+    pub fn image(model: &'a str, prompt: &'a str, image_data: &'a [u8], image_path: &str, limits: Limits) -> Self {
+        let image = Image { data: image_data, media_type: media_type(image_data, image_path) };
+        Self { model, prompt, image: Some(image), limits }
+    }
+}
+
+/// Why a request didn't start streaming
+pub enum Failure {
+    /// Over capacity or rate limited; worth retrying, after `retry_after` if the backend said when
+    Overloaded { retry_after: Option<Duration> },
+    Other(Box<dyn Error>),
+}
+
+impl<E: Into<Box<dyn Error>>> From<E> for Failure {
+    fn from(e: E) -> Self {
+        Failure::Other(e.into())
+    }
+}
+
+/// What a streamed event carried
+pub enum Chunk {
+    Text(String),
+    /// Token counts so far; a count left at 0 isn't known yet
+    Usage(Usage),
+    /// The reply is complete
+    Done,
+}
+
+/// Turns the data of one server-sent event into what it carried
+pub type Decode = fn(&str) -> Result<Vec<Chunk>, Box<dyn Error>>;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// A model backend
+pub trait OcrProvider {
+    /// Name for messages, e.g. "Anthropic"
+    fn name(&self) -> &'static str;
+
+    /// The endpoint requests go to, which is probed to tell whether it can be reached
+    fn url(&self) -> &str;
+
+    /// Sends `request` asking for a streamed reply
+    fn stream<'a>(&'a self, client: &'a reqwest::Client, request: &'a Request<'a>)
+    -> BoxFuture<'a, Result<Reply, Failure>>;
+
+    /// What `model` costs here in cents per million input and output tokens
+    fn price(&self, model: &str) -> Option<(u64, u64)> {
+        price(model)
+    }
+
+    /// How long a request may take to start answering before it is retried
+    fn start_timeout(&self) -> Duration {
+        RESPONSE_TIMEOUT
+    }
+}
+
+/// How the events of a streamed reply are delimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Server-sent events, their `data:` lines decoded
+    ServerSentEvents,
+    /// One JSON object per line
+    JsonLines,
+}
+
+/// A reply arriving as server-sent events; dropping it cancels the request
+pub struct Reply {
+    response: reqwest::Response,
+    framing: Framing,
+    decode: Decode,
+    /// Bytes received after the last complete event
+    buffer: Vec<u8>,
+    events: VecDeque<String>,
+    /// Decoded text not passed on yet
+    texts: VecDeque<String>,
+    usage: Usage,
+    done: bool,
+}
+
+impl Reply {
+    /// A reply sent as server-sent events
+    pub fn new(response: reqwest::Response, decode: Decode) -> Self {
+        Self::framed(response, Framing::ServerSentEvents, decode)
+    }
+
+    pub fn framed(response: reqwest::Response, framing: Framing, decode: Decode) -> Self {
+        Self {
+            response,
+            framing,
+            decode,
+            buffer: Vec::new(),
+            events: VecDeque::new(),
+            texts: VecDeque::new(),
+            usage: Usage::default(),
+            done: false,
+        }
+    }
+
+    /// The next piece of text, `None` once the reply is complete
+    pub async fn next(&mut self) -> Option<Result<String, Box<dyn Error>>> {
+        loop {
+            if let Some(text) = self.texts.pop_front() {
+                return Some(Ok(text));
+            }
+            if let Some(data) = self.events.pop_front() {
+                let chunks = match (self.decode)(&data) {
+                    Ok(chunks) => chunks,
+                    Err(e) => return Some(Err(e)),
+                };
+                for chunk in chunks {
+                    match chunk {
+                        Chunk::Text(text) => self.texts.push_back(text),
+                        Chunk::Usage(usage) => {
+                            // Counts are running totals, not increments
+                            self.usage.input_tokens = self.usage.input_tokens.max(usage.input_tokens);
+                            self.usage.output_tokens = self.usage.output_tokens.max(usage.output_tokens);
+                        }
+                        Chunk::Done => self.done = true,
+                    }
+                }
+                continue;
+            }
+            if self.done {
+                return None;
+            }
+            let chunk = match tokio::time::timeout(STALL_TIMEOUT, self.response.chunk()).await {
+                Ok(chunk) => chunk,
+                Err(_) => {
+                    self.done = true;
+                    return Some(Err(format!("the reply stalled for {}s", STALL_TIMEOUT.as_secs()).into()));
+                }
+            };
+            match chunk {
+                Ok(Some(bytes)) => {
+                    self.buffer.extend_from_slice(&bytes);
+                    self.split_events();
+                    if self.buffer.len() > MAX_EVENT_BYTES {
+                        self.done = true;
+                        return Some(Err("the reply sent an event of more than 16 MiB, giving up on it".into()));
+                    }
+                }
+                Ok(None) => {
+                    self.done = true;
+                    return Some(Err("the reply ended before it was complete".into()));
+                }
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+
+    /// Tokens used as far as the reply has told so far, all of them once it's complete
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+
+    /// Moves the data of every complete event in the buffer to `events`
+    fn split_events(&mut self) {
+        if self.framing == Framing::JsonLines {
+            while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                if !line.trim().is_empty() {
+                    self.events.push_back(line.trim().to_string());
+                }
+            }
+            return;
+        }
+        while let Some(end) = event_end(&self.buffer) {
+            let event: Vec<u8> = self.buffer.drain(..end.0 + end.1).collect();
+            let event = String::from_utf8_lossy(&event[..end.0]);
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            if !data.is_empty() {
+                self.events.push_back(data.join("\n"));
+            }
+        }
+    }
+}
+
+/// Where the first event in `buffer` ends and how long the blank line after it is
+fn event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    ["\r\n\r\n", "\n\n"]
+        .iter()
+        .filter_map(|separator| {
+            let separator = separator.as_bytes();
+            buffer
+                .windows(separator.len())
+                .position(|window| window == separator)
+                .map(|position| (position, separator.len()))
+        })
+        .min()
+}
+
+/// Sends `request` and collects the whole reply, retrying with a countdown
+/// while `provider` is overloaded until the configured retries are used up
+pub async fn complete(
+    provider: &dyn OcrProvider,
+    client: &reqwest::Client,
+    request: &Request<'_>,
+) -> Result<String, Box<dyn Error>> {
+    complete_with_usage(provider, client, request).await.map(|(text, _)| text)
+}
+
+/// `complete`, also returning the tokens the reply used
+pub async fn complete_with_usage(
+    provider: &dyn OcrProvider,
+    client: &reqwest::Client,
+    request: &Request<'_>,
+) -> Result<(String, Usage), Box<dyn Error>> {
+    let name = provider.name();
+    let retries = OVERLOADED_RETRIES.load(Ordering::Relaxed);
+    let mut wait = OVERLOADED_BACKOFF;
+    let mut attempt = 0;
+    let mut reply = loop {
+        // What went wrong, the first time and once retrying didn't help
+        let timeout = ("timed out", "still timed out");
+        let started = tokio::time::timeout(provider.start_timeout(), provider.stream(client, request)).await;
+        let ((problem, still), retry_after) = match started {
+            Err(_) => (timeout, None),
+            Ok(Ok(reply)) => break reply,
+            Ok(Err(Failure::Overloaded { retry_after })) => (("is overloaded", "is still overloaded"), retry_after),
+            Ok(Err(Failure::Other(e))) if timed_out(&*e) => (timeout, None),
+            Ok(Err(Failure::Other(e))) => return Err(e),
+        };
+        if attempt == retries && retries == 0 {
+            return Err(format!("{} {}, try again later", name, problem).into());
+        }
+        if attempt == retries {
+            return Err(format!("{} {} after {} retries, try again later", name, still, retries).into());
+        }
+        // Retrying sooner than the server asks would only be turned away again
+        if let Some(retry_after) = retry_after.filter(|retry_after| *retry_after > MAX_BACKOFF) {
+            return Err(format!(
+                "{} {} and asks to wait {}s, try again later",
+                name,
+                problem,
+                retry_after.as_secs()
+            )
+            .into());
+        }
+        attempt += 1;
+        let wait_for = retry_after.unwrap_or_else(|| jitter(wait));
+        events::emit("overloaded", json!({
+            "provider": name,
+            "problem": problem,
+            "attempt": attempt,
+            "retries": retries,
+            "wait_seconds": wait_for.as_secs(),
+        }));
+        notify::send(
+            &format!("{} {}", name, problem),
+            &format!("Retrying in {}s (retry {} of {})", wait_for.as_secs(), attempt, retries),
+            Sound::Failure,
+        );
+        countdown(name, problem, wait_for).await;
+        wait = (wait * 2).min(MAX_BACKOFF);
+    };
+    let limit = MAX_REPLY_BYTES.load(Ordering::Relaxed) as usize;
+    let mut live = Live::start(name);
+    let mut text = String::new();
+    let mut chars = 0;
+    let result = loop {
+        match reply.next().await {
+            Some(Ok(delta)) => {
+                text.push_str(&delta);
+                chars += delta.chars().count();
+                live.received(chars);
+                if limit > 0 && text.len() > limit {
+                    // Returning drops the stream, which stops the download
+                    truncate(&mut text, limit, name);
+                    break Ok(text);
+                }
+            }
+            Some(Err(e)) => break Err(e),
+            None => break Ok(text),
+        }
+    };
+    // Tokens are billed whether or not the reply made it through
+    let usage = reply.usage();
+    record(provider, request.model, &usage);
+    result.map(|text| (text, usage))
+}
+
+/// Shows how far a streamed reply has got: a spinner counting characters
+/// while stderr is a terminal, otherwise a single notification once the reply
+/// has taken a while. The spinner is cleared when this is dropped
+struct Live {
+    name: &'static str,
+    started: std::time::Instant,
+    spinner: Option<indicatif::ProgressBar>,
+    notified: bool,
+}
+
+/// Spinners of replies streaming at the same time, e.g. while racing models, one line each
+static SPINNERS: OnceLock<indicatif::MultiProgress> = OnceLock::new();
+
+impl Live {
+    fn start(name: &'static str) -> Self {
+        let shown = LIVE_PROGRESS.load(Ordering::Relaxed);
+        let spinner = (shown && std::io::stderr().is_terminal()).then(|| {
+            let spinner = SPINNERS.get_or_init(indicatif::MultiProgress::new).add(indicatif::ProgressBar::new_spinner());
+            spinner.set_message(format!("{} is replying", name));
+            spinner.enable_steady_tick(Duration::from_millis(100));
+            spinner
+        });
+        // Without a terminal there is nothing to notify for either when progress is hidden
+        Self { name, started: std::time::Instant::now(), spinner, notified: !shown }
+    }
+
+    fn received(&mut self, chars: usize) {
+        if let Some(spinner) = &self.spinner {
+            spinner.set_message(format!("{} is replying, {} characters so far", self.name, chars));
+        } else if !self.notified && self.started.elapsed() >= PROGRESS_NOTIFICATION_AFTER {
+            self.notified = true;
+            notify::send(
+                &format!("Converting with {}", self.name),
+                &format!("{} characters received so far", chars),
+                Sound::Progress,
+            );
+        }
+    }
+}
+
+impl Drop for Live {
+    fn drop(&mut self) {
+        if let Some(spinner) = &self.spinner {
+            spinner.finish_and_clear();
+        }
+    }
+}
+
+/// Cuts `text` down to at most `limit` bytes on a character boundary, and says so
+fn truncate(text: &mut String, limit: usize, name: &str) {
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    eprintln!("Warning: the reply from {} passed max_reply_bytes ({}), the rest was cut off", name, limit);
+    events::emit("truncated", json!({ "provider": name, "bytes": end }));
+    notify::send(
+        "Reply cut off",
+        &format!("{} sent more than {} bytes; only the start was kept", name, limit),
+        Sound::Failure,
+    );
+}
+
+/// Whether `e` is the request running out of time, which is worth another try
+fn timed_out(e: &(dyn Error + 'static)) -> bool {
+    e.downcast_ref::<reqwest::Error>().is_some_and(reqwest::Error::is_timeout)
+}
+
+/// `wait` give or take up to a fifth, so instances that were turned away
+/// together don't all come back at the same moment
+fn jitter(wait: Duration) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+
+    // A freshly seeded hasher is random enough, without a dependency for it
+    let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    let fraction = (random % 1000) as f64 / 1000.0;
+    wait.mul_f64(0.8 + 0.4 * fraction)
+}
+
+/// How long the `retry-after` header of a response asks to wait, given in
+/// seconds or as an HTTP date
+pub fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get("retry-after")?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let seconds = (date.timestamp() - chrono::Utc::now().timestamp()).max(0);
+    Some(Duration::from_secs(seconds as u64))
+}
+
+/// Sleeps for `wait`, counting the seconds down on stderr when it is a terminal
+async fn countdown(name: &str, problem: &str, wait: Duration) {
+    let mut stderr = std::io::stderr();
+    if !stderr.is_terminal() {
+        tokio::time::sleep(wait).await;
+        return;
+    }
+    // The part of a second first, so the count lands on whole seconds
+    let mut remaining = wait.as_secs();
+    let fraction = wait - Duration::from_secs(remaining);
+    if !fraction.is_zero() {
+        let _ = write!(stderr, "\r{} {}, retrying in {}s ", name, problem, remaining + 1);
+        let _ = stderr.flush();
+        tokio::time::sleep(fraction).await;
+    }
+    while remaining > 0 {
+        let _ = write!(stderr, "\r{} {}, retrying in {}s ", name, problem, remaining);
+        let _ = stderr.flush();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        remaining -= 1;
+    }
+    let _ = write!(stderr, "\r\x1b[K");
+    let _ = stderr.flush();
+}
+
+/// Variables reqwest sends requests through a proxy for
+const PROXY_VARIABLES: &[&str] = &["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"];
+
+/// Resolves, with the reason, only if `provider`'s server can't be connected
+/// to, so offline a request fails right away instead of when it times out
+pub async fn unreachable(provider: &dyn OcrProvider) -> Box<dyn Error> {
+    // Behind a proxy the server may well be out of direct reach; the request
+    // finds out on its own
+    if PROXY_VARIABLES.iter().any(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty())) {
+        return std::future::pending().await;
+    }
+    let address = reqwest::Url::parse(provider.url())
+        .ok()
+        .and_then(|url| Some((url.host_str()?.to_string(), url.port_or_known_default()?)));
+    // Nothing to probe, the request will say what's wrong with the URL
+    let Some((host, port)) = address else {
+        return std::future::pending().await;
+    };
+    let connect = tokio::net::TcpStream::connect((host.as_str(), port));
+    match tokio::time::timeout(PROBE_TIMEOUT, connect).await {
+        Ok(Ok(_)) => std::future::pending().await,
+        Ok(Err(e)) => format!("offline? cannot reach {} ({})", host, e).into(),
+        Err(_) => format!("offline? {} didn't answer within {}s", host, PROBE_TIMEOUT.as_secs()).into(),
+    }
+}
+
+/// Determines the media type from the image's magic bytes, since preprocessing
+/// may have re-encoded it, falling back to the file extension
+pub fn media_type(image_data: &[u8], image_path: &str) -> &'static str {
+    if image_data.starts_with(b"\x89PNG") {
+        return "image/png";
+    }
+    if image_data.starts_with(&[0xFF, 0xD8]) {
+        return "image/jpeg";
+    }
+    if image_data.starts_with(b"GIF8") {
+        return "image/gif";
+    }
+    if image_data.starts_with(b"RIFF") && image_data.get(8..12) == Some(b"WEBP") {
+        return "image/webp";
+    }
+    if let Some(ext) = Path::new(image_path).extension() {
+        match ext.to_string_lossy().to_lowercase().as_str() {
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            _ => "image/jpeg",  // Default to JPEG
+        }
+    } else {
+        "image/jpeg"  // Default to JPEG if no extension
+    }
+}
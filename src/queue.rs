@@ -0,0 +1,154 @@
+//! Serialises overlapping invocations so rapid-fire screenshots are converted
+//! one after another, in the order they were triggered.
+//!
+//! Every invocation drops a ticket named after its start time into the queue
+//! directory and waits until no older live ticket is left. The ticket at the
+//! head of the queue also keeps a count of how many conversions the current
+//! burst has finished, which is what powers the "2 of 3" labels.
+//!
+//! A ticket stays locked by its invocation for as long as it waits and
+//! converts, so one a crashed invocation left behind is told apart by its
+//! lock being free.
+
+use std::fs::{self, File, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::atomic;
+use crate::lock::Lock;
+use crate::paths;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+const DONE_FILE: &str = "done";
+
+/// Where a conversion sits in the current burst of invocations
+#[derive(Debug, Clone, Copy)]
+pub struct Position {
+    pub index: usize,
+    pub total: usize,
+}
+
+impl Position {
+    /// " (2 of 3)" when other conversions are queued, empty otherwise
+    pub fn label(&self) -> String {
+        if self.total > 1 {
+            format!(" ({} of {})", self.index, self.total)
+        } else {
+            String::new()
+        }
+    }
+}
+
+/// This invocation's place in the queue; leaving the queue on drop lets the next one run
+pub struct Ticket {
+    dir: PathBuf,
+    path: PathBuf,
+    /// Holds the lock that shows the ticket is live
+    file: Option<File>,
+}
+
+impl Ticket {
+    pub fn take() -> io::Result<Self> {
+        let dir = paths::state_dir().join("queue");
+        fs::create_dir_all(&dir)?;
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let name = format!("{:020}-{}", nanos, std::process::id());
+        let path = dir.join(&name);
+        // Locked before it is in the queue, so no one takes it for a stale ticket
+        let pending = dir.join(format!(".{}.tmp", name));
+        let file = File::create(&pending)?;
+        file.lock()?;
+        fs::rename(&pending, &path)?;
+        Ok(Self { dir, path, file: Some(file) })
+    }
+
+    /// Our position right now, without waiting
+    pub fn position(&self) -> Position {
+        let tickets = live_tickets(&self.dir);
+        let ahead = tickets.iter().take_while(|ticket| **ticket != self.path).count();
+        let done = read_done(&self.dir);
+        Position {
+            index: done + ahead + 1,
+            total: done + tickets.len().max(ahead + 1),
+        }
+    }
+
+    /// Waits until every conversion queued before this one has finished
+    pub async fn wait_turn(&self) -> Position {
+        loop {
+            let tickets = live_tickets(&self.dir);
+            if tickets.first().is_none_or(|head| *head == self.path) {
+                return self.position();
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let _lock = Lock::exclusive(&self.dir.join("lock"));
+        let _ = fs::remove_file(&self.path);
+        drop(self.file.take());
+        let done_path = self.dir.join(DONE_FILE);
+        if live_tickets(&self.dir).is_empty() {
+            // End of the burst, the next invocation starts counting from one again
+            let _ = fs::remove_file(done_path);
+        } else {
+            let _ = atomic::write(&done_path, (read_done(&self.dir) + 1).to_string());
+        }
+    }
+}
+
+fn read_done(dir: &Path) -> usize {
+    fs::read_to_string(dir.join(DONE_FILE))
+        .ok()
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Tickets whose invocation is still running, oldest first; stale ones are removed
+fn live_tickets(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut tickets: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            if !is_ticket(path) {
+                return false;
+            }
+            if held(path) {
+                true
+            } else {
+                let _ = fs::remove_file(path);
+                false
+            }
+        })
+        .collect();
+    tickets.sort();
+    tickets
+}
+
+/// Whether `path` is named like a ticket, `<start time>-<pid>`
+fn is_ticket(path: &Path) -> bool {
+    let Some((stamp, pid)) = path.file_name().and_then(|name| name.to_str()?.split_once('-')) else {
+        return false;
+    };
+    // Skips the lock, the done count and tickets still being taken
+    !stamp.is_empty() && stamp.bytes().all(|b| b.is_ascii_digit()) && pid.parse::<u32>().is_ok()
+}
+
+/// Whether the invocation that took the ticket at `path` still holds its lock
+fn held(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    // Getting the lock means no one else has it; it is released as `file` closes
+    matches!(file.try_lock(), Err(TryLockError::WouldBlock))
+}
@@ -0,0 +1,136 @@
+//! Turning a screen recording, or a folder of frames exported from one, into
+//! the distinct images worth converting, for `latex_ocr recording`: frames are
+//! sampled every few seconds and ones that look like the frame kept before
+//! them are dropped, so a board that changes slowly is converted once per step.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::discovery::{self, Selection};
+use crate::paths;
+
+/// The frames of `path` in order: a folder's images by name, or a video
+/// sampled every `every_seconds` with ffmpeg into the data directory
+pub fn frames(path: &Path, every_seconds: u32) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if !path.exists() {
+        return Err("no such file or folder".into());
+    }
+    let directory = if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        extract(path, Some(every_seconds), "recordings")?
+    };
+    in_order(&directory)
+}
+
+/// The images in `directory` by name, the order frames are written in
+pub fn in_order(directory: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut frames: Vec<PathBuf> = discovery::images_in(directory, Selection::Modified)?
+        .into_iter()
+        .map(|candidate| candidate.path)
+        .collect();
+    // Frames are named in order, their times are all alike
+    frames.sort();
+    Ok(frames)
+}
+
+/// Writes one frame per `every_seconds` of `video`, or each of its frames, to
+/// a folder named after it in the data directory's `kind` folder
+pub fn extract(video: &Path, every_seconds: Option<u32>, kind: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let directory = frame_directory(video, kind)?;
+    let mut command = Command::new("ffmpeg");
+    command.args(["-nostdin", "-loglevel", "error", "-i"]).arg(video);
+    match every_seconds {
+        Some(seconds) => command.arg("-vf").arg(format!("fps=1/{}", seconds.max(1))),
+        None => command.args(["-vsync", "0"]),
+    };
+    let output = command
+        .arg(directory.join("frame-%05d.png"))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("reading frames needs ffmpeg installed: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("ffmpeg failed: {}", stderr.lines().next().unwrap_or("no error output")).into());
+    }
+    Ok(directory)
+}
+
+/// An empty folder for the frames of `video`, named after it in the data
+/// directory's `kind` folder
+pub fn frame_directory(video: &Path, kind: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let name = video.file_stem().ok_or("the recording's path has no file name")?;
+    let directory = paths::data_dir().join(kind).join(name);
+    // Frames left from an earlier run at another rate would mix in
+    if directory.exists() {
+        fs::remove_dir_all(&directory)?;
+    }
+    fs::create_dir_all(&directory)?;
+    Ok(directory)
+}
+
+/// `frames` without the ones that look like the last frame kept, within
+/// `similarity` of 64 bits of their difference hash
+#[cfg(feature = "preprocess")]
+pub fn distinct(frames: &[PathBuf], similarity: u32) -> Vec<PathBuf> {
+    let mut kept = Vec::new();
+    let mut last: Option<u64> = None;
+    for frame in frames {
+        let hash = match fs::read(frame).map_err(Box::<dyn Error>::from).and_then(|data| difference_hash(&data)) {
+            Ok(hash) => hash,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", frame.display(), e);
+                continue;
+            }
+        };
+        if last.is_some_and(|last| (last ^ hash).count_ones() <= similarity) {
+            continue;
+        }
+        last = Some(hash);
+        kept.push(frame.clone());
+    }
+    kept
+}
+
+/// Without image decoding only frames that are byte for byte the same as the
+/// last one kept are dropped
+#[cfg(not(feature = "preprocess"))]
+pub fn distinct(frames: &[PathBuf], _similarity: u32) -> Vec<PathBuf> {
+    let mut kept = Vec::new();
+    let mut last: Option<String> = None;
+    for frame in frames {
+        let Ok(data) = fs::read(frame) else {
+            eprintln!("Skipping {}, it can't be read", frame.display());
+            continue;
+        };
+        let hash = crate::pipeline::sha256_hex(&data);
+        if last.as_ref() == Some(&hash) {
+            continue;
+        }
+        last = Some(hash);
+        kept.push(frame.clone());
+    }
+    kept
+}
+
+/// Which of each pair of neighbouring pixels is brighter, over the image
+/// shrunk to 9x8 in grayscale; similar images differ in few bits
+#[cfg(feature = "preprocess")]
+fn difference_hash(data: &[u8]) -> Result<u64, Box<dyn Error>> {
+    let small = image::load_from_memory(data)?
+        .grayscale()
+        .resize_exact(9, 8, image::imageops::FilterType::Triangle)
+        .to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Ok(hash)
+}
@@ -0,0 +1,265 @@
+//! Masking personal details before an image is uploaded: text a local
+//! tesseract run finds that looks like an email address or phone number, or
+//! spells one of `redact_names`, is covered with a solid box.
+
+/// What `redact` can list
+pub const KINDS: &[&str] = &["emails", "phones", "names"];
+
+#[cfg(feature = "preprocess")]
+pub use masking::{apply, configure, enabled};
+
+/// Without image decoding there is nothing to mask with; `validate` rejects `redact`
+#[cfg(not(feature = "preprocess"))]
+pub fn configure(_config: &crate::settings::AppConfig) {}
+
+#[cfg(not(feature = "preprocess"))]
+pub fn enabled() -> bool {
+    false
+}
+
+#[cfg(not(feature = "preprocess"))]
+pub fn apply(_image_data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Err("redaction needs a build with the `preprocess` feature".into())
+}
+
+#[cfg(feature = "preprocess")]
+mod masking {
+    use std::error::Error;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use std::sync::OnceLock;
+
+    use crate::settings::AppConfig;
+
+    /// Pixels of margin around a masked word, so antialiased edges are covered too
+    const PADDING: u32 = 2;
+
+    /// Phone numbers have at least this many digits, and at most
+    const PHONE_DIGITS: std::ops::RangeInclusive<usize> = 7..=15;
+
+    struct Rules {
+        emails: bool,
+        phones: bool,
+        /// Each name's words, lowercased
+        names: Vec<Vec<String>>,
+    }
+
+    static RULES: OnceLock<Rules> = OnceLock::new();
+
+    /// Turns redaction on for this process if `redact` lists anything
+    pub fn configure(config: &AppConfig) {
+        let listed = |kind: &str| config.redact.iter().any(|listed| listed == kind);
+        if config.redact.is_empty() {
+            return;
+        }
+        let names = if listed("names") {
+            config.redact_names.iter().map(|name| words(name)).filter(|name| !name.is_empty()).collect()
+        } else {
+            Vec::new()
+        };
+        let _ = RULES.set(Rules { emails: listed("emails"), phones: listed("phones"), names });
+    }
+
+    pub fn enabled() -> bool {
+        RULES.get().is_some()
+    }
+
+    /// A word tesseract recognized, with its bounding box
+    struct Word {
+        /// Block, paragraph and line number; words of a line share it
+        line: (u32, u32, u32),
+        left: u32,
+        top: u32,
+        width: u32,
+        height: u32,
+        text: String,
+    }
+
+    /// `image_data` with what `redact` asks for masked, re-encoded as PNG; the
+    /// original bytes when nothing matched
+    pub fn apply(image_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let Some(rules) = RULES.get() else {
+            return Ok(image_data.to_vec());
+        };
+        let words = recognize(image_data)?;
+        let masked = rules.matches(&words);
+        if masked.is_empty() {
+            return Ok(image_data.to_vec());
+        }
+        let mut image = image::load_from_memory(image_data)?.to_rgb8();
+        let (width, height) = image.dimensions();
+        for word in masked {
+            let right = (word.left + word.width + PADDING).min(width);
+            let bottom = (word.top + word.height + PADDING).min(height);
+            for y in word.top.saturating_sub(PADDING)..bottom {
+                for x in word.left.saturating_sub(PADDING)..right {
+                    image.put_pixel(x, y, image::Rgb([0, 0, 0]));
+                }
+            }
+        }
+        crate::preprocess::encode_png(&image)
+    }
+
+    /// Words and their boxes from `tesseract ... tsv`
+    fn recognize(image_data: &[u8]) -> Result<Vec<Word>, Box<dyn Error>> {
+        let mut child = Command::new("tesseract")
+            .args(["stdin", "stdout", "tsv"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("redaction needs tesseract installed: {}", e))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let output = std::thread::scope(|scope| {
+            // Written from another thread so a full stdout pipe can't stall both sides
+            scope.spawn(move || stdin.write_all(image_data));
+            child.wait_with_output()
+        })?;
+        if !output.status.success() {
+            return Err(format!("tesseract exited with {}", output.status).into());
+        }
+        let tsv = String::from_utf8_lossy(&output.stdout);
+        // level, page, block, paragraph, line, word, left, top, width, height, confidence, text
+        let words = tsv
+            .lines()
+            .skip(1)
+            .filter_map(|row| {
+                let fields: Vec<&str> = row.split('\t').collect();
+                let number = |index: usize| fields.get(index)?.parse::<u32>().ok();
+                let text = fields.get(11)?.trim();
+                // Level 5 rows are words, the others their lines, paragraphs and blocks
+                if number(0)? != 5 || text.is_empty() {
+                    return None;
+                }
+                Some(Word {
+                    line: (number(2)?, number(3)?, number(4)?),
+                    left: number(6)?,
+                    top: number(7)?,
+                    width: number(8)?,
+                    height: number(9)?,
+                    text: text.to_string(),
+                })
+            })
+            .collect();
+        Ok(words)
+    }
+
+    impl Rules {
+        /// The words to mask
+        fn matches<'a>(&self, recognized: &'a [Word]) -> Vec<&'a Word> {
+            let mut masked = Vec::new();
+            for line in recognized.chunk_by(|a, b| a.line == b.line) {
+                if self.emails {
+                    masked.extend(line.iter().filter(|word| is_email(&word.text)));
+                }
+                if self.phones {
+                    masked.extend(phone_numbers(line));
+                }
+                for name in &self.names {
+                    let texts: Vec<String> = line.iter().map(|word| words(&word.text).concat()).collect();
+                    for start in 0..line.len() {
+                        if texts[start..].starts_with(name) {
+                            masked.extend(&line[start..start + name.len()]);
+                        }
+                    }
+                }
+            }
+            masked
+        }
+    }
+
+    /// `name@domain.tld`, maybe with punctuation around it
+    fn is_email(text: &str) -> bool {
+        let text = text.trim_matches(|c: char| !c.is_alphanumeric());
+        text.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty()
+                && domain.split('.').count() > 1
+                && domain.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '-'))
+        })
+    }
+
+    /// Runs of words in a line that together spell a phone number, e.g.
+    /// "+1 (555) 123-4567"; decimals and short numbers in math are left alone
+    fn phone_numbers(line: &[Word]) -> Vec<&Word> {
+        let phone_part = |text: &str| {
+            let digits = text.chars().filter(char::is_ascii_digit).count();
+            let decimal = text.split_once('.').is_some_and(|(whole, fraction)| {
+                !whole.is_empty() && !fraction.contains('.') && whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+            });
+            // A country code like "+1" is a part too
+            let enough = digits >= 2 || (digits > 0 && text.starts_with('+'));
+            enough && !decimal && text.chars().all(|c| c.is_ascii_digit() || "+-().".contains(c))
+        };
+        let mut found = Vec::new();
+        for run in line.chunk_by(|a, b| phone_part(&a.text) == phone_part(&b.text)) {
+            let digits: usize = run.iter().map(|word| word.text.chars().filter(char::is_ascii_digit).count()).sum();
+            if phone_part(&run[0].text) && PHONE_DIGITS.contains(&digits) {
+                found.extend(run);
+            }
+        }
+        found
+    }
+
+    /// The lowercased alphanumeric words of `text`
+    fn words(text: &str) -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// The words of `text` as tesseract would find them, on one line
+        fn line(text: &str) -> Vec<Word> {
+            text.split_whitespace()
+                .enumerate()
+                .map(|(index, text)| Word {
+                    line: (1, 1, 1),
+                    left: index as u32 * 10,
+                    top: 0,
+                    width: 8,
+                    height: 8,
+                    text: text.to_string(),
+                })
+                .collect()
+        }
+
+        fn masked(rules: &Rules, text: &str) -> Vec<String> {
+            rules.matches(&line(text)).into_iter().map(|word| word.text.clone()).collect()
+        }
+
+        fn rules(names: &[&str]) -> Rules {
+            Rules { emails: true, phones: true, names: names.iter().map(|name| words(name)).collect() }
+        }
+
+        #[test]
+        fn emails_are_masked() {
+            assert_eq!(masked(&rules(&[]), "mail (ada@example.org) for x^2"), ["(ada@example.org)"]);
+            assert!(masked(&rules(&[]), "f@x = 2 and a@b").is_empty());
+        }
+
+        #[test]
+        fn phones_are_masked() {
+            assert_eq!(masked(&rules(&[]), "call +1 (555) 123-4567 today"), ["+1", "(555)", "123-4567"]);
+            assert_eq!(masked(&rules(&[]), "x = 0301 2345678"), ["0301", "2345678"]);
+        }
+
+        #[test]
+        fn decimals_in_math_are_not_phones() {
+            assert!(masked(&rules(&[]), "\\pi = 3.14159 \\approx 3.14 + 2.71 1.41 1.73").is_empty());
+            assert!(masked(&rules(&[]), "x = 12 + 34 - (56)").is_empty());
+            assert_eq!(masked(&rules(&[]), "ada@example.org 1.234 5.678"), ["ada@example.org"]);
+            assert_eq!(masked(&rules(&[]), "3.14 555-123-4567 2.71"), ["555-123-4567"]);
+        }
+
+        #[test]
+        fn listed_names_are_masked() {
+            let rules = rules(&["Ada Lovelace"]);
+            assert_eq!(masked(&rules, "Notes by Ada Lovelace, 1843"), ["Ada", "Lovelace,"]);
+            assert!(masked(&rules, "Ada Byron").is_empty());
+        }
+    }
+}
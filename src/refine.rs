@@ -0,0 +1,44 @@
+//! The fast path: deliver the quick model's result right away, then check it
+//! against a stronger model and only speak up when they disagree.
+
+use latex_ocr::normalize;
+
+/// Whether the results differ in more than spelling, spacing or delimiters
+pub fn disagrees(quick: &str, refined: &str) -> bool {
+    normalize::math(quick) != normalize::math(refined)
+}
+
+/// A word diff of the two results, `[-removed-]` and `{+added+}` inline
+pub fn diff(quick: &str, refined: &str) -> String {
+    let old: Vec<&str> = quick.split_whitespace().collect();
+    let new: Vec<&str> = refined.split_whitespace().collect();
+
+    // Longest common subsequence table, from the end
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut words = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            words.push(old[i].to_string());
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            words.push(format!("{{+{}+}}", new[j]));
+            j += 1;
+        } else {
+            words.push(format!("[-{}-]", old[i]));
+            i += 1;
+        }
+    }
+    words.join(" ")
+}
@@ -0,0 +1,66 @@
+//! `batch --report`: one HTML file with every image next to its rendered
+//! LaTeX and the code, for proofreading a whole batch in the browser. The
+//! images are embedded, so the file can be moved or sent on its own; the
+//! math is typeset by MathJax, loaded when the page is opened.
+
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::atomic;
+use crate::document;
+use crate::html::escape;
+use crate::pipeline::Conversion;
+use crate::provider;
+
+const MATHJAX: &str = "https://cdn.jsdelivr.net/npm/mathjax@3/es5/tex-chtml.js";
+
+const STYLE: &str = "body { font: 14px -apple-system, sans-serif; margin: 2em; }\n\
+    section { display: grid; grid-template-columns: 1fr 1fr; gap: 2em; padding: 1.5em 0; border-bottom: 1px solid #ddd; }\n\
+    h2 { grid-column: 1 / -1; margin: 0; font-size: 1em; color: #666; }\n\
+    img { max-width: 100%; border: 1px solid #ddd; }\n\
+    .rendered { overflow-x: auto; }\n\
+    pre { background: #f6f6f6; padding: 1em; white-space: pre-wrap; }";
+
+/// Writes the report for `conversions` to `path`
+pub fn write(path: &Path, title: &str, conversions: &[Conversion]) -> Result<(), Box<dyn Error>> {
+    let mut html = format!(
+        "<!doctype html>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n\
+         <script>window.MathJax = {{ tex: {{ inlineMath: [['$', '$'], ['\\\\(', '\\\\)']] }} }};</script>\n\
+         <script async src=\"{}\"></script>\n<h1>{}</h1>\n",
+        escape(title),
+        STYLE,
+        MATHJAX,
+        escape(title)
+    );
+    for (index, conversion) in conversions.iter().enumerate() {
+        let name = Path::new(&conversion.image)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        html.push_str(&format!("<section id=\"{}\">\n<h2>{}. {}</h2>\n", index + 1, index + 1, escape(&name)));
+        match fs::read(&conversion.image) {
+            Ok(data) => html.push_str(&format!(
+                "<img src=\"data:{};base64,{}\" alt=\"{}\">\n",
+                provider::media_type(&data, &conversion.image),
+                BASE64.encode(&data),
+                escape(conversion.alt_text.as_deref().unwrap_or_default())
+            )),
+            Err(e) => html.push_str(&format!("<p>{} is gone: {}</p>\n", escape(&conversion.image), escape(&e.to_string()))),
+        }
+        let latex = conversion.latex.trim();
+        let rendered = if document::is_bare_math(latex) {
+            format!("\\[{}\\]", latex)
+        } else {
+            latex.to_string()
+        };
+        html.push_str(&format!(
+            "<div>\n<div class=\"rendered\">{}</div>\n<pre>{}</pre>\n</div>\n</section>\n",
+            escape(&rendered),
+            escape(latex)
+        ));
+    }
+    atomic::write(path, html)?;
+    Ok(())
+}
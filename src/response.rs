@@ -0,0 +1,30 @@
+//! Parsing provider responses into typed structs, so schema drift fails with
+//! the path of the field that didn't match instead of a generic error.
+
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DEBUG: AtomicBool = AtomicBool::new(false);
+
+/// Attach raw response bodies to parse errors (`--debug`)
+pub fn enable_debug() {
+    DEBUG.store(true, Ordering::Relaxed);
+}
+
+/// Deserializes `body`, reporting e.g. "unexpected response shape: missing
+/// field `text` at content[0]"
+pub fn parse<T: DeserializeOwned>(body: &str) -> Result<T, Box<dyn Error>> {
+    let mut deserializer = serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+        let path = e.path().to_string();
+        let mut message = format!("unexpected response shape: {}", e.inner());
+        if path != "." {
+            message.push_str(&format!(" at {}", path));
+        }
+        if DEBUG.load(Ordering::Relaxed) {
+            message.push_str(&format!("\nraw response body:\n{}", body));
+        }
+        message.into()
+    })
+}
@@ -0,0 +1,94 @@
+//! `latex_ocr batch --resume`: what a batch has converted so far, kept in the
+//! state directory so a crashed or cancelled run can pick up where it stopped.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::atomic;
+use crate::paths;
+use crate::pipeline::Conversion;
+
+/// The running batch as it was started, and the images it has finished
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub inputs: Vec<String>,
+    pub document: Option<String>,
+    pub compile: bool,
+    #[serde(default)]
+    pub report: Option<String>,
+    #[serde(default)]
+    pub only_new: bool,
+    done: Vec<Conversion>,
+}
+
+fn checkpoint_path() -> PathBuf {
+    paths::state_dir().join("batch.json")
+}
+
+impl Checkpoint {
+    /// Starts over for a new batch, replacing any earlier checkpoint. Paths
+    /// are kept absolute so the batch can be resumed from anywhere
+    pub fn start(
+        inputs: &[String],
+        document: Option<&str>,
+        compile: bool,
+        report: Option<&str>,
+        only_new: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let checkpoint = Self {
+            inputs: inputs.iter().map(|input| absolute(input)).collect(),
+            document: document.map(absolute),
+            compile,
+            report: report.map(absolute),
+            only_new,
+            done: Vec::new(),
+        };
+        checkpoint.save()?;
+        Ok(checkpoint)
+    }
+
+    /// The interrupted batch, if there is one
+    pub fn load() -> Result<Option<Self>, Box<dyn Error>> {
+        let path = checkpoint_path();
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map(Some)
+                .map_err(|e| format!("{} is damaged: {}", path.display(), e).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The earlier result for `image`, provided it hasn't changed since
+    pub fn find(&self, image: &str, image_hash: &str) -> Option<&Conversion> {
+        self.done
+            .iter()
+            .find(|conversion| conversion.image == image && conversion.image_hash == image_hash)
+    }
+
+    /// Remembers one more finished image
+    pub fn record(&mut self, conversion: &Conversion) -> Result<(), Box<dyn Error>> {
+        self.done.retain(|done| done.image != conversion.image);
+        self.done.push(conversion.clone());
+        self.save()
+    }
+
+    /// Forgets the batch once it has completed
+    pub fn clear(self) {
+        let _ = fs::remove_file(checkpoint_path());
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        atomic::write(&checkpoint_path(), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+fn absolute(path: &str) -> String {
+    let path = shellexpand::tilde(path);
+    std::path::absolute(path.as_ref())
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
@@ -0,0 +1,86 @@
+//! Reviewing low-confidence results in an editor before they are delivered,
+//! or every result with `confirm_result`.
+
+use std::error::Error;
+use std::fs;
+use std::io::IsTerminal;
+
+use crate::dialog;
+use crate::settings::AppConfig;
+use crate::shell;
+
+/// Editor opened when `review_command` is empty and neither `$VISUAL` nor
+/// `$EDITOR` is set; `waits` says whether it returns only once closed
+#[cfg(target_os = "macos")]
+const FALLBACK_EDITOR: (&str, bool) = ("open -W -n -t {file}", true);
+#[cfg(not(target_os = "macos"))]
+const FALLBACK_EDITOR: (&str, bool) = ("xdg-open {file}", false);
+
+/// Starts the instructions line, which is dropped again afterwards
+const HEADER_PREFIX: &str = "% latex_ocr:";
+
+/// Opens `latex` in the review editor and returns what was saved, or `None`
+/// if the file was emptied to discard the result
+pub fn edit(config: &AppConfig, latex: &str, confidence: Option<u32>) -> Result<Option<String>, Box<dyn Error>> {
+    let confidence = match confidence {
+        Some(confidence) => format!("{}%", confidence),
+        None => "unknown".to_string(),
+    };
+    open(config, latex, &format!("confidence {}", confidence))
+}
+
+/// Shows `latex` for `confirm_result` and returns it as accepted, maybe edited,
+/// or `None` if it was rejected. A one-line result gets a dialog when there is
+/// no terminal to edit in, anything else the review editor
+pub fn confirm(config: &AppConfig, latex: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let latex = latex.trim();
+    if std::io::stdin().is_terminal() || latex.contains('\n') {
+        return open(config, latex, "Check the result");
+    }
+    Ok(dialog::edit("Check the Result", "Accept to deliver it, edited or not; cancel discards it", latex))
+}
+
+/// Opens `latex` in the review editor below a header starting with `note`
+fn open(config: &AppConfig, latex: &str, note: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("latex_ocr-review-{}.tex", std::process::id()));
+    let header = format!(
+        "{} {}. Fix the result, save and close; empty the file to discard it.\n",
+        HEADER_PREFIX, note
+    );
+    fs::write(&path, format!("{}{}\n", header, latex.trim()))?;
+
+    let (template, waits) = editor(&config.review_command);
+    let (mut editor, command) = shell::run_template(&template, "file", &path);
+    let status = editor.status();
+    let reviewed = match status {
+        // An editor that returns right away can't tell us when the review is done
+        Ok(status) if status.success() && !waits && !dialog::confirm("Review", "Deliver the saved result?") => {
+            Ok(String::new())
+        }
+        Ok(status) if status.success() => fs::read_to_string(&path),
+        Ok(status) => Err(std::io::Error::other(format!("`{}` exited with {}", command, status))),
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_file(&path);
+
+    let reviewed = reviewed?;
+    let body: Vec<&str> = reviewed.lines().filter(|line| !line.starts_with(HEADER_PREFIX)).collect();
+    let body = body.join("\n").trim().to_string();
+    Ok(Some(body).filter(|body| !body.is_empty()))
+}
+
+/// The shell command opening `{file}`, `review_command` unless that's empty,
+/// and whether it waits for the editor to close
+pub fn editor(review_command: &str) -> (String, bool) {
+    if !review_command.trim().is_empty() {
+        return (review_command.to_string(), true);
+    }
+    for variable in ["VISUAL", "EDITOR"] {
+        if let Ok(editor) = std::env::var(variable)
+            && !editor.trim().is_empty()
+        {
+            return (format!("{} {{file}}", editor), true);
+        }
+    }
+    (FALLBACK_EDITOR.0.to_string(), FALLBACK_EDITOR.1)
+}
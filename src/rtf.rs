@@ -0,0 +1,89 @@
+//! Minimal RTF rendering of LaTeX source with syntax highlighting, so rich
+//! clipboard managers show something nicer than a wall of backslashes.
+
+const HEADER: &str = "{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0\\fmodern Menlo;}}\
+{\\colortbl;\\red0\\green0\\blue0;\\red0\\green70\\blue170;\\red170\\green40\\blue40;\\red110\\green110\\blue110;\\red30\\green120\\blue30;}\
+\\f0\\fs24 ";
+
+// Indices into the color table above
+const PLAIN: u8 = 1;
+const COMMAND: u8 = 2;
+const MATH_SHIFT: u8 = 3;
+const BRACE: u8 = 4;
+const COMMENT: u8 = 5;
+
+/// Renders `latex` as an RTF document with commands, braces, math shifts and comments colored
+pub fn highlight_latex(latex: &str) -> String {
+    let mut out = String::from(HEADER);
+    let mut chars = latex.chars().peekable();
+    let mut color = PLAIN;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                set_color(&mut out, &mut color, COMMAND);
+                push_escaped(&mut out, c);
+                if let Some(&next) = chars.peek() {
+                    if next.is_ascii_alphabetic() {
+                        while let Some(&letter) = chars.peek().filter(|l| l.is_ascii_alphabetic()) {
+                            push_escaped(&mut out, letter);
+                            chars.next();
+                        }
+                    } else {
+                        // Control symbols like \\ or \{ are a single character
+                        push_escaped(&mut out, next);
+                        chars.next();
+                    }
+                }
+            }
+            '%' => {
+                set_color(&mut out, &mut color, COMMENT);
+                push_escaped(&mut out, c);
+                while let Some(&rest) = chars.peek().filter(|r| **r != '\n') {
+                    push_escaped(&mut out, rest);
+                    chars.next();
+                }
+            }
+            '$' => {
+                set_color(&mut out, &mut color, MATH_SHIFT);
+                push_escaped(&mut out, c);
+            }
+            '{' | '}' | '[' | ']' | '&' | '^' | '_' => {
+                set_color(&mut out, &mut color, BRACE);
+                push_escaped(&mut out, c);
+            }
+            _ => {
+                set_color(&mut out, &mut color, PLAIN);
+                push_escaped(&mut out, c);
+            }
+        }
+    }
+
+    out.push('}');
+    out
+}
+
+fn set_color(out: &mut String, current: &mut u8, wanted: u8) {
+    if *current != wanted {
+        out.push_str(&format!("\\cf{} ", wanted));
+        *current = wanted;
+    }
+}
+
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '\\' => out.push_str("\\\\"),
+        '{' => out.push_str("\\{"),
+        '}' => out.push_str("\\}"),
+        '\n' => out.push_str("\\line\n"),
+        '\t' => out.push_str("\\tab "),
+        c if c.is_ascii() => out.push(c),
+        c => {
+            // RTF stores non-ASCII as signed 16-bit code units with an ASCII fallback
+            let mut units = [0u16; 2];
+            for unit in c.encode_utf16(&mut units) {
+                out.push_str(&format!("\\u{}?", *unit as i16));
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! `latex_ocr session start/stop`: groups the conversions in between, shares
+//! them as context and assembles them into one .tex document at the end.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::atomic;
+use crate::document::{self, Section};
+use crate::lock::Lock;
+use crate::paths;
+
+/// How much of the session so far is sent along as context
+const CONTEXT_CHARS: usize = 4000;
+
+/// The running session, stored as numbered entry files in the state directory
+pub struct Session {
+    dir: PathBuf,
+}
+
+fn session_dir() -> PathBuf {
+    paths::state_dir().join("session")
+}
+
+/// Starts a session named `name` (a timestamped "lecture-…" when not given)
+pub fn start(name: Option<&str>) -> Result<Session, Box<dyn Error>> {
+    if let Some(session) = active() {
+        return Err(format!("session `{}` is already running", session.name()).into());
+    }
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => {
+            let started = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+            format!("lecture-{}", started.as_secs())
+        }
+    };
+    let dir = session_dir();
+    fs::create_dir_all(&dir)?;
+    atomic::write(&dir.join("name"), &name)?;
+    Ok(Session { dir })
+}
+
+/// The running session, if any
+pub fn active() -> Option<Session> {
+    let dir = session_dir();
+    dir.join("name").exists().then_some(Session { dir })
+}
+
+impl Session {
+    pub fn name(&self) -> String {
+        fs::read_to_string(self.dir.join("name"))
+            .map(|name| name.trim().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Entries in capture order
+    pub fn entries(&self) -> Vec<String> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "tex"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .collect()
+    }
+
+    /// Appends one delivered result
+    pub fn record(&self, latex: &str) -> Result<(), Box<dyn Error>> {
+        // Numbering reads the directory first, keep concurrent captures from taking the same number
+        let _lock = Lock::exclusive(&self.dir.join("lock"))?;
+        let index = self.entries().len() + 1;
+        atomic::write(&self.dir.join(format!("{:04}.tex", index)), latex.trim())?;
+        Ok(())
+    }
+
+    /// The tail of the session so the model keeps macros and numbering consistent
+    pub fn context(&self) -> Option<String> {
+        let entries = self.entries();
+        if entries.is_empty() {
+            return None;
+        }
+        let so_far = entries.join("\n\n");
+        let start = so_far
+            .char_indices()
+            .rev()
+            .nth(CONTEXT_CHARS)
+            .map_or(0, |(index, _)| index);
+        Some(format!(
+            "This screenshot continues a lecture ({} earlier captures). Keep using the same macros, notation and equation numbering as what came before:\n{}",
+            entries.len(),
+            &so_far[start..]
+        ))
+    }
+
+    /// Writes the assembled document to `output` (`NAME.tex` in the current
+    /// directory by default) and ends the session
+    pub fn finish(self, preamble: &str, output: Option<&Path>) -> Result<PathBuf, Box<dyn Error>> {
+        let name = self.name();
+        let output = match output {
+            Some(path) => path.to_path_buf(),
+            None => PathBuf::from(format!("{}.tex", name)),
+        };
+        let entries = self.entries();
+        let sections: Vec<Section> = entries
+            .iter()
+            .map(|latex| Section { title: None, latex })
+            .collect();
+        atomic::write(&output, document::assemble(preamble, &name, &sections))?;
+        fs::remove_dir_all(&self.dir)?;
+        Ok(output)
+    }
+}
@@ -0,0 +1,1273 @@
+//! User configuration, read from `~/.config/latex_ocr/config.toml`.
+
+use config::{Config, ConfigError, File};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use latex_ocr::postprocess;
+
+use crate::anthropic;
+use crate::atomic;
+use crate::clipboard;
+use crate::discovery::Selection;
+use crate::document_class;
+use crate::format::Format;
+use crate::gemini;
+use crate::hotkey;
+use crate::language::{Accents, Rtl};
+use crate::metadata;
+use crate::migrate;
+use crate::numbers::Locale;
+#[cfg(feature = "ollama")]
+use crate::ollama;
+use crate::output::Output;
+use crate::paths;
+use crate::openai;
+use crate::provider::{Backend, OcrProvider};
+use crate::redact;
+use crate::shrink;
+use crate::target::{self, Target};
+use crate::watermark::{Crop, Mask};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    /// Where conversions are sent: Anthropic (with `api_key`), OpenAI, Gemini
+    /// or a local Ollama server
+    pub provider: Backend,
+    pub api_key: String,
+    /// Messages API endpoint, for gateways and proxies in front of Anthropic
+    pub anthropic_url: String,
+    pub openai_api_key: String,
+    /// Chat Completions endpoint, for OpenAI-compatible servers
+    pub openai_url: String,
+    pub gemini_api_key: String,
+    /// generativelanguage API root the model path is appended to
+    pub gemini_url: String,
+    /// Address of the Ollama server
+    pub ollama_url: String,
+    /// Ollama model conversions go to when `provider` can't be reached; empty
+    /// fails them as offline instead
+    pub offline_model: String,
+    /// Names this user or machine to a gateway: appended to the User-Agent and
+    /// sent as the request's user metadata where the provider has a field for it
+    pub client_tag: String,
+    /// Directory to scan; empty means the system's screenshot location
+    pub image_directory: String,
+    pub model: String,
+    /// Stronger model that re-checks every result after `model`'s has been
+    /// delivered, offering its own only when they disagree; empty is off
+    pub refine_model: String,
+    /// Second model sent every image at the same time as `model`; the first
+    /// acceptable answer wins and the other request is cancelled. Empty is off
+    pub race_model: String,
+    pub prompt: String,
+    /// Extended thinking tokens before the model answers (0 disables it); hard
+    /// equations benefit, but only models with extended thinking accept it
+    pub thinking_budget: u32,
+    /// Also put a syntax-highlighted RTF flavor on the clipboard (macOS)
+    pub clipboard_rtf: bool,
+    /// Push each equation as its own clipboard event before the full result
+    pub clipboard_split_equations: bool,
+    /// Pause between split clipboard events so clipboard managers record each one
+    pub clipboard_event_delay_ms: u64,
+    /// Convert the image on the clipboard when there is one, before looking in
+    /// `image_directory`
+    pub from_clipboard: bool,
+    /// How the clipboard copy is written: latex, katex or mathml
+    pub clipboard_target: Target,
+    /// Targets for the apps they are pasted into, picked by the frontmost app
+    pub clipboard_targets: HashMap<String, Target>,
+    /// What makes an image the most recent: its mtime, birth time or the
+    /// timestamp in its filename (for synced folders, where mtimes lie)
+    pub selection: Selection,
+    /// Newest image older than this (e.g. "10m") isn't auto-selected; empty disables the check
+    pub max_age: String,
+    /// Offer this many of the most recent images to pick from instead of
+    /// confirming the newest; 0 or 1 only ever offers the newest
+    pub pick_recent: usize,
+    /// How often a request the provider rejects as overloaded or rate limited (529,
+    /// 429, 503), or that times out, is retried with a growing pause in between;
+    /// 0 fails right away
+    pub overloaded_retries: u32,
+    /// Bytes of a reply kept before the rest is cut off with a warning, against
+    /// runaway replies repeating themselves; 0 keeps everything
+    pub max_reply_bytes: u64,
+    /// How often a failed conversion is retried silently before it is reported
+    pub failure_retries: u32,
+    /// Failures in a row before a failure notification plays a sound; the ones
+    /// before it are silent. 1 sounds every time
+    pub failure_sound_after: u32,
+    /// Failures in a row before a failure is shown in a dialog that has to be
+    /// dismissed instead of a notification; 0 never does
+    pub failure_dialog_after: u32,
+    /// Ask a cheap model whether the image contains math and confirm again if it doesn't
+    pub math_check: bool,
+    pub math_check_model: String,
+    /// Ask the model for the visible citation/DOI/arXiv ID and append it as a `% source:` comment
+    pub cite_source: bool,
+    /// Keep the layout of page excerpts (columns, figure placement, footnotes)
+    /// with multicols, minipages and figure environments instead of one stream
+    pub preserve_layout: bool,
+    /// Tell the model where the screenshot came from (frontmost app, window
+    /// title, capture type); sends the window title along
+    pub metadata_hints: bool,
+    /// Send the previous conversion's result along as context, like `--previous`
+    pub context_previous: bool,
+    /// Tera template wrapping every result, e.g. `\begin{equation}{{ latex }}\end{equation}`;
+    /// empty copies the bare result
+    pub template: String,
+    /// Markup results are written in without a template: latex, org, org-export,
+    /// rst, markdown or mathml rewritten from LaTeX, or typst or asciimath
+    /// written by the model; `output_format` is another name for it
+    #[serde(alias = "output_format")]
+    pub format: Format,
+    /// Cleanup steps run over every reply, in order: "preamble", "fences",
+    /// "display-brackets", "display-dollars" and "trim"
+    pub postprocess: Vec<String>,
+    /// Rewrite numbers written the way this locale does ("de", "fr", "de-CH", ...)
+    /// with a decimal point and no thousands separators; empty leaves them alone
+    pub number_locale: String,
+    /// Ask which language any prose is in and wrap results that aren't in
+    /// English in babel's `otherlanguage` environment
+    pub detect_language: bool,
+    /// Accented letters and quotes: keep (UTF-8), escape (`\'e`, `\enquote`) or transliterate
+    pub accents: Accents,
+    /// Markup around Arabic and Hebrew prose: none, polyglossia, babel or bidi
+    pub rtl_markup: Rtl,
+    /// Also describe each result in plain English for alt text, as an `% alt:`
+    /// comment and, on macOS, an HTML clipboard flavor with an aria-label
+    pub alt_text: bool,
+    /// Open results the model is less confident about than this (0-100) in an
+    /// editor before delivering them; 0 never asks for a review
+    pub review_below: u32,
+    /// Opens `{file}` for review and returns once it is closed; empty uses
+    /// `$VISUAL`/`$EDITOR`, or TextEdit on macOS
+    pub review_command: String,
+    /// Show every result, editable, before it is delivered: accepting delivers
+    /// what is shown, rejecting discards it
+    pub confirm_result: bool,
+    /// How many of the latest results corrected in review are sent along as
+    /// examples of the notation wanted; 0 sends none
+    pub correction_examples: usize,
+    /// Keep success notifications open to clicks (macOS), which show the
+    /// history entry with its image; the process waits until it is dismissed
+    pub notification_links: bool,
+    /// Don't deliver a result whose normalized LaTeX is already in the history
+    /// (duplicates are always reported)
+    pub skip_duplicates: bool,
+    /// Start each result written to a file output with a comment block saying
+    /// where it came from (image, time, model, profile, image hash)
+    pub provenance: bool,
+    /// Where results go, each in its own format; empty means just the clipboard
+    pub outputs: Vec<Output>,
+    /// Everything before `\begin{document}` in assembled documents (sessions, `batch --document`)
+    pub document_preamble: String,
+    /// Compiles an assembled document for `batch --compile`; `{file}` is replaced
+    pub latex_command: String,
+    /// Compile each result alone with `latex_command` before delivering it, and
+    /// warn with the first error if it doesn't compile
+    pub compile_check: bool,
+    /// Class of the document results are pasted into (amsart, beamer, exam, ...):
+    /// the prompt mentions it and environments it lacks are rewritten; empty for none
+    pub document_class: String,
+    /// Personal details masked in images before they are sent, found with a
+    /// local tesseract: "emails", "phones" and "names" (those in `redact_names`)
+    pub redact: Vec<String>,
+    /// Names "names" in `redact` masks, e.g. the user's own in a menu bar
+    pub redact_names: Vec<String>,
+    /// Pixels cut off the edges of every image before it is sent, where a
+    /// screenshot tool stamps its watermark
+    pub watermark_crop: Crop,
+    /// Fixed regions painted over before sending, for watermarks inside the image
+    pub watermark_masks: Vec<Mask>,
+    /// Images taller than this many pixels are converted in tiles this tall,
+    /// and their results merged; 0 sends every image whole
+    pub tile_height: u32,
+    /// Pixels each tile repeats of the one above, so no row is only ever cut in half
+    pub tile_overlap: u32,
+    /// Cut the margin around what an image shows before sending it
+    pub upload_trim: bool,
+    /// Send images in grayscale
+    pub upload_grayscale: bool,
+    /// Scale images down so neither side is longer than this many pixels; 0 keeps their size
+    pub upload_max_dimension: u32,
+    /// What images are re-encoded as before sending, "png" or "jpeg"
+    pub upload_format: String,
+    /// JPEG quality (1-100) with `upload_format = "jpeg"`
+    pub upload_quality: u8,
+    /// Shell command selecting a screen region into `{output}` for `capture`;
+    /// empty uses screencapture on macOS and maim, grim or the like elsewhere
+    pub capture_command: String,
+    /// Shell command recording `{seconds}` of audio to `{output}` for `--dictate`;
+    /// empty uses ffmpeg on macOS and arecord elsewhere
+    pub record_command: String,
+    pub dictation_seconds: u32,
+    /// `recording` samples a frame of a video every this many seconds
+    pub recording_frame_seconds: u32,
+    /// How many of the 64 bits of a frame's difference hash may differ from the
+    /// last frame kept for `recording` to drop it as the same picture
+    pub recording_similarity: u32,
+    /// How many images `convert` with several images or `--all-since`
+    /// converts at once
+    pub convert_jobs: usize,
+    /// Whisper-compatible transcription endpoint used by `--dictate`
+    pub transcription_url: String,
+    pub transcription_api_key: String,
+    pub transcription_model: String,
+    /// How long `watch` waits for more screenshots after one arrives, so a
+    /// burst is offered once instead of one notification per image
+    pub watch_burst_ms: u64,
+    /// Most screenshots offered as one burst; the rest start the next one
+    pub watch_burst_max: usize,
+    /// Most conversions `watch` starts per minute while working through a
+    /// burst; 0 doesn't limit them
+    pub watch_requests_per_minute: u32,
+    /// When not empty, `watch` converts screenshots taken in these apps without
+    /// asking and ignores all others; matched case-insensitively
+    pub watch_apps: Vec<String>,
+    /// Key combination `latex_ocr hotkey` listens for, e.g. "CmdOrCtrl+Shift+L"
+    pub hotkey: String,
+    /// The subcommand a hotkey press runs: capture, convert, snip or paste
+    pub hotkey_command: String,
+    /// Ask before converting each new screenshot in `watch`; false converts
+    /// and copies them right away
+    pub watch_confirm: bool,
+    /// Named bundles of settings selected with `--profile`
+    pub profiles: HashMap<String, Profile>,
+    /// Profiles used without `--profile` when the frontmost app, or a word in
+    /// its window title (for sites like Overleaf in a browser), matches
+    pub app_profiles: HashMap<String, String>,
+    /// What is being transcribed, selected with `--mode`; picks the prompt and model
+    pub modes: HashMap<String, Mode>,
+}
+
+/// Reads a region the way Live Text would, but with the math as LaTeX
+const SNIP_PROMPT: &str = "Transcribe all of the text in this image exactly as it reads, writing any mathematics as latex: inline math in $...$ and displayed equations in \\[...\\]. Only output the transcription, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.";
+
+/// Settings that vary with the kind of image being converted
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// Name it was selected by, for provenance comments
+    #[serde(skip)]
+    pub name: Option<String>,
+    /// `--mode` applied on top, for provenance comments
+    #[serde(skip)]
+    pub mode: Option<String>,
+    /// Preprocessing applied to the image before upload, e.g. "whiteboard"
+    pub preprocess: Option<String>,
+    /// Replaces the top-level prompt
+    pub prompt: Option<String>,
+    /// Overrides the top-level `cite_source`
+    pub cite_source: Option<bool>,
+    /// Overrides the top-level `preserve_layout`
+    pub preserve_layout: Option<bool>,
+    /// Replaces the top-level `template`
+    pub template: Option<String>,
+    /// Replaces the top-level `format`
+    pub format: Option<Format>,
+    /// Replaces the top-level and per-app `clipboard_target`
+    pub clipboard_target: Option<Target>,
+    /// Replaces the top-level `thinking_budget`
+    pub thinking_budget: Option<u32>,
+    /// Replaces the top-level `number_locale`
+    pub number_locale: Option<String>,
+    /// Replaces the top-level `model`
+    pub model: Option<String>,
+    /// Replaces the top-level `refine_model`
+    pub refine_model: Option<String>,
+    /// Also list the symbols used and their likely meanings as a nomenclature table
+    pub glossary: Option<bool>,
+    /// Yes/no question `math_check` asks instead of whether the image has math
+    pub check: Option<String>,
+    /// Checks the reply before it is delivered, e.g. "fen"
+    pub validate: Option<String>,
+}
+
+/// A kind of content with its own prompt and the model suited to it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Mode {
+    pub prompt: Option<String>,
+    pub model: Option<String>,
+    /// Like the profile setting of the same name
+    pub glossary: Option<bool>,
+    /// Like the profile setting of the same name, for content that isn't math
+    pub check: Option<String>,
+    /// Like the profile setting of the same name
+    pub validate: Option<String>,
+}
+
+impl Mode {
+    /// Built-in modes; config entries of the same name override them field by field
+    fn builtin(name: &str) -> Option<Self> {
+        let (prompt, model) = match name {
+            "equation" => (None, "claude-3-5-haiku-20241022"),
+            "table" => (Some(TABLE_PROMPT), "claude-3-5-sonnet-20241022"),
+            "tikz" => (Some(TIKZ_PROMPT), "claude-3-opus-20240229"),
+            // Handwritten multi-part answers need the stronger model
+            "exam" => (Some(EXAM_PROMPT), "claude-3-5-sonnet-20241022"),
+            "worksheet" => (Some(WORKSHEET_PROMPT), "claude-3-5-sonnet-20241022"),
+            // Reading unfamiliar notation well takes the stronger model
+            "glossary" => {
+                return Some(Self {
+                    model: Some("claude-3-5-sonnet-20241022".to_string()),
+                    glossary: Some(true),
+                    ..Self::default()
+                });
+            }
+            // Experimental: reading rhythms and accidentals takes the stronger model
+            "lilypond" => {
+                return Some(Self {
+                    prompt: Some(LILYPOND_PROMPT.to_string()),
+                    model: Some("claude-3-5-sonnet-20241022".to_string()),
+                    check: Some(LILYPOND_CHECK.to_string()),
+                    ..Self::default()
+                });
+            }
+            // Estimating coordinates off the axes takes the strongest model
+            "plot" => {
+                return Some(Self {
+                    prompt: Some(PLOT_PROMPT.to_string()),
+                    model: Some("claude-3-opus-20240229".to_string()),
+                    check: Some(PLOT_CHECK.to_string()),
+                    ..Self::default()
+                });
+            }
+            // Slides mix prose, layout and math, which takes the stronger model
+            "slide" => {
+                return Some(Self {
+                    prompt: Some(SLIDE_PROMPT.to_string()),
+                    model: Some("claude-3-5-sonnet-20241022".to_string()),
+                    check: Some(SLIDE_CHECK.to_string()),
+                    ..Self::default()
+                });
+            }
+            "chess" => {
+                return Some(Self {
+                    prompt: Some(CHESS_PROMPT.to_string()),
+                    model: Some("claude-3-5-sonnet-20241022".to_string()),
+                    check: Some(CHESS_CHECK.to_string()),
+                    validate: Some("fen".to_string()),
+                    ..Self::default()
+                });
+            }
+            _ => return None,
+        };
+        Some(Self {
+            prompt: prompt.map(str::to_string),
+            model: Some(model.to_string()),
+            ..Self::default()
+        })
+    }
+
+    /// `self` with unset fields taken from `fallback`
+    fn or(self, fallback: Self) -> Self {
+        Self {
+            prompt: self.prompt.or(fallback.prompt),
+            model: self.model.or(fallback.model),
+            glossary: self.glossary.or(fallback.glossary),
+            check: self.check.or(fallback.check),
+            validate: self.validate.or(fallback.validate),
+        }
+    }
+}
+
+const TABLE_PROMPT: &str = "Convert the table in this image to a latex tabular environment, keeping merged cells, alignment and rules. Only output latex code corresponding to the image, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.";
+
+const TIKZ_PROMPT: &str = "Recreate the diagram in this image as a tikzpicture, keeping its layout, labels and arrows. Only output the tikzpicture environment, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.";
+
+const EXAM_PROMPT: &str = "Transcribe the solutions on this exam or homework answer sheet to latex. Put the problems in an enumerate environment with one \\item per problem, numbered as on the sheet (e.g. \\item[3.]), and the parts of each problem in a nested enumerate labelled as on the sheet (e.g. \\item[(a)]). Keep each part's work and final answer in the order written, displayed equations in align* environments, and leave an empty \\item with a % TODO comment for any part that is missing or unreadable. Only output the enumerate environment, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.";
+
+const WORKSHEET_PROMPT: &str = "Rebuild the problem set or worksheet in this image as a skeleton for the latex exam class: a questions environment with one \\question per problem (with its points as \\question[5] where the sheet gives them) and its parts as \\part inside a parts environment, subparts as \\subpart inside subparts. Transcribe every problem statement as written, math included, and put an empty solution environment with a % TODO comment, \\begin{solution} % TODO \\end{solution}, after each question without parts and after each part, for the answers to be filled in later. Only output the questions environment, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.";
+
+const LILYPOND_PROMPT: &str = "Transcribe the music in this image to LilyPond source. Use \\relative pitches, keep the clef, key and time signatures, durations, ties, slurs, articulations, dynamics and any lyrics, and put each staff in its own \\new Staff inside a \\score with bar checks (|) at every barline. Only output the LilyPond source, don't put anything else in the response. Don't nest in a code block either or preface it with anything.";
+
+const LILYPOND_CHECK: &str = "Does this image contain musical notation, such as a staff with notes? Reply with a single word: yes or no.";
+
+const PLOT_PROMPT: &str = "Recreate the graph in this image as pgfplots code: a tikzpicture with an axis environment. Read the axis labels, tick range, legend and title and set xlabel, ylabel, xmin, xmax, ymin, ymax, legend entries and title to match. For each curve, if it looks like a recognisable function write it as an \\addplot expression with a domain, otherwise estimate its data points off the axes and write them as \\addplot coordinates, with a % approximate comment. Only output the tikzpicture environment, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.";
+
+const PLOT_CHECK: &str = "Does this image show a plotted graph or chart with axes? Reply with a single word: yes or no.";
+
+const SLIDE_PROMPT: &str = "Rebuild the presentation slide in this image as a beamer frame: \\begin{frame}{Title} with the slide's title (and \\framesubtitle if it has one), its bullet points as itemize or enumerate environments nested the way they are indented, \\alert or \\textbf where the slide emphasizes text, math as inline $...$ or display equations, and side-by-side content in a columns environment. Put a \\includegraphics[width=\\linewidth]{figure} with a % TODO comment describing it wherever the slide shows a picture or plot, and leave out slide numbers, logos, footers and navigation symbols. Only output the frame environment, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.";
+
+const SLIDE_CHECK: &str = "Does this image show a presentation slide? Reply with a single word: yes or no.";
+
+const CHESS_PROMPT: &str = "Read the chess position in this image and write it as \\chessboard[setfen=FEN] for the latex chessboard package, where FEN is the full Forsyth-Edwards Notation of the position: piece placement from rank 8 to rank 1 as seen from White's side (flip a board shown from Black's side), then the side to move, castling rights, en passant square and move counters, using w, KQkq, - and 0 1 where the image doesn't say. Only output the \\chessboard command, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.";
+
+const CHESS_CHECK: &str = "Does this image show a chessboard with pieces on it? Reply with a single word: yes or no.";
+
+impl Profile {
+    /// How the profile was picked, e.g. "papers, mode table"
+    pub fn describe(&self) -> String {
+        let name = self.name.as_deref().unwrap_or("default");
+        match &self.mode {
+            Some(mode) => format!("{}, mode {}", name, mode),
+            None => name.to_string(),
+        }
+    }
+
+    /// Profiles that work without any configuration; config entries of the same name win
+    fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "whiteboard" => Some(Self {
+                preprocess: Some("whiteboard".to_string()),
+                ..Self::default()
+            }),
+            "snip" => Some(Self {
+                prompt: Some(SNIP_PROMPT.to_string()),
+                ..Self::default()
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            provider: Backend::Anthropic,
+            api_key: String::new(),
+            anthropic_url: "https://api.anthropic.com/v1/messages".to_string(),
+            openai_api_key: String::new(),
+            openai_url: "https://api.openai.com/v1/chat/completions".to_string(),
+            gemini_api_key: String::new(),
+            gemini_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
+            ollama_url: "http://localhost:11434".to_string(),
+            offline_model: String::new(),
+            client_tag: String::new(),
+            image_directory: String::new(),
+            model: "claude-3-5-haiku-20241022".to_string(),
+            refine_model: String::new(),
+            race_model: String::new(),
+            prompt: "Convert the following text to latex, if there is any latex. Only output latex code corresponding to the image, don't put anything else in the response. Don't nest in a code block either or preface with the words latex.".to_string(),
+            thinking_budget: 0,
+            clipboard_rtf: false,
+            clipboard_split_equations: false,
+            clipboard_event_delay_ms: 800,
+            from_clipboard: false,
+            clipboard_target: Target::Latex,
+            clipboard_targets: HashMap::new(),
+            selection: Selection::Modified,
+            max_age: String::new(),
+            pick_recent: 0,
+            overloaded_retries: 3,
+            max_reply_bytes: 64 * 1024,
+            failure_retries: 0,
+            failure_sound_after: 1,
+            failure_dialog_after: 0,
+            math_check: false,
+            math_check_model: "claude-3-5-haiku-20241022".to_string(),
+            cite_source: false,
+            preserve_layout: false,
+            metadata_hints: false,
+            context_previous: false,
+            template: String::new(),
+            format: Format::Latex,
+            postprocess: ["preamble", "fences", "trim"].map(String::from).to_vec(),
+            number_locale: String::new(),
+            detect_language: false,
+            accents: Accents::Keep,
+            rtl_markup: Rtl::None,
+            alt_text: false,
+            review_below: 0,
+            review_command: String::new(),
+            confirm_result: false,
+            correction_examples: 0,
+            notification_links: false,
+            skip_duplicates: false,
+            provenance: false,
+            outputs: Vec::new(),
+            document_preamble: "\\documentclass{article}\n\\usepackage{amsmath,amssymb}".to_string(),
+            latex_command: "pdflatex -interaction=nonstopmode -halt-on-error {file}".to_string(),
+            compile_check: false,
+            document_class: String::new(),
+            record_command: String::new(),
+            capture_command: String::new(),
+            redact: Vec::new(),
+            watermark_crop: Crop::default(),
+            watermark_masks: Vec::new(),
+            tile_height: 0,
+            tile_overlap: 150,
+            upload_trim: false,
+            upload_grayscale: false,
+            upload_max_dimension: 0,
+            upload_format: "png".to_string(),
+            upload_quality: 85,
+            redact_names: Vec::new(),
+            dictation_seconds: 10,
+            recording_frame_seconds: 5,
+            recording_similarity: 6,
+            convert_jobs: 4,
+            transcription_url: "https://api.openai.com/v1/audio/transcriptions".to_string(),
+            transcription_api_key: String::new(),
+            transcription_model: "whisper-1".to_string(),
+            watch_burst_ms: 1000,
+            watch_burst_max: 20,
+            watch_requests_per_minute: 0,
+            watch_apps: Vec::new(),
+            watch_confirm: true,
+            hotkey: "CmdOrCtrl+Shift+L".to_string(),
+            hotkey_command: "capture".to_string(),
+            profiles: HashMap::new(),
+            app_profiles: HashMap::new(),
+            modes: HashMap::new(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Where the config file is, whether or not it exists yet
+    pub fn path() -> PathBuf {
+        paths::config_dir().join("config.toml")
+    }
+
+    pub fn load() -> Result<Self, ConfigError> {
+        // Add configuration from config file if it exists
+        let config_path = Self::path();
+        
+        // If config file doesn't exist, create a default one
+        if !config_path.exists() {
+            let default_config = r#"
+# Format version of this file, managed by latex_ocr
+version = 1
+
+# Anthropic API key (required unless another provider is picked below)
+api_key = ""
+
+# Where Anthropic requests go, to use a gateway or proxy instead
+# anthropic_url = "https://api.anthropic.com/v1/messages"
+
+# Send conversions to OpenAI instead, e.g. with model = "gpt-4o": its API key
+# then takes the place of api_key. Every *_model setting names a model of the
+# chosen provider, and thinking_budget becomes the reasoning_effort of reasoning
+# models like o3 (low below 4096, medium below 16384). openai_url can
+# point at any server with an OpenAI-compatible Chat Completions API.
+# provider = "openai"
+# openai_api_key = ""
+# openai_url = "https://api.openai.com/v1/chat/completions"
+
+# Or to Google's Gemini, e.g. with model = "gemini-2.5-flash", using the key from
+# Google AI Studio; thinking_budget works for Gemini 2.5 models as well
+# provider = "gemini"
+# gemini_api_key = ""
+# gemini_url = "https://generativelanguage.googleapis.com/v1beta"
+
+# Or to vision models running locally in Ollama, offline and free, e.g. after
+# `ollama pull qwen2.5vl` set model = "qwen2.5vl" (llava works too, less well).
+# Needs latex_ocr built with the `ollama` feature (the default); no key is used
+# provider = "ollama"
+# ollama_url = "http://localhost:11434"
+
+# A conversion whose provider can't be reached fails within a few seconds as
+# offline. With the `ollama` feature it can go to this local model instead
+# offline_model = "qwen2.5vl"
+
+# Tag requests with who sent them, for gateways attributing usage per user or
+# machine: the User-Agent becomes "latex_ocr/<version> (<tag>)", and Anthropic
+# and OpenAI requests also carry it as their user metadata. Printable ASCII only
+# client_tag = ""
+
+# Directory to scan for recent images; defaults to where macOS saves screenshots
+# (the com.apple.screencapture location), or ~/Downloads elsewhere
+# image_directory = "~/Downloads"
+
+# Model to use for image processing
+model = "claude-3-5-haiku-20241022"
+
+# Fast path: copy the result of `model` right away, then re-run the image with
+# this stronger model in the background and only notify you, with a word diff,
+# if it reads the image differently (profiles can set their own)
+# refine_model = "claude-3-5-sonnet-20241022"
+
+# Race mode, for when latency matters more than cost: send every image to this
+# model too, at the same time, and take whichever acceptable answer arrives
+# first (the slower request is cancelled, but both are billed)
+# race_model = "claude-3-5-sonnet-20241022"
+
+# Prompt to send with the image
+prompt = "Convert the following text to latex, if there is any latex. Only output latex code corresponding to the image, don't put anything else in the response. Don't nest in a code block either or preface with the words latex."
+
+# Let the model think before answering, in tokens (0 is off, at least 1024 with Anthropic).
+# Needs a model with extended thinking, e.g. claude-3-7-sonnet-20250219;
+# profiles can set their own
+# thinking_budget = 0
+
+# Also put a syntax-highlighted RTF copy on the clipboard (macOS)
+# clipboard_rtf = false
+
+# Push each equation as a separate clipboard event so clipboard managers keep them all
+# clipboard_split_equations = false
+# clipboard_event_delay_ms = 800
+
+# Convert the image on the clipboard (e.g. from Cmd+Ctrl+Shift+4) when there is
+# one and only look in image_directory otherwise; --from-clipboard does this for
+# one run. Clipboard images are kept in ~/.local/share/latex_ocr/clipboard so the
+# history can show them.
+# from_clipboard = false
+
+# Write the clipboard copy for the app it's pasted into: "latex" as is (Overleaf),
+# "katex" with $$ displays and only environments KaTeX knows (Notion, Obsidian)
+# or "mathml" (Word). --target NAME or a profile's clipboard_target picks one for
+# a single run; otherwise clipboard_targets decides by the app in front, and
+# clipboard_target is used for every other app.
+# clipboard_target = "latex"
+#
+# [clipboard_targets]
+# Notion = "katex"
+# "Microsoft Word" = "mathml"
+
+# How the most recent image is picked: "modified" (mtime), "created" (birth
+# time) or "filename" (the timestamp in names like "Screenshot 2024-05-01 at
+# 10.23.45.png", other files fall back to mtime); synced folders often rewrite
+# mtimes, the filename keeps the time the screenshot was taken
+# selection = "modified"
+
+# Don't auto-select the newest image if it is older than this ("90s", "10m", "2h");
+# a file picker opens instead
+# max_age = "10m"
+
+# Instead of asking whether to convert the newest image, list this many of the
+# most recent ones (with how long ago each was taken) and convert the one
+# picked, for when several screenshots were taken in a row; 5 to 10 works well
+# pick_recent = 0
+
+# Retry requests the provider turns away as overloaded or rate limited, or that
+# time out, this many times, waiting about 5s, 10s, 20s, ... up to a minute in
+# between, or as long as the provider's retry-after says if that's no more than a
+# minute (0 gives up right away)
+# overloaded_retries = 3
+
+# Cut a reply off after this many bytes, with a warning, so a model stuck
+# repeating itself can't flood the clipboard (0 never cuts)
+# max_reply_bytes = 65536
+
+# How failed conversions escalate: retry quietly failure_retries times first,
+# then notify without a sound, with the failure sound from the
+# failure_sound_after-th failure in a row, and with a dialog from the
+# failure_dialog_after-th (0 never). Any success starts the count over.
+# failure_retries = 0
+# failure_sound_after = 1
+# failure_dialog_after = 0
+
+# Before converting, ask a cheap model whether the image contains math at all
+# and ask again for confirmation if it doesn't
+# math_check = false
+# math_check_model = "claude-3-5-haiku-20241022"
+
+# Ask the model for the citation, DOI or arXiv ID visible in a paper screenshot
+# and append it as a "% source:" comment (profiles can override this)
+# cite_source = false
+
+# Rebuild the layout of page excerpts instead of flattening them into one
+# stream: two columns as a multicols environment (add \usepackage{multicol} to
+# your preamble), side-by-side blocks as minipages, figures and tables where
+# they sit, and footnotes as \footnote at their marks. Profiles can override it,
+# --preserve-layout turns it on for one run
+# preserve_layout = false
+
+# Tell the model where a fresh screenshot came from ("a window of paper.pdf in
+# Preview") so context-dependent notation is read right; sends the window title
+# metadata_hints = false
+
+# Send the previous result along so a sequence of related screenshots uses the
+# same notation (same as --previous; --context TEXT and --ask-context add more)
+# context_previous = false
+
+# Tera template wrapping each result before it is copied. In scope: latex, source
+# (from cite_source, may be empty), image (path) and equations (the result split
+# into separate equations). Profiles can set their own to match the destination.
+# template = '\begin{equation}{{ latex }}\end{equation}'
+#
+# [profiles.org]
+# template = '''
+# #+begin_export latex
+# {{ latex }}
+# #+end_export'''
+#
+# [profiles.html]
+# template = '<span class="math">\({{ latex }}\)</span>'
+
+# Markup results are written in when there's no template: "latex" as returned,
+# "org" (bare math as \[...\], $...$ as \(...\)), "org-export" (a
+# #+begin_export latex block), "rst" (a .. math:: directive or :math: roles),
+# "markdown" ($$...$$ displays, $...$ inline) or "mathml", all rewritten from
+# the LaTeX; with "typst" or "asciimath" the model is asked to write that
+# instead. Also settable as output_format, or --output-format for one run
+# format = "latex"
+
+# Cleanup run over every reply, in this order: "preamble" drops lead-ins like
+# "Here is the LaTeX:", "fences" unwraps ```latex code fences, "display-brackets"
+# rewrites $$...$$ as \[...\] (or "display-dollars" the other way round) and
+# "trim" drops surrounding whitespace. [] keeps replies exactly as sent
+# postprocess = ["preamble", "fences", "trim"]
+
+# Read numbers the way this locale writes them and rewrite them with a decimal
+# point and no thousands separators, e.g. "de" turns 1.234,56 into 1234.56 and
+# "fr" turns 1 234,5 into 1234.5 (also "de-CH", "en", "es", "it", "nl", "sv", ...);
+# profiles can set their own
+# number_locale = "de"
+
+# Ask the model which language any prose in the image is in and wrap results
+# that aren't English in \begin{otherlanguage}{ngerman}...\end{otherlanguage}
+# for babel
+# detect_language = false
+
+# Accented letters and typographic quotes in the result: "keep" them as UTF-8
+# (LuaLaTeX, XeLaTeX, inputenc), "escape" them as \'e, \ss{} and \enquote{...}
+# (csquotes), or "transliterate" the letters to plain ASCII
+# accents = "keep"
+
+# Mark up Arabic and Hebrew prose for the RTL package your documents use:
+# "polyglossia" (\textarabic{...}, \texthebrew{...}), "babel"
+# (\foreignlanguage{arabic}{...}), "bidi" (\RL{...}) or "none"
+# rtl_markup = "none"
+
+# Also describe each result in plain English ("x squared plus y squared equals r
+# squared") for accessible course materials: appended as an % alt: comment and,
+# on macOS, put on the clipboard as HTML with the description as its aria-label
+# so rich editors keep it as alt text; templates can use {{ alt }}
+# alt_text = false
+
+# Ask the model how confident it is (0-100) and open results below this
+# threshold in an editor first, so only doubtful ones interrupt you; save and
+# close to deliver the edited result, empty the file to discard it (0 is off)
+# review_below = 80
+# review_command = "code --wait {file}"
+
+# Show every result before it is copied instead of copying it silently: in a
+# dialog you can edit it in (accept delivers it, cancel discards it), or in the
+# review editor above when running in a terminal or for multi-line results.
+# --no-confirm skips it for one run
+# confirm_result = false
+
+# Both versions of a result fixed in review are kept in the history; this sends
+# the latest few corrections with every request as examples, so the model picks
+# up your recurring notation (0 sends none)
+# correction_examples = 5
+
+# Make success notifications clickable (macOS): clicking opens the history
+# entry (image, LaTeX and metadata) in your browser. latex_ocr stays running
+# until the notification is clicked or dismissed
+# notification_links = false
+
+# A result identical to an earlier one in the history is reported ("identical to
+# conversion from Mar 3"); set this to also skip copying it
+# skip_duplicates = false
+
+# Start every result written to a file output with a comment block recording
+# the image, time, model, profile and image hash (never on the clipboard);
+# outputs can set their own provenance = true/false
+# provenance = false
+
+# Outputs replace the single clipboard copy; every output renders the result with
+# its own template or format (falling back to the ones above). kind is clipboard, file
+# (appended to path) or webhook (POSTs {"text", "results"} JSON to url).
+# [[outputs]]
+# kind = "clipboard"
+#
+# [[outputs]]
+# kind = "file"
+# path = "~/notes/equations.tex"
+# template = '''
+# \begin{equation}
+# {{ latex }}
+# \end{equation}'''
+#
+# [[outputs]]
+# kind = "file"
+# path = "~/org/math.org"
+# format = "org"
+#
+# [[outputs]]
+# kind = "webhook"
+# url = "https://example.com/hooks/latex"
+
+# Preamble of documents assembled by `session stop` and `batch --document`,
+# and the command `batch --compile` checks them with
+# document_preamble = '''
+# \documentclass{article}
+# \usepackage{amsmath,amssymb}'''
+# latex_command = "pdflatex -interaction=nonstopmode -halt-on-error {file}"
+
+# Compile every result in a minimal document (document_preamble, then the
+# result) with latex_command before it is copied, and warn in the notification
+# with LaTeX's first error if it doesn't compile; it is still copied. Faster
+# with e.g. latex_command = "tectonic {file}" once tectonic's cache is warm
+# compile_check = false
+
+# The kind of document you paste results into: "article", "amsart", "amsbook",
+# "beamer" or "exam". The model is told what the class expects (e.g. \question
+# for exams, nothing float-like on slides) and environments it doesn't have are
+# rewritten or dropped, e.g. figure becomes center for beamer
+# document_class = "amsart"
+
+# Mask personal details in screenshots of work documents before they are sent:
+# words a local OCR run (tesseract, which has to be installed) reads as email
+# addresses, phone numbers or any of redact_names are covered with black boxes.
+# Costs a second or so per image
+# redact = ["emails", "phones", "names"]
+# redact_names = ["Jane Doe", "Acme Corp"]
+
+# Keep a screenshot tool's watermark or credit out of the result: crop cuts
+# pixels off the edges, and each mask paints a rectangle white (a negative x or
+# y counts from the right or bottom edge). Both apply before anything else
+# watermark_crop = { bottom = 40 }
+# watermark_masks = [{ x = -220, y = -40, width = 220, height = 40 }]
+
+# Very tall images (a scrolled-through page of notes) are read better a piece
+# at a time: taller than tile_height pixels, they are cut into tiles that tall,
+# each repeating tile_overlap pixels of the one above, converted one after the
+# other and merged with the rows the tiles share kept once. 0 never tiles
+# tile_height = 2000
+# tile_overlap = 150
+
+# Large retina screenshots cost tokens and upload time for pixels the model
+# doesn't need. Each image (or tile) can be trimmed to what it shows, turned
+# grayscale and scaled down so no side is longer than upload_max_dimension
+# (0 keeps the size) before it is sent, re-encoded as PNG or as JPEG at
+# upload_quality. All of this needs the `preprocess` feature
+# upload_trim = true
+# upload_grayscale = true
+# upload_max_dimension = 1568
+# upload_format = "jpeg"
+# upload_quality = 85
+
+# `latex_ocr capture` selects a region with screencapture on macOS and the first
+# of maim, grim with slurp, gnome-screenshot and spectacle installed elsewhere;
+# this command is used instead, writing the image to {output}
+# capture_command = "flameshot gui --raw > {output}"
+
+# --dictate records a short clip, transcribes it and converts the spoken math
+# record_command = "ffmpeg -loglevel error -f avfoundation -i :0 -t {seconds} -y {output}"
+# dictation_seconds = 10
+# transcription_url = "https://api.openai.com/v1/audio/transcriptions"
+# transcription_api_key = ""
+# transcription_model = "whisper-1"
+
+# `latex_ocr recording lecture.mp4` samples a frame every recording_frame_seconds
+# with ffmpeg (or takes a folder of exported frames as they are), drops frames
+# that look like the last one kept and converts the rest as a batch. Frames whose
+# 64-bit difference hashes differ in at most recording_similarity bits count as
+# the same picture; raise it if a lecturer walking past adds frames, lower it if
+# small additions to the board are missed
+# recording_frame_seconds = 5
+# recording_similarity = 6
+
+# `latex_ocr convert a.png b.png` or `convert --all-since 10m` converts several
+# images, convert_jobs of them at a time, and delivers the results together
+# (or with --separate, each to a file next to its image)
+# convert_jobs = 4
+
+# A burst of screenshots arriving in `watch` (e.g. a scripted export) within
+# this many ms of each other is offered once and converted one at a time, at
+# most watch_requests_per_minute of them per minute (0 is unlimited). A burst
+# holds at most watch_burst_max screenshots; the ones after it start another
+# watch_burst_ms = 1000
+# watch_burst_max = 20
+# watch_requests_per_minute = 0
+
+# Only watch screenshots of these apps, and convert them without asking. The app
+# is the one in front when the screenshot appears (the window class on Linux).
+# watch_apps = ["Preview", "zoom.us", "Notability"]
+
+# Convert every new screenshot in `watch` without asking first, each result
+# copied with its own notification (as --no-confirm does for one run)
+# watch_confirm = true
+
+# `latex_ocr hotkey` stays running and, whenever this key combination is pressed
+# anywhere, runs `latex_ocr <hotkey_command>`: capture (select a region), convert
+# (the newest screenshot), snip or paste. Modifiers are Cmd, Ctrl, Alt/Option,
+# Shift and CmdOrCtrl (Cmd on macOS, Ctrl elsewhere)
+# hotkey = "CmdOrCtrl+Shift+L"
+# hotkey_command = "capture"
+
+# Profiles bundle settings for a kind of image and are picked with --profile NAME.
+# "whiteboard" is built in: it whitens the background, cuts glare and keeps marker strokes.
+# "snip" is built in too: it transcribes prose and math together (used by `latex_ocr snip`).
+# [profiles.whiteboard]
+# preprocess = "whiteboard"
+# prompt = "Convert the handwriting on this whiteboard photo to latex..."
+#
+# [profiles.papers]
+# cite_source = true
+# preserve_layout = true
+
+# Without --profile, the profile for the app in front when latex_ocr starts. A
+# name matches the app or part of its window title, so a site like Overleaf is
+# picked up in any browser; the window title is checked only when no app matches.
+# [app_profiles]
+# Overleaf = "overleaf"
+# Obsidian = "notes"
+# "Microsoft Word" = "word"
+#
+# [profiles.notes]
+# clipboard_target = "katex"
+#
+# [profiles.word]
+# clipboard_target = "mathml"
+
+# Modes say what is being transcribed and are picked with --mode NAME; each sets
+# the prompt and the model suited to it. Built in: "equation" (haiku), "table"
+# (a tabular, sonnet), "tikz" (a tikzpicture, opus), "exam" (numbered problems
+# and lettered parts as nested enumerates, sonnet), "worksheet" (a problem set
+# as exam-class questions with empty solution environments to fill in, sonnet),
+# "glossary" (the LaTeX plus a nomenclature table of the symbols used and their
+# likely meanings) and the experimental "lilypond" (sheet music as LilyPond source, sonnet), "plot" (a
+# pgfplots axis reproducing a graph, opus), "slide" (a beamer frame rebuilt from
+# a presentation slide, sonnet) and "chess" (a \chessboard[setfen=...] position,
+# sonnet). `check` replaces the question math_check asks before converting, and
+# `validate = "fen"` rejects replies that aren't a legal FEN (bare or in a
+# setfen option).
+# Entries here fill in or override the built-in ones.
+# [modes.table]
+# model = "claude-3-7-sonnet-20250219"
+#
+# [modes.chem]
+# prompt = "Convert the chemical equations in this image to latex using mhchem..."
+# model = "claude-3-5-sonnet-20241022"
+"#;
+            let _ = atomic::write(&config_path, default_config);
+        }
+        migrate::config(&config_path).map_err(ConfigError::Message)?;
+        
+        // Load from config file
+        let settings = Config::builder()
+            .add_source(File::from(config_path))
+            .build()?;
+        
+        // Try to convert the loaded configuration into our AppConfig struct
+        let config: Self = settings.try_deserialize()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks values serde can't, so mistakes surface at startup
+    fn validate(&self) -> Result<(), ConfigError> {
+        if cfg!(not(feature = "ollama")) && self.provider == Backend::Ollama {
+            return Err(ConfigError::Message(
+                "provider: \"ollama\" needs latex_ocr built with `--features ollama`".to_string(),
+            ));
+        }
+        if self.client_tag.chars().any(|c| !c.is_ascii_graphic() && c != ' ') {
+            return Err(ConfigError::Message(
+                "client_tag: only printable ASCII characters can go in a User-Agent".to_string(),
+            ));
+        }
+        if cfg!(not(feature = "ollama")) && !self.offline_model.trim().is_empty() {
+            return Err(ConfigError::Message(
+                "offline_model: falling back to Ollama needs latex_ocr built with `--features ollama`".to_string(),
+            ));
+        }
+        for kind in &self.redact {
+            if !redact::KINDS.contains(&kind.as_str()) {
+                return Err(ConfigError::Message(format!(
+                    "redact: expected {}, not `{}`",
+                    redact::KINDS.join(", "),
+                    kind
+                )));
+            }
+        }
+        for name in &self.postprocess {
+            if !postprocess::NAMES.contains(&name.as_str()) {
+                return Err(ConfigError::Message(format!(
+                    "postprocess: expected {}, not `{}`",
+                    postprocess::NAMES.join(", "),
+                    name
+                )));
+            }
+        }
+        for mask in &self.watermark_masks {
+            if mask.width == 0 || mask.height == 0 {
+                return Err(ConfigError::Message(format!(
+                    "watermark_masks: the mask at {}, {} has no width or height",
+                    mask.x, mask.y
+                )));
+            }
+        }
+        let watermark = !self.watermark_crop.is_empty() || !self.watermark_masks.is_empty();
+        if cfg!(not(feature = "preprocess")) && watermark {
+            return Err(ConfigError::Message(
+                "watermark_crop, watermark_masks: these need latex_ocr built with the `preprocess` feature".to_string(),
+            ));
+        }
+        if cfg!(not(feature = "preprocess")) && !self.redact.is_empty() {
+            return Err(ConfigError::Message(
+                "redact: masking needs latex_ocr built with the `preprocess` feature".to_string(),
+            ));
+        }
+        if self.tile_height > 0 && self.tile_overlap * 2 > self.tile_height {
+            return Err(ConfigError::Message(format!(
+                "tile_overlap: {} is more than half of tile_height ({})",
+                self.tile_overlap, self.tile_height
+            )));
+        }
+        if !shrink::FORMATS.contains(&self.upload_format.trim()) {
+            return Err(ConfigError::Message(format!(
+                "upload_format: expected {}, not `{}`",
+                shrink::FORMATS.join(" or "),
+                self.upload_format
+            )));
+        }
+        if !(1..=100).contains(&self.upload_quality) {
+            return Err(ConfigError::Message(format!(
+                "upload_quality: {} is not between 1 and 100",
+                self.upload_quality
+            )));
+        }
+        let shrinks = self.upload_trim
+            || self.upload_grayscale
+            || self.upload_max_dimension > 0
+            || self.upload_format.trim() == "jpeg";
+        if cfg!(not(feature = "preprocess")) && shrinks {
+            return Err(ConfigError::Message(
+                "upload_trim, upload_grayscale, upload_max_dimension, upload_format: these need latex_ocr \
+                 built with the `preprocess` feature"
+                    .to_string(),
+            ));
+        }
+        if cfg!(not(feature = "preprocess")) && self.tile_height > 0 {
+            return Err(ConfigError::Message(
+                "tile_height: tiling needs latex_ocr built with the `preprocess` feature".to_string(),
+            ));
+        }
+        if !self.document_class.trim().is_empty()
+            && !document_class::names().contains(&self.document_class.trim())
+        {
+            return Err(ConfigError::Message(format!(
+                "document_class: expected one of {}, not `{}`",
+                document_class::names().join(", "),
+                self.document_class
+            )));
+        }
+        hotkey::parse(&self.hotkey).map_err(|e| ConfigError::Message(format!("hotkey: {}", e)))?;
+        if !hotkey::COMMANDS.contains(&self.hotkey_command.as_str()) {
+            return Err(ConfigError::Message(format!(
+                "hotkey_command: expected one of {}, not `{}`",
+                hotkey::COMMANDS.join(", "),
+                self.hotkey_command
+            )));
+        }
+        if !self.max_age.trim().is_empty() {
+            parse_duration(&self.max_age)
+                .map_err(|e| ConfigError::Message(format!("max_age: {}", e)))?;
+        }
+        // Other providers take any budget, or turn it into an effort
+        let budgets = std::iter::once(self.thinking_budget)
+            .chain(self.profiles.values().filter_map(|profile| profile.thinking_budget))
+            .filter(|_| self.provider == Backend::Anthropic);
+        for budget in budgets {
+            if budget != 0 && budget < anthropic::MIN_THINKING_BUDGET {
+                return Err(ConfigError::Message(format!(
+                    "thinking_budget: {} is below the minimum of {}",
+                    budget,
+                    anthropic::MIN_THINKING_BUDGET
+                )));
+            }
+        }
+        if self.recording_frame_seconds == 0 {
+            return Err(ConfigError::Message("recording_frame_seconds: must be at least 1".to_string()));
+        }
+        if self.convert_jobs == 0 {
+            return Err(ConfigError::Message("convert_jobs: must be at least 1".to_string()));
+        }
+        if self.watch_burst_max == 0 {
+            return Err(ConfigError::Message("watch_burst_max: must be at least 1".to_string()));
+        }
+        if self.recording_similarity > 64 {
+            return Err(ConfigError::Message(format!(
+                "recording_similarity: {} is above 64, the bits in a hash",
+                self.recording_similarity
+            )));
+        }
+        if self.review_below > 100 {
+            return Err(ConfigError::Message(format!(
+                "review_below: {} is above 100",
+                self.review_below
+            )));
+        }
+        let locales = std::iter::once(&self.number_locale)
+            .chain(self.profiles.values().filter_map(|profile| profile.number_locale.as_ref()));
+        for locale in locales {
+            if !locale.trim().is_empty() && Locale::parse(locale).is_none() {
+                return Err(ConfigError::Message(format!("number_locale: unknown locale `{}`", locale)));
+            }
+        }
+        for (app, profile) in &self.app_profiles {
+            if self.profile(profile).is_none() {
+                return Err(ConfigError::Message(format!("app_profiles: no profile `{}` for {}", profile, app)));
+            }
+        }
+        for output in &self.outputs {
+            output
+                .validate()
+                .map_err(|e| ConfigError::Message(format!("outputs: {}", e)))?;
+        }
+        Ok(())
+    }
+    
+    pub fn image_directory_expanded(&self) -> String {
+        if self.image_directory.trim().is_empty() {
+            return paths::screenshot_dir().to_string_lossy().to_string();
+        }
+        shellexpand::tilde(&self.image_directory).to_string()
+    }
+
+    /// The template results are wrapped in under `profile`, if any
+    pub fn template<'a>(&'a self, profile: &'a Profile) -> Option<&'a str> {
+        let template = profile.template.as_deref().unwrap_or(&self.template);
+        Some(template).filter(|template| !template.trim().is_empty())
+    }
+
+    /// Looks up a mode by name; config entries fill in or override the built-in one
+    pub fn mode(&self, name: &str) -> Option<Mode> {
+        match (self.modes.get(name).cloned(), Mode::builtin(name)) {
+            (Some(configured), Some(builtin)) => Some(configured.or(builtin)),
+            (configured, builtin) => configured.or(builtin),
+        }
+    }
+
+    /// The format results are written in under `profile`
+    pub fn format(&self, profile: &Profile) -> Format {
+        profile.format.unwrap_or(self.format)
+    }
+
+    /// The profile's clipboard target, else the one for the frontmost app,
+    /// else the top-level one
+    pub fn clipboard_target(&self, profile: &Profile) -> Target {
+        if let Some(target) = profile.clipboard_target {
+            return target;
+        }
+        if !self.clipboard_targets.is_empty()
+            && let Some(app) = metadata::frontmost().app
+            && let Some(target) = target::for_app(&self.clipboard_targets, &app)
+        {
+            return target;
+        }
+        self.clipboard_target
+    }
+
+    /// The backend conversions are sent to
+    pub fn provider(&self) -> Box<dyn OcrProvider> {
+        match self.provider {
+            Backend::Anthropic => Box::new(anthropic::Anthropic {
+                api_key: self.api_key.clone(),
+                url: self.anthropic_url.clone(),
+            }),
+            Backend::OpenAi => Box::new(openai::OpenAi {
+                api_key: self.openai_api_key.clone(),
+                url: self.openai_url.clone(),
+            }),
+            Backend::Gemini => Box::new(gemini::Gemini {
+                api_key: self.gemini_api_key.clone(),
+                url: self.gemini_url.clone(),
+            }),
+            #[cfg(feature = "ollama")]
+            Backend::Ollama => Box::new(ollama::Ollama { url: self.ollama_url.clone() }),
+            #[cfg(not(feature = "ollama"))]
+            Backend::Ollama => unreachable!("validate rejects the ollama provider without the feature"),
+        }
+    }
+
+    /// The local backend conversions fall back to offline, if `offline_model` is set
+    pub fn offline_provider(&self) -> Option<Box<dyn OcrProvider>> {
+        #[cfg(feature = "ollama")]
+        if !self.offline_model.trim().is_empty() && self.provider != Backend::Ollama {
+            return Some(Box::new(ollama::Ollama { url: self.ollama_url.clone() }));
+        }
+        None
+    }
+
+    /// The name and value of the API key setting `provider` uses, if it needs one
+    pub fn provider_key(&self) -> Option<(&'static str, &str)> {
+        match self.provider {
+            Backend::Anthropic => Some(("api_key", &self.api_key)),
+            Backend::OpenAi => Some(("openai_api_key", &self.openai_api_key)),
+            Backend::Gemini => Some(("gemini_api_key", &self.gemini_api_key)),
+            Backend::Ollama => None,
+        }
+    }
+
+    /// Looks up a profile by name, falling back to the built-in ones
+    pub fn profile(&self, name: &str) -> Option<Profile> {
+        let profile = self.profiles.get(name).cloned().or_else(|| Profile::builtin(name))?;
+        Some(Profile { name: Some(name.to_string()), ..profile })
+    }
+
+    /// The `app_profiles` entry for `hint`'s app, or failing that for a name in
+    /// its window title
+    pub fn app_profile(&self, hint: &metadata::Hint) -> Option<Profile> {
+        let by_app = self.app_profiles.iter().find(|(name, _)| hint.app_is(name));
+        let by_window = || self.app_profiles.iter().find(|(name, _)| hint.window_mentions(name));
+        let (_, profile) = by_app.or_else(by_window)?;
+        self.profile(profile)
+    }
+
+    /// The `max_age` limit, if one is set
+    pub fn max_age(&self) -> Option<Duration> {
+        parse_duration(&self.max_age).ok()
+    }
+
+    pub fn clipboard_options(&self) -> clipboard::Options {
+        clipboard::Options {
+            rtf: self.clipboard_rtf,
+            split_equations: self.clipboard_split_equations,
+            event_delay: Duration::from_millis(self.clipboard_event_delay_ms),
+        }
+    }
+}
+
+/// Parses durations like "90s", "10m", "2h" or "1d"; a bare number is seconds
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", text))?;
+    let unit_seconds = match unit.trim() {
+        "" | "s" | "sec" | "secs" => 1,
+        "m" | "min" | "mins" => 60,
+        "h" | "hr" | "hrs" => 60 * 60,
+        "d" | "day" | "days" => 60 * 60 * 24,
+        other => return Err(format!("unknown duration unit `{}` in `{}`", other, text)),
+    };
+    let seconds = number
+        .checked_mul(unit_seconds)
+        .ok_or_else(|| format!("invalid duration `{}`", text))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Renders a duration in the largest whole unit, e.g. "3h" or "45s"
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        s if s >= 86_400 => format!("{}d", s / 86_400),
+        s if s >= 3_600 => format!("{}h", s / 3_600),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_parse_with_their_unit() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("10 min"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86_400)));
+        assert!(parse_duration("3w").is_err());
+    }
+
+    #[test]
+    fn overflowing_durations_are_invalid() {
+        assert_eq!(
+            parse_duration("999999999999999999d"),
+            Err("invalid duration `999999999999999999d`".to_string())
+        );
+    }
+}
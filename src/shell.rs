@@ -0,0 +1,19 @@
+//! User-configured shell commands (editors, capture, recording and LaTeX
+//! tools) with a placeholder standing for the file they work on.
+
+use std::path::Path;
+use std::process::Command;
+
+/// `sh -c` running `template` with `{key}` replaced by `path` as one quoted
+/// word, and the command line itself for messages
+pub fn run_template(template: &str, key: &str, path: &Path) -> (Command, String) {
+    let text = template.replace(&format!("{{{}}}", key), &quote(&path.to_string_lossy()));
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(&text);
+    (command, text)
+}
+
+/// `text` as one single-quoted `sh` word
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
@@ -0,0 +1,126 @@
+//! Making images cheaper to send: a retina screenshot is mostly empty margin
+//! and far more pixels than the model reads, and every one of them costs
+//! tokens and upload time. Applied to each image (or tile) just before it is
+//! encoded into the request, as the `upload_*` settings ask.
+
+use std::error::Error;
+#[cfg(feature = "preprocess")]
+use std::sync::OnceLock;
+
+use crate::settings::AppConfig;
+
+/// What `upload_format` can be
+pub const FORMATS: &[&str] = &["png", "jpeg"];
+
+/// How far from the corner's color a pixel may be and still count as margin
+#[cfg(feature = "preprocess")]
+const MARGIN_TOLERANCE: u8 = 24;
+
+/// Pixels of margin left around the content, so nothing touches the edge
+#[cfg(feature = "preprocess")]
+const MARGIN_KEPT: u32 = 8;
+
+#[cfg(feature = "preprocess")]
+struct Options {
+    trim: bool,
+    grayscale: bool,
+    max_dimension: u32,
+    jpeg_quality: Option<u8>,
+}
+
+#[cfg(feature = "preprocess")]
+static OPTIONS: OnceLock<Options> = OnceLock::new();
+
+/// Turns shrinking on for this process if any `upload_*` setting asks for it
+#[cfg(feature = "preprocess")]
+pub fn configure(config: &AppConfig) {
+    let jpeg = config.upload_format.trim() == "jpeg";
+    if !config.upload_trim && !config.upload_grayscale && config.upload_max_dimension == 0 && !jpeg {
+        return;
+    }
+    let _ = OPTIONS.set(Options {
+        trim: config.upload_trim,
+        grayscale: config.upload_grayscale,
+        max_dimension: config.upload_max_dimension,
+        jpeg_quality: jpeg.then_some(config.upload_quality),
+    });
+}
+
+#[cfg(feature = "preprocess")]
+pub fn enabled() -> bool {
+    OPTIONS.get().is_some()
+}
+
+/// Without image decoding there is nothing to shrink with; `validate` rejects the settings
+#[cfg(not(feature = "preprocess"))]
+pub fn configure(_config: &AppConfig) {}
+
+#[cfg(not(feature = "preprocess"))]
+pub fn enabled() -> bool {
+    false
+}
+
+/// `image_data` trimmed, grayed and scaled down as configured, re-encoded
+/// as PNG or as JPEG at `upload_quality`
+#[cfg(feature = "preprocess")]
+pub fn apply(image_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let Some(options) = OPTIONS.get() else {
+        return Ok(image_data.to_vec());
+    };
+    let mut image = image::load_from_memory(image_data)?;
+    if options.trim {
+        image = trim(image);
+    }
+    if options.grayscale {
+        image = image::DynamicImage::ImageLuma8(image.to_luma8());
+    }
+    let max = options.max_dimension;
+    if max > 0 && (image.width() > max || image.height() > max) {
+        image = image.resize(max, max, image::imageops::FilterType::Lanczos3);
+    }
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    match options.jpeg_quality {
+        Some(quality) => {
+            let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut encoded, quality);
+            // JPEG has no alpha channel, and gray is one channel instead of three
+            if options.grayscale {
+                encoder.encode_image(&image.to_luma8())?;
+            } else {
+                encoder.encode_image(&image.to_rgb8())?;
+            }
+        }
+        None => image.write_to(&mut encoded, image::ImageFormat::Png)?,
+    }
+    Ok(encoded.into_inner())
+}
+
+#[cfg(not(feature = "preprocess"))]
+pub fn apply(_image_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    Err("shrinking images needs a build with the `preprocess` feature".into())
+}
+
+/// `image` cut down to what differs from the color of its top left corner,
+/// with a little margin; an image that is all margin is left alone
+#[cfg(feature = "preprocess")]
+fn trim(image: image::DynamicImage) -> image::DynamicImage {
+    let rgb = image.to_rgb8();
+    let background = *rgb.get_pixel(0, 0);
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, pixel) in rgb.enumerate_pixels() {
+        let margin = pixel.0.iter().zip(background.0).all(|(&c, b)| c.abs_diff(b) <= MARGIN_TOLERANCE);
+        if !margin {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x);
+            bottom = bottom.max(y);
+        }
+    }
+    if left > right {
+        return image;
+    }
+    let left = left.saturating_sub(MARGIN_KEPT);
+    let top = top.saturating_sub(MARGIN_KEPT);
+    let right = (right + MARGIN_KEPT).min(image.width() - 1);
+    let bottom = (bottom + MARGIN_KEPT).min(image.height() - 1);
+    image.crop_imm(left, top, right - left + 1, bottom - top + 1)
+}
@@ -0,0 +1,163 @@
+//! Joining the results of the tiles a tall image is cut into. Neighbouring
+//! tiles overlap, so the rows at each boundary are transcribed twice and the
+//! edge of a tile may cut through one; the merge keeps a single copy of each
+//! row, from the tile that was surer of it.
+
+use crate::normalize;
+
+/// How much two transcriptions of the same row may differ, as a share of
+/// the longer one's normalized length
+const MAX_DIFFERENCE: f64 = 0.3;
+
+/// The same, for a row a tile's edge may have cut through
+const MAX_CUT_DIFFERENCE: f64 = 0.5;
+
+/// One tile's result
+#[derive(Debug, Clone, Copy)]
+pub struct Tile<'a> {
+    pub latex: &'a str,
+    /// How sure the model was of it, 0-100, when it was asked
+    pub confidence: Option<u32>,
+}
+
+/// The results of tiles cut top to bottom, as one, with the rows the end of
+/// one tile and the start of the next share kept once. Of two copies of a
+/// row the surer tile's wins, otherwise the more complete one
+pub fn merge(tiles: &[Tile]) -> String {
+    let mut merged: Vec<(String, Option<u32>)> = Vec::new();
+    for tile in tiles {
+        let mut rows: Vec<&str> = tile.latex.trim().lines().collect();
+        let mut shared = overlap(&merged, &rows);
+        // Each tile closes the environment it was cut out of; one that carries
+        // on in the next tile is joined back together
+        if shared == 0
+            && let (Some((last, _)), Some(first)) = (merged.last(), rows.first())
+            && let Some(name) = last.trim().strip_prefix("\\end{")
+            && first.trim().strip_prefix("\\begin{") == Some(name)
+        {
+            let inside = overlap(&merged[..merged.len() - 1], &rows[1..]);
+            if inside > 0 {
+                merged.pop();
+                rows.remove(0);
+                shared = inside;
+            }
+        }
+        let start = merged.len() - shared;
+        for (kept, row) in merged[start..].iter_mut().zip(&rows) {
+            let chosen = if prefers(row, tile.confidence, kept) { row } else { kept.0.as_str() };
+            // Whether more rows follow is up to the later tile
+            *kept = (with_ending(chosen, row), kept.1.max(tile.confidence));
+        }
+        merged.extend(rows[shared..].iter().map(|row| (row.to_string(), tile.confidence)));
+    }
+    merged.into_iter().map(|(row, _)| row).collect::<Vec<_>>().join("\n")
+}
+
+/// How many rows at the start of `rows` repeat the last ones of `merged`,
+/// as many as match
+fn overlap(merged: &[(String, Option<u32>)], rows: &[&str]) -> usize {
+    (1..=merged.len().min(rows.len()))
+        .rev()
+        .find(|&shared| {
+            let tail = &merged[merged.len() - shared..];
+            tail.iter().zip(rows).enumerate().all(|(i, ((kept, _), row))| {
+                // The first row of the later tile and the last of the earlier
+                // one are at an edge
+                let cut = i == 0 || i == shared - 1;
+                same_row(kept, row, if cut { MAX_CUT_DIFFERENCE } else { MAX_DIFFERENCE })
+            })
+        })
+        .unwrap_or(0)
+}
+
+/// Whether `row` from a tile `confidence` sure of it should replace `kept`
+fn prefers(row: &str, confidence: Option<u32>, kept: &(String, Option<u32>)) -> bool {
+    match (confidence, kept.1) {
+        (Some(new), Some(old)) if new != old => new > old,
+        _ => content(row).chars().count() > content(&kept.0).chars().count(),
+    }
+}
+
+fn same_row(a: &str, b: &str, max_difference: f64) -> bool {
+    // `\end{align}` is nearly `\begin{align}`, but nothing like it
+    let environment = |row: &str| ["\\begin{", "\\end{"].iter().any(|start| row.trim_start().starts_with(start));
+    let structural = environment(a) || environment(b);
+    let (a, b) = (content(a), content(b));
+    if a == b || structural {
+        return a == b;
+    }
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let longer = a.len().max(b.len());
+    // Short rows are too alike by chance to match unless they are equal
+    longer >= 4 && distance(&a, &b) as f64 <= max_difference * longer as f64
+}
+
+/// What a row says, without its alignment and line break
+fn content(row: &str) -> String {
+    let row = row.trim();
+    normalize::math(&row.strip_suffix("\\\\").unwrap_or(row).replace('&', ""))
+}
+
+/// `row` ending in a line break if `like` does, and not otherwise
+fn with_ending(row: &str, like: &str) -> String {
+    let bare = row.trim_end();
+    let bare = bare.strip_suffix("\\\\").map_or(bare, str::trim_end);
+    if like.trim_end().ends_with("\\\\") { format!("{} \\\\", bare) } else { bare.to_string() }
+}
+
+/// Edits turning `a` into `b`
+fn distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, y) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(x != y);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(latex: &str) -> Tile<'_> {
+        Tile { latex, confidence: None }
+    }
+
+    #[test]
+    fn shared_rows_are_kept_once() {
+        let merged = merge(&[tile("a = 1 \\\\\nb = 2 \\\\\nc = 3"), tile("b = 2 \\\\\nc = 3 \\\\\nd = 4")]);
+        assert_eq!(merged, "a = 1 \\\\\nb = 2 \\\\\nc = 3 \\\\\nd = 4");
+    }
+
+    #[test]
+    fn tiles_without_shared_rows_are_joined() {
+        assert_eq!(merge(&[tile("x^2 + y^2"), tile("e^{i\\pi} = -1")]), "x^2 + y^2\ne^{i\\pi} = -1");
+    }
+
+    #[test]
+    fn a_row_cut_by_the_edge_is_taken_whole() {
+        let merged = merge(&[tile("\\int_0^1 f(x)\\,dx = F(1) - F(0)\ng(x) = \\sin"), tile("g(x) = \\sin x + \\cos x\nh = 0")]);
+        assert_eq!(merged, "\\int_0^1 f(x)\\,dx = F(1) - F(0)\ng(x) = \\sin x + \\cos x\nh = 0");
+    }
+
+    #[test]
+    fn the_surer_tile_wins() {
+        let first = Tile { latex: "p = 1\n\\alpha + \\beta = \\gamma_1", confidence: Some(90) };
+        let second = Tile { latex: "\\alpha + \\beta = \\gamma_{12}\nq = 2", confidence: Some(40) };
+        assert_eq!(merge(&[first, second]), "p = 1\n\\alpha + \\beta = \\gamma_1\nq = 2");
+    }
+
+    #[test]
+    fn an_environment_split_across_tiles_is_joined() {
+        let first = "\\begin{align}\na &= b + c \\\\\nd &= e + f\n\\end{align}";
+        let second = "\\begin{align}\nd &= e + f \\\\\ng &= h + i\n\\end{align}";
+        assert_eq!(
+            merge(&[tile(first), tile(second)]),
+            "\\begin{align}\na &= b + c \\\\\nd &= e + f \\\\\ng &= h + i\n\\end{align}"
+        );
+    }
+}
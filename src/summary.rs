@@ -0,0 +1,73 @@
+//! A one-line description of a result for the success notification, e.g.
+//! "3 display equations, 142 chars, align environment", so it's clear what
+//! was copied without pasting it.
+
+/// Environments whose rows are equations of their own
+const MULTILINE: &[&str] = &["align", "align*", "gather", "gather*", "flalign", "flalign*", "eqnarray", "eqnarray*"];
+
+/// Other environments that are a displayed equation
+const DISPLAY: &[&str] = &["equation", "equation*", "multline", "multline*", "displaymath", "math"];
+
+/// How many displayed and inline equations `latex` has, how long it is and
+/// which environments it uses
+pub fn describe(latex: &str) -> String {
+    let latex = latex.trim();
+    let environments = environments(latex);
+    let mut display = latex.matches("\\[").count() + latex.matches("$$").count() / 2;
+    for (name, body) in &environments {
+        if MULTILINE.contains(&name.as_str()) {
+            display += body.split("\\\\").filter(|row| !row.trim().is_empty()).count();
+        } else if DISPLAY.contains(&name.as_str()) {
+            display += 1;
+        }
+    }
+    let single_dollars = latex.replace("$$", "").matches('$').count().saturating_sub(latex.matches("\\$").count());
+    let inline = latex.matches("\\(").count() + single_dollars / 2;
+    if display == 0 && inline == 0 && crate::document::is_bare_math(latex) && !latex.is_empty() {
+        display = 1;
+    }
+
+    let mut parts = Vec::new();
+    if display > 0 {
+        parts.push(plural(display, "display equation"));
+    }
+    if inline > 0 {
+        parts.push(format!("{} inline", inline));
+    }
+    parts.push(plural(latex.chars().count(), "char"));
+    let mut names: Vec<&str> = Vec::new();
+    for (name, _) in &environments {
+        let name = name.trim_end_matches('*');
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    match names.len() {
+        0 => {}
+        1 => parts.push(format!("{} environment", names[0])),
+        _ => parts.push(format!("{} environments", names.join(", "))),
+    }
+    parts.join(", ")
+}
+
+/// Each `\begin{name}`'s name and what it encloses, outermost first
+fn environments(latex: &str) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    let mut rest = latex;
+    while let Some(start) = rest.find("\\begin{") {
+        rest = &rest[start + "\\begin{".len()..];
+        let Some(close) = rest.find('}') else {
+            break;
+        };
+        let name = &rest[..close];
+        let after = &rest[close + 1..];
+        let end = format!("\\end{{{}}}", name);
+        let body = after.find(&end).map_or(after, |end| &after[..end]);
+        found.push((name.to_string(), body.to_string()));
+    }
+    found
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 { format!("1 {}", noun) } else { format!("{} {}s", count, noun) }
+}
@@ -0,0 +1,206 @@
+//! Clipboard targets: the copy rewritten for the app it gets pasted into,
+//! since Notion only knows KaTeX, Word wants MathML and Overleaf takes the
+//! LaTeX as it is.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::document;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// The LaTeX unchanged
+    #[default]
+    Latex,
+    /// `$$...$$` displays with only environments KaTeX supports, no labels
+    Katex,
+    /// MathML `<math>` elements, which Word turns into equations on paste
+    Mathml,
+}
+
+/// Display environments and the KaTeX environment their body goes in, if any
+const DISPLAYS: &[(&str, Option<&str>)] = &[
+    ("equation", None),
+    ("equation*", None),
+    ("displaymath", None),
+    ("align", Some("aligned")),
+    ("align*", Some("aligned")),
+    ("eqnarray", Some("aligned")),
+    ("eqnarray*", Some("aligned")),
+    ("gather", Some("gathered")),
+    ("gather*", Some("gathered")),
+    ("multline", Some("gathered")),
+    ("multline*", Some("gathered")),
+];
+
+impl Target {
+    /// Looks up a target by the name used in the config and on the command line
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "latex" => Some(Target::Latex),
+            "katex" => Some(Target::Katex),
+            "mathml" => Some(Target::Mathml),
+            _ => None,
+        }
+    }
+
+    /// Rewrites `text` for this target
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Target::Latex => text.to_string(),
+            Target::Katex => katex(text),
+            Target::Mathml => {
+                // Leaves the copy as KaTeX-style LaTeX when it uses something
+                // the converter doesn't know rather than failing the delivery
+                let math = katex(text).replace("\\$", "&dollar;");
+                latex2mathml::replace(&math).unwrap_or_else(|e| {
+                    eprintln!("Copying LaTeX, it can't be turned into MathML: {}", e);
+                    text.to_string()
+                })
+            }
+        }
+    }
+}
+
+/// The target `apps` (app name to target, as in `clipboard_targets`) gives
+/// `app`, matched case-insensitively
+pub fn for_app(apps: &HashMap<String, Target>, app: &str) -> Option<Target> {
+    apps.iter()
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case(app.trim()))
+        .map(|(_, target)| *target)
+}
+
+/// `text` with every display as `$$...$$`, inline math as `$...$` and the
+/// environments and commands KaTeX rejects rewritten or dropped
+fn katex(text: &str) -> String {
+    let text = text.trim();
+    let mut text = if document::is_bare_math(text) {
+        format!("$${}$$", text)
+    } else {
+        [("\\[", "$$"), ("\\]", "$$"), ("\\(", "$"), ("\\)", "$")]
+            .iter()
+            .fold(text.to_string(), |text, (delimiter, dollars)| replace_delimiter(&text, delimiter, dollars))
+    };
+    for (environment, inner) in DISPLAYS {
+        text = replace_environment(&text, environment, *inner);
+    }
+    for command in ["\\nonumber", "\\notag"] {
+        text = text.replace(command, "");
+    }
+    remove_labels(&text)
+}
+
+/// Turns every `\begin{environment}...\end{environment}` into a `$$` display,
+/// wrapping the body in `inner` when it has one
+fn replace_environment(text: &str, environment: &str, inner: Option<&str>) -> String {
+    let begin = format!("\\begin{{{}}}", environment);
+    let end = format!("\\end{{{}}}", environment);
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(&begin) {
+        let Some(length) = rest[start..].find(&end) else {
+            break;
+        };
+        let body = rest[start + begin.len()..start + length].trim();
+        replaced.push_str(&rest[..start]);
+        match inner {
+            Some(inner) => replaced.push_str(&format!("$$\\begin{{{0}}}{1}\\end{{{0}}}$$", inner, body)),
+            None => replaced.push_str(&format!("$${}$$", body)),
+        }
+        rest = &rest[start + length + end.len()..];
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+/// Replaces `delimiter` except where its backslash ends a `\\` line break, as in `\\[2pt]`
+fn replace_delimiter(text: &str, delimiter: &str, replacement: &str) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(delimiter) {
+        let backslashes = rest[..start].chars().rev().take_while(|c| *c == '\\').count();
+        replaced.push_str(&rest[..start]);
+        replaced.push_str(if backslashes % 2 == 0 { replacement } else { delimiter });
+        rest = &rest[start + delimiter.len()..];
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+/// Drops every `\label{...}`, braces inside the key included
+fn remove_labels(text: &str) -> String {
+    let mut removed = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("\\label{") {
+        let key = start + "\\label{".len();
+        let mut depth = 1;
+        let Some(length) = rest[key..].find(|c| {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        }) else {
+            break;
+        };
+        removed.push_str(&rest[..start]);
+        rest = &rest[key + length + 1..];
+    }
+    removed.push_str(rest);
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_become_dollars() {
+        assert_eq!(katex("x^2"), "$$x^2$$");
+        assert_eq!(katex("Let \\(x\\) be \\[x^2\\]"), "Let $x$ be $$x^2$$");
+        assert_eq!(
+            katex("\\begin{align}a &= b \\nonumber\\end{align}"),
+            "$$\\begin{aligned}a &= b \\end{aligned}$$"
+        );
+    }
+
+    #[test]
+    fn line_break_spacing_is_not_a_delimiter() {
+        assert_eq!(replace_delimiter("a \\\\[2pt] b", "\\[", "$$"), "a \\\\[2pt] b");
+        assert_eq!(replace_delimiter("\\\\\\[x", "\\[", "$$"), "\\\\$$x");
+        assert_eq!(
+            katex("\\[\\begin{matrix}a \\\\[2pt] b\\end{matrix}\\]"),
+            "$$\\begin{matrix}a \\\\[2pt] b\\end{matrix}$$"
+        );
+    }
+
+    #[test]
+    fn environments_get_their_katex_equivalent() {
+        assert_eq!(
+            replace_environment("\\begin{gather*} a \\end{gather*}", "gather*", Some("gathered")),
+            "$$\\begin{gathered}a\\end{gathered}$$"
+        );
+        assert_eq!(
+            replace_environment("\\begin{equation}a\\end{equation}, \\begin{equation}b\\end{equation}", "equation", None),
+            "$$a$$, $$b$$"
+        );
+    }
+
+    #[test]
+    fn unclosed_environments_are_left_alone() {
+        assert_eq!(replace_environment("\\begin{equation} a", "equation", None), "\\begin{equation} a");
+        assert_eq!(
+            replace_environment("\\begin{equation}a\\end{equation} \\begin{equation} b", "equation", None),
+            "$$a$$ \\begin{equation} b"
+        );
+    }
+
+    #[test]
+    fn labels_are_removed_with_nested_braces() {
+        assert_eq!(remove_labels("a \\label{eq:a_{1}}= b"), "a = b");
+        assert_eq!(remove_labels("\\label{one}x\\label{two}"), "x");
+        assert_eq!(remove_labels("x \\label{open"), "x \\label{open");
+    }
+}
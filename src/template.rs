@@ -0,0 +1,43 @@
+//! Output templates: Tera templates wrapping a result in the scaffolding the
+//! destination document expects (figure environments, org blocks, HTML spans).
+
+use std::error::Error;
+use tera::{Context, Tera};
+
+use crate::clipboard;
+
+/// Values a template can refer to
+pub struct Fields<'a> {
+    /// The converted LaTeX
+    pub latex: &'a str,
+    /// Citation extracted with `cite_source`, empty when there is none
+    pub source: &'a str,
+    /// Path of the converted image
+    pub image: &'a str,
+    /// `(symbol, meaning)` pairs from the glossary mode
+    pub glossary: &'a [(String, String)],
+    /// Babel name of a non-English prose language, empty otherwise
+    pub language: &'a str,
+    /// Description for alt text, empty unless `alt_text` is on
+    pub alt: &'a str,
+}
+
+/// Renders `template` with `latex`, `source`, `image`, `language`, `alt`, `equations` (the
+/// result split into separate equations) and `glossary` (a list of `symbol`,
+/// `meaning` objects) in scope; nothing is HTML-escaped
+pub fn render(template: &str, fields: &Fields) -> Result<String, Box<dyn Error>> {
+    let mut context = Context::new();
+    context.insert("latex", fields.latex);
+    context.insert("source", fields.source);
+    context.insert("image", fields.image);
+    context.insert("language", fields.language);
+    context.insert("alt", fields.alt);
+    context.insert("equations", &clipboard::split_equations(fields.latex));
+    let glossary: Vec<_> = fields
+        .glossary
+        .iter()
+        .map(|(symbol, meaning)| serde_json::json!({ "symbol": symbol, "meaning": meaning }))
+        .collect();
+    context.insert("glossary", &glossary);
+    Ok(Tera::one_off(template, &context, false)?)
+}
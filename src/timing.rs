@@ -0,0 +1,64 @@
+use std::time::{Duration, Instant};
+
+/// Startup budget for reaching the confirmation dialog
+pub const DIALOG_BUDGET: Duration = Duration::from_millis(100);
+
+/// Records how long each startup phase takes, for the `--timing` report
+pub struct Timing {
+    enabled: bool,
+    start: Instant,
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+    to_dialog: Option<Duration>,
+}
+
+impl Timing {
+    pub fn new(enabled: bool) -> Self {
+        let now = Instant::now();
+        Self {
+            enabled,
+            start: now,
+            last: now,
+            phases: Vec::new(),
+            to_dialog: None,
+        }
+    }
+
+    /// Close the current phase under `label`
+    pub fn mark(&mut self, label: &'static str) {
+        let now = Instant::now();
+        self.phases.push((label, now - self.last));
+        self.last = now;
+    }
+
+    /// Remember the moment the confirmation dialog is about to be shown
+    pub fn dialog_shown(&mut self) {
+        self.mark("until dialog");
+        self.to_dialog = Some(self.start.elapsed());
+    }
+
+    /// Print the per-phase report to stderr if `--timing` was given
+    pub fn report(&self) {
+        if !self.enabled {
+            return;
+        }
+        eprintln!("timing:");
+        for (label, elapsed) in &self.phases {
+            eprintln!("  {:<20} {:>8.1} ms", label, as_ms(*elapsed));
+        }
+        eprintln!("  {:<20} {:>8.1} ms", "total", as_ms(self.start.elapsed()));
+        if let Some(to_dialog) = self.to_dialog {
+            let verdict = if to_dialog <= DIALOG_BUDGET { "within" } else { "over" };
+            eprintln!(
+                "  time to dialog: {:.1} ms ({} the {} ms budget)",
+                as_ms(to_dialog),
+                verdict,
+                DIALOG_BUDGET.as_millis()
+            );
+        }
+    }
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
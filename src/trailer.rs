@@ -0,0 +1,57 @@
+//! Extra facts the model is asked to report after the LaTeX, one
+//! `%% key: value` line each, which are split off the reply before use.
+
+/// Prompt text asking for each `(key, description)` item as a trailer line
+pub fn instructions(items: &[(&str, &str)]) -> String {
+    let mut text = String::from(
+        "\n\nAfter the LaTeX, add one line per item below in the form `%% key: value`, \
+         leaving out any item you cannot determine:",
+    );
+    for (key, description) in items {
+        text.push_str(&format!("\n- {}: {}", key, description));
+    }
+    text
+}
+
+/// Separates the reply body from its trailer lines
+pub fn split(reply: &str) -> (String, Vec<(String, String)>) {
+    let mut body = Vec::new();
+    let mut items = Vec::new();
+    for line in reply.lines() {
+        match parse_line(line) {
+            Some(item) => items.push(item),
+            None => body.push(line),
+        }
+    }
+    (body.join("\n").trim_end().to_string(), items)
+}
+
+/// The value reported for `key`, ignoring placeholders like "none"
+pub fn value<'a>(items: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    items
+        .iter()
+        .find(|(item_key, _)| item_key == key)
+        .map(|(_, value)| value.as_str())
+        .filter(|value| {
+            !value.is_empty() && !matches!(value.to_lowercase().as_str(), "none" | "n/a" | "unknown")
+        })
+}
+
+/// Every value reported for a key the model may repeat
+pub fn values<'a>(items: &'a [(String, String)], key: &str) -> Vec<&'a str> {
+    items
+        .iter()
+        .filter(|(item_key, value)| item_key == key && !value.is_empty())
+        .map(|(_, value)| value.as_str())
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let rest = line.trim().strip_prefix("%%")?;
+    let (key, value) = rest.split_once(':')?;
+    let key = key.trim().to_lowercase();
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return None;
+    }
+    Some((key, value.trim().to_string()))
+}
@@ -0,0 +1,149 @@
+//! `latex_ocr self-update`: replaces the running binary with the latest
+//! GitHub release for this platform after checking its SHA-256.
+
+use serde::Deserialize;
+use std::error::Error;
+use std::time::Duration;
+
+use crate::atomic;
+use crate::pipeline::sha256_hex;
+use crate::response;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/d-zg/ss_to_tex/releases/latest";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// What `self-update` did
+pub enum Outcome {
+    UpToDate(String),
+    Available(String),
+    Updated(String),
+}
+
+/// Release asset name for this build, e.g. `latex_ocr-aarch64-apple-darwin`;
+/// each asset ships with a `.sha256` file next to it
+fn asset_name() -> Result<String, Box<dyn Error>> {
+    let os = match std::env::consts::OS {
+        "macos" => "apple-darwin",
+        "linux" => "unknown-linux-gnu",
+        other => return Err(format!("no release builds for {}", other).into()),
+    };
+    Ok(format!("latex_ocr-{}-{}", std::env::consts::ARCH, os))
+}
+
+/// Checks the latest release and, unless `check_only`, installs it over the running binary
+pub async fn run(check_only: bool) -> Result<Outcome, Box<dyn Error>> {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("latex_ocr/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(300))
+        .build()?;
+    let body = client
+        .get(RELEASES_URL)
+        .header("accept", "application/vnd.github+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let release: Release = response::parse(&body)?;
+    let tag = &release.tag_name;
+    let latest = tag.trim_start_matches('v').to_string();
+    if !is_newer(&latest, env!("CARGO_PKG_VERSION")) {
+        return Ok(Outcome::UpToDate(latest));
+    }
+    if check_only {
+        return Ok(Outcome::Available(latest));
+    }
+
+    let name = asset_name()?;
+    let asset_url = |wanted: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == wanted)
+            .map(|asset| asset.browser_download_url.clone())
+            .ok_or_else(|| format!("release {} has no {} asset", tag, wanted))
+    };
+    let binary_url = asset_url(&name)?;
+    let checksum_url = asset_url(&format!("{}.sha256", name))?;
+
+    let binary = client.get(&binary_url).send().await?.error_for_status()?.bytes().await?;
+    let checksum = client.get(&checksum_url).send().await?.error_for_status()?.text().await?;
+    // `sha256sum` format: the hash, then optionally the file name
+    let expected = checksum.split_whitespace().next().unwrap_or_default().to_lowercase();
+    let actual = sha256_hex(&binary);
+    if expected != actual {
+        return Err(format!("checksum mismatch for {}: expected {}, got {}", name, expected, actual).into());
+    }
+
+    replace_executable(&binary)?;
+    Ok(Outcome::Updated(latest))
+}
+
+/// Writes the new binary next to the current one, fsynced, and renames it
+/// over it, so the swap is atomic and an interrupted update leaves the old
+/// binary working
+fn replace_executable(binary: &[u8]) -> Result<(), Box<dyn Error>> {
+    let current = std::env::current_exe()?.canonicalize()?;
+    // The new binary takes over the current one's permissions, execute bits included
+    atomic::write(&current, binary).map_err(|e| format!("cannot replace {}: {}", current.display(), e).into())
+}
+
+/// Compares dotted numeric versions, with or without a `v`; pre-release
+/// suffixes are ignored and missing parts count as 0
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        let mut parts: Vec<u64> = version
+            .trim()
+            .trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect();
+        // So 1.2 and 1.2.0 are the same
+        while parts.last() == Some(&0) {
+            parts.pop();
+        }
+        parts
+    };
+    parse(candidate) > parse(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts_compare_as_numbers() {
+        assert!(is_newer("0.10.0", "0.9.1"));
+        assert!(!is_newer("0.9.1", "0.10.0"));
+        assert!(is_newer("1.0.0", "0.99.99"));
+    }
+
+    #[test]
+    fn missing_parts_are_zero() {
+        assert!(!is_newer("1.2", "1.2.0"));
+        assert!(!is_newer("1.2.0", "1.2"));
+        assert!(is_newer("1.2.1", "1.2"));
+    }
+
+    #[test]
+    fn tags_and_suffixes_are_ignored() {
+        assert!(is_newer("v0.2.0", "0.1.0"));
+        assert!(!is_newer("v0.1.0", "0.1.0"));
+        assert!(!is_newer("0.2.0-beta.1", "0.2.0"));
+        assert!(!is_newer("0.1.0+build.5", "0.1.0"));
+    }
+}
@@ -0,0 +1,119 @@
+//! Checks on a reply before it is delivered, selected by a mode's or
+//! profile's `validate` setting, for output with a stricter grammar than LaTeX.
+
+use std::error::Error;
+
+/// Runs the named validator over the converted text
+pub fn apply(validator: &str, text: &str) -> Result<(), Box<dyn Error>> {
+    match validator {
+        "fen" => fen::check(fen::find(text)).map_err(|e| format!("invalid FEN: {}", e).into()),
+        other => Err(format!("unknown validator `{}`", other).into()),
+    }
+}
+
+/// Forsyth-Edwards Notation, bare or inside `\chessboard[setfen=...]`.
+mod fen {
+    /// The FEN in `text`: the `setfen` option if there is one, else the whole text
+    pub fn find(text: &str) -> &str {
+        match text.split_once("setfen=") {
+            Some((_, rest)) => rest.split([',', ']']).next().unwrap_or(rest).trim(),
+            None => text.trim(),
+        }
+    }
+
+    pub fn check(fen: &str) -> Result<(), String> {
+        let mut fields = fen.split_whitespace();
+        let placement = fields.next().ok_or("empty position")?;
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("{} ranks instead of 8", ranks.len()));
+        }
+        for (index, rank) in ranks.iter().enumerate() {
+            let mut squares = 0;
+            for c in rank.chars() {
+                squares += match c {
+                    '1'..='8' => c.to_digit(10).unwrap_or(0),
+                    'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => 1,
+                    other => return Err(format!("unexpected `{}` in rank {}", other, 8 - index)),
+                };
+            }
+            if squares != 8 {
+                return Err(format!("rank {} has {} squares", 8 - index, squares));
+            }
+        }
+        for king in ['K', 'k'] {
+            let count = placement.matches(king).count();
+            if count != 1 {
+                return Err(format!("{} `{}` kings instead of 1", count, king));
+            }
+        }
+
+        // The remaining fields are optional, but must be well-formed when present
+        if let Some(side) = fields.next()
+            && side != "w"
+            && side != "b"
+        {
+            return Err(format!("side to move `{}` isn't w or b", side));
+        }
+        if let Some(castling) = fields.next()
+            && castling != "-"
+            && !castling.chars().all(|c| "KQkq".contains(c))
+        {
+            return Err(format!("castling rights `{}` aren't KQkq or -", castling));
+        }
+        if let Some(square) = fields.next()
+            && square != "-"
+            && !matches!(square.as_bytes(), [b'a'..=b'h', b'3' | b'6'])
+        {
+            return Err(format!("en passant square `{}` isn't on rank 3 or 6", square));
+        }
+        for counter in fields.by_ref().take(2) {
+            if counter.parse::<u32>().is_err() {
+                return Err(format!("move counter `{}` isn't a number", counter));
+            }
+        }
+        if let Some(extra) = fields.next() {
+            return Err(format!("unexpected `{}` after the move counters", extra));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn rejects(fen: &str, reason: &str) {
+        let error = apply("fen", fen).unwrap_err().to_string();
+        assert!(error.contains(reason), "{:?} failed with {:?}", fen, error);
+    }
+
+    #[test]
+    fn valid_positions_pass() {
+        assert!(apply("fen", START).is_ok());
+        assert!(apply("fen", "8/8/8/4k3/8/8/8/4K3").is_ok());
+        assert!(apply("fen", "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2").is_ok());
+        assert!(apply("fen", &format!("\\chessboard[setfen={}, showmover]", START)).is_ok());
+    }
+
+    #[test]
+    fn each_field_is_checked() {
+        rejects("", "empty position");
+        rejects("8/8/8/4k3/8/8/4K3", "7 ranks instead of 8");
+        rejects("8/8/8/4k3/8/8/8/4K2x", "unexpected `x` in rank 1");
+        rejects("8/8/8/4k3/8/8/8/4K4", "rank 1 has 9 squares");
+        rejects("8/8/8/8/8/8/8/4K3", "0 `k` kings instead of 1");
+        rejects("8/8/8/4k3/8/8/8/4K3 x", "side to move `x`");
+        rejects("8/8/8/4k3/8/8/8/4K3 w KX", "castling rights `KX`");
+        rejects("8/8/8/4k3/8/8/8/4K3 w - e4", "en passant square `e4`");
+        rejects("8/8/8/4k3/8/8/8/4K3 w - - one 1", "move counter `one`");
+        rejects(&format!("{} extra", START), "unexpected `extra` after the move counters");
+    }
+
+    #[test]
+    fn unknown_validators_fail() {
+        assert!(apply("json", "{}").unwrap_err().to_string().contains("unknown validator `json`"));
+    }
+}
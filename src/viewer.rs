@@ -0,0 +1,60 @@
+//! A small page showing history entries (the image next to its LaTeX and
+//! metadata), opened from a clicked notification.
+
+use std::error::Error;
+use std::process::Command;
+
+use crate::atomic;
+use crate::history::Entry;
+use crate::html::escape;
+use crate::paths;
+
+#[cfg(target_os = "macos")]
+const OPENER: &str = "open";
+#[cfg(not(target_os = "macos"))]
+const OPENER: &str = "xdg-open";
+
+/// Writes a page for `entries` and opens it in the default browser
+pub fn open(entries: &[Entry]) -> Result<(), Box<dyn Error>> {
+    let path = paths::state_dir().join("viewer.html");
+    atomic::write(&path, page(entries))?;
+    let status = Command::new(OPENER).arg(&path).status()?;
+    if !status.success() {
+        return Err(format!("`{} {}` exited with {}", OPENER, path.display(), status).into());
+    }
+    Ok(())
+}
+
+fn page(entries: &[Entry]) -> String {
+    let mut html = String::from(
+        "<!doctype html>\n<meta charset=\"utf-8\">\n<title>latex_ocr history</title>\n<style>\n\
+         body { font: 14px -apple-system, sans-serif; max-width: 48em; margin: 2em auto; }\n\
+         img { max-width: 100%; border: 1px solid #ddd; }\n\
+         pre { background: #f6f6f6; padding: 1em; white-space: pre-wrap; }\n\
+         dt { color: #666; } dd { margin: 0 0 .5em; }\n\
+         </style>\n",
+    );
+    for entry in entries {
+        html.push_str("<section>\n");
+        if !entry.image.is_empty() {
+            html.push_str(&format!("<img src=\"file://{}\" alt=\"\">\n", escape(&entry.image)));
+        }
+        html.push_str(&format!("<pre>{}</pre>\n<dl>\n", escape(&entry.latex)));
+        let mut fields = vec![("converted", entry.when())];
+        if !entry.image.is_empty() {
+            fields.push(("image", entry.image.clone()));
+        }
+        if let Some(source) = &entry.source {
+            fields.push(("source", source.clone()));
+        }
+        if let Some(note) = &entry.note {
+            fields.push(("note", note.clone()));
+        }
+        fields.push(("id", entry.id()));
+        for (name, value) in fields {
+            html.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", name, escape(&value)));
+        }
+        html.push_str("</dl>\n</section>\n");
+    }
+    html
+}
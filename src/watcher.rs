@@ -0,0 +1,47 @@
+//! Filesystem events for new screenshots in the image directory.
+
+// `::notify` is the filesystem watcher crate, `crate::notify` our notifications
+use ::notify::event::{EventKind, ModifyKind};
+use ::notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::discovery;
+
+/// Starts watching `directory`; every new image file is reported once on the
+/// returned channel for as long as the watcher is kept alive
+pub fn watch(directory: &Path) -> ::notify::Result<(RecommendedWatcher, UnboundedReceiver<PathBuf>)> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let mut seen = HashSet::new();
+    let root = directory.to_path_buf();
+    let mut watcher = ::notify::recommended_watcher(move |event: ::notify::Result<::notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        // macOS writes screenshots to a hidden temp file and renames it into place
+        let relevant = matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+        );
+        if !relevant {
+            return;
+        }
+        // Re-read every time so edits to the ignore file apply right away
+        let ignore = discovery::Ignore::load(&root);
+        for path in event.paths {
+            if is_new_screenshot(&path) && !ignore.skips(&path) && seen.insert(path.clone()) {
+                let _ = sender.send(path);
+            }
+        }
+    })?;
+    watcher.watch(directory, RecursiveMode::NonRecursive)?;
+    Ok((watcher, receiver))
+}
+
+fn is_new_screenshot(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    !hidden && path.is_file() && discovery::is_image(path)
+}
@@ -0,0 +1,141 @@
+//! Cutting off or blanking the fixed parts of screenshots where tools stamp a
+//! watermark or credit, so its text doesn't end up in the result.
+
+use serde::Deserialize;
+use std::error::Error;
+#[cfg(feature = "preprocess")]
+use std::sync::OnceLock;
+
+use crate::settings::AppConfig;
+
+/// Pixels cut off each edge
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct Crop {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+/// A rectangle painted over; a negative `x` or `y` counts from the right or
+/// bottom edge, so a mask stays on a corner whatever the screenshot's size
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Mask {
+    pub x: i64,
+    pub y: i64,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[cfg(feature = "preprocess")]
+struct Regions {
+    crop: Crop,
+    masks: Vec<Mask>,
+}
+
+#[cfg(feature = "preprocess")]
+static REGIONS: OnceLock<Regions> = OnceLock::new();
+
+impl Crop {
+    pub fn is_empty(&self) -> bool {
+        self.top == 0 && self.bottom == 0 && self.left == 0 && self.right == 0
+    }
+
+    /// The width and height left of a `width` by `height` image, if any
+    #[cfg(feature = "preprocess")]
+    fn kept(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let kept_width = width.checked_sub(self.left.saturating_add(self.right)).filter(|kept| *kept > 0)?;
+        let kept_height = height.checked_sub(self.top.saturating_add(self.bottom)).filter(|kept| *kept > 0)?;
+        Some((kept_width, kept_height))
+    }
+}
+
+/// Turns stripping on for this process if `watermark_crop` or `watermark_masks` is set
+#[cfg(feature = "preprocess")]
+pub fn configure(config: &AppConfig) {
+    if config.watermark_crop.is_empty() && config.watermark_masks.is_empty() {
+        return;
+    }
+    let _ = REGIONS.set(Regions { crop: config.watermark_crop, masks: config.watermark_masks.clone() });
+}
+
+#[cfg(feature = "preprocess")]
+pub fn enabled() -> bool {
+    REGIONS.get().is_some()
+}
+
+/// Without image decoding there is nothing to strip with; `validate` rejects the settings
+#[cfg(not(feature = "preprocess"))]
+pub fn configure(_config: &AppConfig) {}
+
+#[cfg(not(feature = "preprocess"))]
+pub fn enabled() -> bool {
+    false
+}
+
+/// `image_data` with the masks painted white and then the crop cut off, as PNG
+#[cfg(feature = "preprocess")]
+pub fn apply(image_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let Some(regions) = REGIONS.get() else {
+        return Ok(image_data.to_vec());
+    };
+    let mut image = image::load_from_memory(image_data)?.to_rgb8();
+    let (width, height) = image.dimensions();
+    for mask in &regions.masks {
+        let left = edge(mask.x, width);
+        let top = edge(mask.y, height);
+        for y in top..top.saturating_add(mask.height).min(height) {
+            for x in left..left.saturating_add(mask.width).min(width) {
+                image.put_pixel(x, y, image::Rgb([255, 255, 255]));
+            }
+        }
+    }
+    let crop = regions.crop;
+    let Some((kept_width, kept_height)) = crop.kept(width, height) else {
+        return Err(format!("watermark_crop leaves nothing of a {}x{} image", width, height).into());
+    };
+    let cropped = image::imageops::crop_imm(&image, crop.left, crop.top, kept_width, kept_height).to_image();
+    crate::preprocess::encode_png(&cropped)
+}
+
+#[cfg(not(feature = "preprocess"))]
+pub fn apply(_image_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    Err("watermark stripping needs a build with the `preprocess` feature".into())
+}
+
+/// Where an offset from the start (positive) or end (negative) of `length` lands
+#[cfg(feature = "preprocess")]
+fn edge(offset: i64, length: u32) -> u32 {
+    let position = if offset < 0 { length as i64 + offset } else { offset };
+    position.clamp(0, length as i64) as u32
+}
+
+#[cfg(all(test, feature = "preprocess"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_count_from_either_end_and_stay_inside() {
+        assert_eq!(edge(10, 100), 10);
+        assert_eq!(edge(-10, 100), 90);
+        assert_eq!(edge(250, 100), 100);
+        assert_eq!(edge(-250, 100), 0);
+        assert_eq!(edge(i64::MIN, 100), 0);
+    }
+
+    #[test]
+    fn crops_keep_what_is_left() {
+        let crop = Crop { top: 10, bottom: 20, left: 5, right: 5 };
+        assert_eq!(crop.kept(100, 50), Some((90, 20)));
+        assert_eq!(crop.kept(10, 50), None);
+        assert_eq!(crop.kept(100, 30), None);
+    }
+
+    #[test]
+    fn huge_crops_leave_nothing_instead_of_overflowing() {
+        let crop = Crop { top: u32::MAX, bottom: 1, left: u32::MAX, right: u32::MAX };
+        assert_eq!(crop.kept(100, 100), None);
+        assert_eq!(Crop::default().kept(100, 100), Some((100, 100)));
+    }
+}